@@ -0,0 +1,169 @@
+//! What the affected-rows count of a write statement means.
+//!
+//! The count the server reports back is the ground truth of what a write did, but how to read
+//! it depends on the statement: `INSERT IGNORE` leaves out the rows it skipped, and
+//! `ON DUPLICATE KEY UPDATE` counts an updated row twice and an unchanged one not at all.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Classes of write statement, each with its own affected-rows semantics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteKind {
+    /// Plain `INSERT`: one per row.
+    Insert,
+    /// `INSERT IGNORE`: one per inserted row; skipped duplicates count zero.
+    InsertIgnore,
+    /// `INSERT ... ON DUPLICATE KEY UPDATE`: one per inserted row, two per updated row and
+    /// zero per row updated to the values it already had.
+    Upsert,
+    /// `DELETE` (or `UPDATE`): one per row matched and changed.
+    Delete,
+}
+
+/// A write's affected-rows count, read according to its [`WriteKind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Affected {
+    /// Rows now in place as the statement wrote them, for `items`.
+    pub written: u64,
+    /// Rows that met an existing key: skipped or updated. A lower bound for
+    /// [`WriteKind::Upsert`], where an inserted and an unchanged row together count the same
+    /// as one updated row.
+    pub duplicates: u64,
+    /// Every row was written fresh, with no duplicate or missing row.
+    pub full: bool,
+    /// The count is one the statement can report at all; anything else means the server
+    /// did something other than asked.
+    pub plausible: bool,
+}
+
+impl WriteKind {
+    /// Read the `affected` count of a statement that carried (or targeted) `rows` rows.
+    pub fn interpret(self, rows: u64, affected: u64) -> Affected {
+        let full = affected == rows;
+        match self {
+            WriteKind::Insert => Affected {
+                written: affected,
+                duplicates: 0,
+                full,
+                plausible: affected <= rows,
+            },
+            WriteKind::InsertIgnore => Affected {
+                written: affected,
+                duplicates: rows.saturating_sub(affected),
+                full,
+                plausible: affected <= rows,
+            },
+            WriteKind::Upsert => Affected {
+                // Inserted, updated or already up to date: every row is in place.
+                written: rows,
+                // Each count below `rows` is an unchanged row, each one above an updated row.
+                duplicates: affected.abs_diff(rows),
+                full,
+                plausible: affected <= rows * 2,
+            },
+            WriteKind::Delete => Affected {
+                written: affected,
+                duplicates: 0,
+                full,
+                plausible: affected <= rows,
+            },
+        }
+    }
+}
+
+/// Counts write iterations whose affected rows were not what their statements allow, across
+/// all workers.
+#[derive(Default)]
+pub struct UnexpectedWrites {
+    checked: AtomicU64,
+    unexpected: AtomicU64,
+}
+
+impl UnexpectedWrites {
+    /// Record an iteration and whether its affected rows were `expected`; returns `expected`.
+    ///
+    /// Batches can only be held to [`Affected::plausible`]; single-row statements that must
+    /// each write their row are held to [`Affected::full`].
+    pub fn check(&self, expected: bool) -> bool {
+        self.checked.fetch_add(1, Ordering::Relaxed);
+        if !expected {
+            self.unexpected.fetch_add(1, Ordering::Relaxed);
+        }
+        expected
+    }
+
+    /// Print how many iterations reported unexpected affected rows, if any. Call once,
+    /// after the run.
+    pub fn print(&self) {
+        let unexpected = self.unexpected.load(Ordering::Relaxed);
+        if unexpected > 0 {
+            eprintln!(
+                "warning: {unexpected} of {} write iterations reported unexpected affected rows \
+                 (client error {})",
+                self.checked.load(Ordering::Relaxed),
+                crate::status::AFFECTED_ROWS_UNEXPECTED
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_counts_one_per_row() {
+        let all = WriteKind::Insert.interpret(3, 3);
+        assert_eq!(
+            (all.written, all.duplicates, all.full, all.plausible),
+            (3, 0, true, true)
+        );
+        let short = WriteKind::Insert.interpret(3, 2);
+        assert_eq!(
+            (short.written, short.full, short.plausible),
+            (2, false, true)
+        );
+        assert!(!WriteKind::Insert.interpret(3, 4).plausible);
+    }
+
+    #[test]
+    fn insert_ignore_counts_skipped_rows_as_duplicates() {
+        let skipped = WriteKind::InsertIgnore.interpret(5, 3);
+        assert_eq!((skipped.written, skipped.duplicates), (3, 2));
+        assert!(!skipped.full && skipped.plausible);
+        assert!(!WriteKind::InsertIgnore.interpret(5, 6).plausible);
+    }
+
+    #[test]
+    fn upsert_counts_two_per_updated_row() {
+        // 2 inserted, 3 updated.
+        let mixed = WriteKind::Upsert.interpret(5, 2 + 3 * 2);
+        assert_eq!((mixed.written, mixed.duplicates), (5, 3));
+        assert!(mixed.plausible);
+        let all_updated = WriteKind::Upsert.interpret(5, 10);
+        assert_eq!((all_updated.written, all_updated.duplicates), (5, 5));
+        assert!(all_updated.plausible);
+        assert!(!WriteKind::Upsert.interpret(5, 11).plausible);
+    }
+
+    #[test]
+    fn upsert_counts_zero_per_unchanged_row() {
+        // Every row already held the values written.
+        let unchanged = WriteKind::Upsert.interpret(4, 0);
+        assert_eq!((unchanged.written, unchanged.duplicates), (4, 4));
+        assert!(!unchanged.full && unchanged.plausible);
+        // 3 inserted, 1 unchanged.
+        let mixed = WriteKind::Upsert.interpret(4, 3);
+        assert_eq!((mixed.written, mixed.duplicates), (4, 1));
+        assert!(mixed.plausible);
+    }
+
+    #[test]
+    fn delete_counts_matched_rows() {
+        let deleted = WriteKind::Delete.interpret(1, 1);
+        assert!(deleted.full && deleted.plausible);
+        let missing = WriteKind::Delete.interpret(1, 0);
+        assert_eq!((missing.written, missing.full), (0, false));
+        assert!(!WriteKind::Delete.interpret(1, 2).plausible);
+    }
+}
@@ -0,0 +1,4 @@
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tidb_bench::run_main::<tidb_bench::GapLockBench>().await
+}
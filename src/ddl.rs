@@ -0,0 +1,148 @@
+//! Online DDL running next to the workload (`--background-ddl`).
+//!
+//! Some time into the run, a separate connection adds a secondary index on the benchmark
+//! table and drops it again while the workers carry on. Iteration latencies are bucketed by
+//! the DDL step running when they completed, and the step boundaries are reported as a
+//! timeline relative to the first iteration.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use mysql_async::prelude::*;
+use tokio::time::Instant;
+
+use crate::harness::{summarize, Latencies};
+use crate::{quote_ident, DbOpts};
+
+/// Name of the index added and dropped again.
+const INDEX: &str = "tidb_bench_background_ddl";
+
+/// Column the index is built on; every benchmark table has it.
+const COLUMN: &str = "value";
+
+/// What the background DDL is doing, in order; iterations are bucketed by it.
+const STEPS: [&str; 4] = ["before", "add index", "drop index", "after"];
+
+/// Shared by all workers; the first iteration starts the clock.
+pub struct BackgroundDdl {
+    db: DbOpts,
+    delay: Duration,
+    started: OnceLock<Instant>,
+    /// Index into [`STEPS`].
+    step: AtomicU8,
+    latencies: [Latencies; STEPS.len()],
+    /// Offset from the first iteration, and what happened.
+    timeline: Mutex<Vec<(Duration, String)>>,
+}
+
+impl BackgroundDdl {
+    pub fn new(db: &DbOpts, delay: Duration, workers: u32) -> Self {
+        Self {
+            db: db.clone(),
+            delay,
+            started: OnceLock::new(),
+            step: AtomicU8::new(0),
+            latencies: std::array::from_fn(|_| Latencies::new(workers)),
+            timeline: Mutex::default(),
+        }
+    }
+
+    /// Start the clock and schedule the DDL, unless an earlier iteration already did.
+    pub fn start(self: &Arc<Self>) {
+        let mut first = false;
+        self.started.get_or_init(|| {
+            first = true;
+            Instant::now()
+        });
+        if first {
+            let ddl = self.clone();
+            tokio::spawn(async move { ddl.run().await });
+        }
+    }
+
+    /// Record the latency of an iteration of `worker_id` that just completed.
+    pub fn record(&self, worker_id: u32, latency: Duration) {
+        let step = self.step.load(Ordering::Acquire) as usize;
+        self.latencies[step].record(worker_id, latency);
+    }
+
+    async fn run(&self) {
+        tokio::time::sleep(self.delay).await;
+        let table = self.db.quoted_table();
+        let index = quote_ident(INDEX);
+        let statements = [
+            (
+                "ADD INDEX",
+                format!("ALTER TABLE {table} ADD INDEX {index} ({COLUMN})"),
+            ),
+            (
+                "DROP INDEX",
+                format!("ALTER TABLE {table} DROP INDEX {index}"),
+            ),
+        ];
+        let mut conn = match self.db.connect().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                self.mark(format!("cannot connect: {e:#}"));
+                self.step.store(STEPS.len() as u8 - 1, Ordering::Release);
+                return;
+            }
+        };
+        for (step, (name, sql)) in statements.iter().enumerate() {
+            self.step.store(step as u8 + 1, Ordering::Release);
+            self.mark(format!("{name} started"));
+            let start = Instant::now();
+            match conn.query_drop(sql).await {
+                Ok(()) => self.mark(format!("{name} done ({:.2?})", start.elapsed())),
+                Err(e) => {
+                    self.mark(format!("{name} failed: {e}"));
+                    break;
+                }
+            }
+        }
+        self.step.store(STEPS.len() as u8 - 1, Ordering::Release);
+    }
+
+    /// Add an event to the timeline, and to the log so it shows up live.
+    fn mark(&self, event: String) {
+        let at = self.started.get().map_or(Duration::ZERO, Instant::elapsed);
+        eprintln!("background DDL at +{at:.2?}: {event}");
+        self.timeline.lock().unwrap().push((at, event));
+    }
+
+    /// Print the DDL timeline and the latency summary of each step. Call once, after the run.
+    pub fn print(&self, percentiles: &[f64]) {
+        let timeline = self.timeline.lock().unwrap();
+        if timeline.is_empty() {
+            eprintln!(
+                "background DDL: the run ended within {} of its first iteration, before the \
+                 DDL started",
+                humantime::format_duration(self.delay)
+            );
+            return;
+        }
+        eprintln!("background DDL (offsets from the first iteration):");
+        for (at, event) in timeline.iter() {
+            eprintln!("  +{at:>9.2?}  {event}");
+        }
+        if self.step.load(Ordering::Acquire) as usize != STEPS.len() - 1 {
+            eprintln!(
+                "  the run ended while the DDL was still running; it continues on the server"
+            );
+        }
+        eprintln!("latency by background DDL step:");
+        for (name, latencies) in STEPS.iter().zip(&self.latencies) {
+            let latencies = latencies.merged();
+            if latencies.is_empty() {
+                eprintln!("  {name:<10}  no iteration completed");
+            } else {
+                eprintln!(
+                    "  {name:<10}  {}  ({} iterations)",
+                    summarize(&latencies, percentiles),
+                    latencies.len()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,407 @@
+//! Value distributions shared by data generation and query construction.
+
+use crate::parse_fraction;
+
+/// Multiplier used to scatter row indexes over the value domain.
+const SCATTER: u64 = 2654435761;
+
+/// Uniform distribution of an integer column over `0..cardinality`.
+///
+/// Every consecutive block of `cardinality` rows holds each value exactly once, so
+/// predicates computed from [`Uniform::threshold`] match a predictable share of the table.
+#[derive(Debug, Clone, Copy)]
+pub struct Uniform {
+    cardinality: u32,
+}
+
+impl Uniform {
+    pub const fn new(cardinality: u32) -> Self {
+        Self { cardinality }
+    }
+
+    /// Value of the `i`-th generated row.
+    pub fn value_at(&self, i: u64) -> u32 {
+        (i.wrapping_mul(SCATTER) % self.cardinality as u64) as u32
+    }
+
+    /// Exclusive upper bound `t` such that `value < t` matches roughly `selectivity` of the rows.
+    pub fn threshold(&self, selectivity: f64) -> u32 {
+        (selectivity * self.cardinality as f64).round() as u32
+    }
+}
+
+/// Options for the generated `value` and `data` columns, shared by the benchmarks that
+/// generate rows.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ValueOpts {
+    /// Distinct values of the generated `value` column, which holds `0..N`.
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t = 1000,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub value_cardinality: u32,
+
+    /// How generated rows spread over the `--value-cardinality` values.
+    #[clap(long, alias = "value-dist", value_enum, default_value = "uniform")]
+    pub value_distribution: ValueDistribution,
+
+    /// Skew of `--value-distribution zipfian`; higher values put more rows on the lowest values.
+    #[clap(long, default_value_t = 0.99, value_parser = parse_theta)]
+    pub value_zipf_theta: f64,
+
+    /// Standard deviation of `--value-distribution normal`, as a fraction of
+    /// `--value-cardinality`; the mean is the middle value.
+    #[clap(long, value_name = "FRACTION", default_value_t = 0.15, value_parser = parse_fraction)]
+    pub value_stddev: f64,
+
+    /// Distinct prefixes of the generated `data` strings, spread uniformly over the rows.
+    ///
+    /// By default all strings share one prefix and differ only in their trailing number.
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub data_prefixes: Option<u32>,
+
+    /// Distinct generated `data` strings; rows cycle through them, for highly repetitive
+    /// column data.
+    ///
+    /// By default every row gets its own string.
+    #[clap(long, value_name = "K", value_parser = clap::value_parser!(u64).range(1..))]
+    pub distinct_data_values: Option<u64>,
+}
+
+/// How generated rows spread over the values of a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ValueDistribution {
+    /// Every value is equally frequent.
+    Uniform,
+    /// The lowest values are the most frequent, YCSB-style; see `--value-zipf-theta`.
+    Zipfian,
+    /// Bell curve around the middle value, clamped to the range; see `--value-stddev`.
+    Normal,
+}
+
+impl ValueOpts {
+    /// Settings shown in the run header.
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = vec![("values", self.describe())];
+        if let Some(prefixes) = self.data_prefixes {
+            settings.push(("data prefixes", prefixes.to_string()));
+        }
+        if let Some(k) = self.distinct_data_values {
+            settings.push(("distinct data", k.to_string()));
+        }
+        settings
+    }
+
+    fn describe(&self) -> String {
+        let n = self.value_cardinality;
+        match self.value_distribution {
+            ValueDistribution::Uniform => format!("{n} distinct, uniform"),
+            ValueDistribution::Zipfian => {
+                format!("{n} distinct, zipfian θ={}", self.value_zipf_theta)
+            }
+            ValueDistribution::Normal => format!("{n} distinct, normal σ={}", self.value_stddev),
+        }
+    }
+
+    /// Tells the generated data apart from that of the defaults, for seeded-table reuse;
+    /// empty for the defaults.
+    pub fn generator_suffix(&self) -> String {
+        let mut suffix = String::new();
+        if self.value_cardinality != 1000 || self.value_distribution != ValueDistribution::Uniform {
+            suffix.push_str(&format!(" values={}", self.describe()));
+        }
+        if let Some(prefixes) = self.data_prefixes {
+            suffix.push_str(&format!(" prefixes={prefixes}"));
+        }
+        if let Some(k) = self.distinct_data_values {
+            suffix.push_str(&format!(" distinct_data={k}"));
+        }
+        suffix
+    }
+
+    pub fn values(&self) -> Values {
+        let n = self.value_cardinality;
+        let shape = match self.value_distribution {
+            ValueDistribution::Uniform => Shape::Uniform(Uniform::new(n)),
+            ValueDistribution::Zipfian => {
+                Shape::Zipfian(Zipfian::new(n as u64, self.value_zipf_theta))
+            }
+            ValueDistribution::Normal => Shape::Normal {
+                mean: (n - 1) as f64 / 2.0,
+                stddev: self.value_stddev * n as f64,
+            },
+        };
+        Values {
+            cardinality: n,
+            shape,
+            prefixes: self.data_prefixes,
+            distinct_data: self.distinct_data_values,
+        }
+    }
+}
+
+/// A [`ValueDistribution`] with its parameters.
+#[derive(Debug, Clone)]
+enum Shape {
+    Uniform(Uniform),
+    Zipfian(Zipfian),
+    Normal { mean: f64, stddev: f64 },
+}
+
+/// Generates the `value` and `data` columns as configured by [`ValueOpts`].
+///
+/// Both are a pure function of the row index, so reads can derive predicates from the same
+/// parameters (see [`Values::threshold`]).
+#[derive(Debug, Clone)]
+pub struct Values {
+    cardinality: u32,
+    shape: Shape,
+    prefixes: Option<u32>,
+    distinct_data: Option<u64>,
+}
+
+impl Values {
+    /// Value of the `i`-th generated row.
+    pub fn value_at(&self, i: u64) -> u32 {
+        let max = self.cardinality as u64 - 1;
+        match &self.shape {
+            Shape::Uniform(uniform) => uniform.value_at(i),
+            Shape::Zipfian(zipf) => zipf.sample(unit(i)).min(max) as u32,
+            Shape::Normal { mean, stddev } => {
+                // Box-Muller, from two independent points of the row index.
+                let (u1, u2) = (1.0 - unit(i), unit(!i));
+                let z = (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos();
+                (mean + z * stddev).round().clamp(0.0, max as f64) as u32
+            }
+        }
+    }
+
+    /// Exclusive upper bound `t` such that `value < t` matches roughly `selectivity` of the rows.
+    pub fn threshold(&self, selectivity: f64) -> u32 {
+        match &self.shape {
+            Shape::Uniform(uniform) => uniform.threshold(selectivity),
+            Shape::Zipfian(zipf) => zipf.quantile(selectivity) as u32,
+            Shape::Normal { .. } => {
+                // The clamped tails have no closed form; sample the generator itself.
+                let mut sample: Vec<u32> =
+                    (0..THRESHOLD_SAMPLE).map(|i| self.value_at(i)).collect();
+                sample.sort_unstable();
+                let rank = (selectivity * THRESHOLD_SAMPLE as f64).round() as usize;
+                sample.get(rank).copied().unwrap_or(self.cardinality)
+            }
+        }
+    }
+
+    /// `data` string of the `i`-th generated row: `base` followed by the row's prefix
+    /// number, if `--data-prefixes` is set, and `i`, or `i` modulo `--distinct-data-values`.
+    pub fn data_at(&self, base: &str, i: u64) -> String {
+        let i = self.distinct_data.map_or(i, |k| i % k);
+        match self.prefixes {
+            None => format!("{base}{i}"),
+            Some(n) => format!("{base}{}_{i}", i.wrapping_mul(SCATTER) % n as u64),
+        }
+    }
+}
+
+/// Rows sampled to find a [`Values::threshold`] without a closed form.
+const THRESHOLD_SAMPLE: u64 = 1 << 16;
+
+/// Map `i` to a pseudo-random point in `[0, 1)` (SplitMix64), so generated rows are
+/// reproducible without an RNG.
+pub(crate) fn unit(i: u64) -> f64 {
+    let mut z = i.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// How positions in `0..n` are drawn, e.g. the starting offset of a scan.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Spread {
+    /// Every position is equally likely.
+    Uniform,
+    /// Skewed towards low positions, YCSB-style; see `--zipf-theta`.
+    Zipfian,
+}
+
+/// Draws positions in `0..n` according to a [`Spread`].
+#[derive(Debug, Clone)]
+pub struct Picker {
+    n: u64,
+    zipf: Option<Zipfian>,
+}
+
+impl Picker {
+    /// `theta` in `(0, 1)` sets the Zipfian skew and is ignored for [`Spread::Uniform`].
+    pub fn new(spread: Spread, n: u64, theta: f64) -> Self {
+        let n = n.max(1);
+        let zipf = match spread {
+            Spread::Uniform => None,
+            Spread::Zipfian => Some(Zipfian::new(n, theta)),
+        };
+        Self { n, zipf }
+    }
+
+    pub fn pick<R: rand::Rng>(&self, rng: &mut R) -> u64 {
+        match &self.zipf {
+            None => rng.gen_range(0..self.n),
+            Some(zipf) => zipf.sample(rng.gen()).min(self.n - 1),
+        }
+    }
+}
+
+/// Zipfian generator from Gray et al., "Quickly Generating Billion-Record Synthetic
+/// Databases" (as used by YCSB). Construction is O(n); sampling is O(1).
+#[derive(Debug, Clone)]
+struct Zipfian {
+    n: f64,
+    theta: f64,
+    alpha: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+impl Zipfian {
+    fn new(n: u64, theta: f64) -> Self {
+        let zeta = |n: u64| (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+        let zetan = zeta(n);
+        // With two positions or fewer `sample` never gets past its special cases, and the
+        // formula would divide by zero.
+        let eta = if n > 2 {
+            (1.0 - (2.0 / n as f64).powf(1.0 - theta)) / (1.0 - zeta(2) / zetan)
+        } else {
+            0.0
+        };
+        Self {
+            n: n as f64,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zetan,
+            eta,
+        }
+    }
+
+    /// Position for a uniform sample `u` in `[0, 1)`.
+    fn sample(&self, u: f64) -> u64 {
+        let uz = u * self.zetan;
+        if uz < 1.0 {
+            0
+        } else if uz < 1.0 + 0.5f64.powf(self.theta) {
+            1
+        } else {
+            (self.n * (self.eta * u - self.eta + 1.0).powf(self.alpha)) as u64
+        }
+    }
+
+    /// Smallest `k` such that positions below `k` get roughly `share` of the samples.
+    fn quantile(&self, share: f64) -> u64 {
+        let target = share * self.zetan;
+        let mut sum = 0.0;
+        let mut k = 0u64;
+        while sum < target && (k as f64) < self.n {
+            k += 1;
+            sum += 1.0 / (k as f64).powf(self.theta);
+        }
+        k
+    }
+}
+
+/// Parse a Zipfian skew in `(0, 1)`, for use as a clap value parser.
+pub fn parse_theta(s: &str) -> std::result::Result<f64, String> {
+    let v: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if v > 0.0 && v < 1.0 {
+        Ok(v)
+    } else {
+        Err(format!("{v} is not in (0, 1)"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn values(args: &[&str]) -> Values {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            values: ValueOpts,
+        }
+        let cli: Cli =
+            clap::Parser::parse_from(std::iter::once("test").chain(args.iter().copied()));
+        cli.values.values()
+    }
+
+    #[test]
+    fn uniform_hits_every_value_once_per_block() {
+        for cardinality in [1, 2, 7, 1000] {
+            let uniform = Uniform::new(cardinality);
+            for start in [0, 1, 5 * cardinality as u64 + 3, 1 << 20] {
+                let mut block: Vec<u32> = (start..start + cardinality as u64)
+                    .map(|i| uniform.value_at(i))
+                    .collect();
+                block.sort_unstable();
+                assert!(
+                    block.iter().copied().eq(0..cardinality),
+                    "cardinality {cardinality}, block at {start}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn thresholds_match_the_selectivity() {
+        const ROWS: u64 = 100_000;
+        // Sampling error, and Gray's generator only approximating the exact Zipfian quantile.
+        const TOLERANCE: f64 = 0.02;
+        let share = |values: &Values, matches: &dyn Fn(u32) -> bool| {
+            let matched = (0..ROWS).filter(|&i| matches(values.value_at(i))).count();
+            matched as f64 / ROWS as f64
+        };
+        for shape in ["uniform", "zipfian", "normal"] {
+            let values = values(&["--value-distribution", shape]);
+            for selectivity in [0.05, 0.25, 0.5, 0.9] {
+                let threshold = values.threshold(selectivity);
+                let below = share(&values, &|v| v < threshold);
+                // Whole values only: the last one may take the share past the selectivity.
+                let last = share(&values, &|v| v + 1 == threshold);
+                assert!(
+                    (selectivity - TOLERANCE..selectivity + last + TOLERANCE).contains(&below),
+                    "{shape}: value < {threshold} matches {below}, not {selectivity}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zipfian_quantiles_are_monotonic() {
+        for theta in [0.2, 0.5, 0.99] {
+            let zipf = Zipfian::new(1000, theta);
+            let quantiles: Vec<u64> = (0..=100).map(|p| zipf.quantile(p as f64 / 100.0)).collect();
+            assert_eq!(quantiles[0], 0);
+            assert_eq!(quantiles[100], 1000);
+            assert!(
+                quantiles.windows(2).all(|w| w[0] <= w[1]),
+                "θ={theta}: {quantiles:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn zipfian_picks_from_tiny_ranges() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let one = Picker::new(Spread::Zipfian, 1, 0.99);
+        assert!((0..1000).all(|_| one.pick(&mut rng) == 0));
+        let two = Picker::new(Spread::Zipfian, 2, 0.99);
+        let picks: Vec<u64> = (0..1000).map(|_| two.pick(&mut rng)).collect();
+        assert!(picks.contains(&0) && picks.contains(&1) && picks.iter().all(|&p| p < 2));
+        for n in [1, 2, 3] {
+            assert!(Zipfian::new(n, 0.5).eta.is_finite(), "n={n}");
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Error classification and run-wide error guards.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+use rlt::{Status, StatusKind};
+
+use crate::status::kind_name;
+
+/// Code recorded for errors that did not come from the server.
+pub const CLIENT_ERROR: i64 = -1;
+
+/// Code recorded when the connection to the server was lost (MySQL's `CR_SERVER_LOST`).
+pub const CONNECTION_LOST: i64 = 2013;
+
+/// MySQL `ER_NO_SUCH_TABLE`.
+pub const NO_SUCH_TABLE: u16 = 1146;
+
+/// Iterations to observe before `--max-error-rate` is enforced.
+const MIN_SAMPLES: u64 = 100;
+
+/// MySQL error code carried by `err`, if the server produced it.
+pub fn server_code(err: &anyhow::Error) -> Option<u16> {
+    match err.downcast_ref::<mysql_async::Error>()? {
+        mysql_async::Error::Server(e) => Some(e.code),
+        _ => None,
+    }
+}
+
+/// Server errors caused by lock contention between transactions.
+pub const LOCK_CONFLICT_CODES: [u16; 3] = [
+    1205, // lock wait timeout exceeded
+    1213, // deadlock found
+    9007, // write conflict
+];
+
+/// MySQL error code carried by `err`, if it is a lock conflict.
+pub fn lock_conflict_code(err: &anyhow::Error) -> Option<u16> {
+    server_code(err).filter(|code| LOCK_CONFLICT_CODES.contains(code))
+}
+
+/// Server errors that are expected under contention or while the cluster recovers from a
+/// failure; iterations hitting them fail without aborting the run.
+pub const TRANSIENT_CODES: [u16; 10] = [
+    1053, // server shutdown in progress
+    1205, // lock wait timeout exceeded
+    1213, // deadlock found
+    8022, // transaction retry failed
+    8028, // schema changed during the transaction
+    9001, // PD server timeout
+    9002, // TiKV server timeout
+    9003, // TiKV server busy
+    9005, // region unavailable
+    9007, // write conflict
+];
+
+/// Whether `err` means the connection itself is gone (server restart, network failure).
+pub fn is_connection_lost(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<mysql_async::Error>(),
+        Some(mysql_async::Error::Io(_))
+            | Some(mysql_async::Error::Driver(
+                mysql_async::DriverError::ConnectionClosed
+            ))
+    )
+}
+
+/// Whether `err` is a recognized runtime error that should fail only the iteration.
+///
+/// Anything else (syntax errors, missing tables, ...) would fail every iteration the same
+/// way and aborts the run.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    is_connection_lost(err) || server_code(err).is_some_and(|code| TRANSIENT_CODES.contains(&code))
+}
+
+/// Code recorded for `err`: the server's error code, [`CONNECTION_LOST`] or [`CLIENT_ERROR`].
+pub fn error_code(err: &anyhow::Error) -> i64 {
+    match server_code(err) {
+        Some(code) => code as i64,
+        None if is_connection_lost(err) => CONNECTION_LOST,
+        None => CLIENT_ERROR,
+    }
+}
+
+/// Status reported for an iteration that failed with `err`.
+pub fn error_status(err: &anyhow::Error) -> Status {
+    match server_code(err) {
+        Some(code) => Status::server_error(code as i64),
+        None => Status::client_error(error_code(err)),
+    }
+}
+
+/// Error that ends the run even when error limits would otherwise let it continue.
+#[derive(Debug)]
+pub struct Fatal(pub String);
+
+impl std::fmt::Display for Fatal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Fatal {}
+
+/// Run-wide error thresholds shared by all workers.
+///
+/// Once a threshold is breached the guard trips, and every worker's next iteration fails
+/// with the error breakdown collected so far.
+pub struct ErrorGuard {
+    max_rate: Option<f64>,
+    max_consecutive: Option<u64>,
+    total: AtomicU64,
+    errors: AtomicU64,
+    consecutive: AtomicU64,
+    by_code: Mutex<BTreeMap<i64, u64>>,
+    tripped: AtomicBool,
+}
+
+impl ErrorGuard {
+    pub fn new(max_rate: Option<f64>, max_consecutive: Option<u64>) -> Self {
+        Self {
+            max_rate,
+            max_consecutive,
+            total: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            consecutive: AtomicU64::new(0),
+            by_code: Mutex::new(BTreeMap::new()),
+            tripped: AtomicBool::new(false),
+        }
+    }
+
+    /// Fail if another worker already tripped the guard.
+    pub fn check(&self) -> Result<()> {
+        if self.tripped.load(Ordering::Relaxed) {
+            bail!("aborted: {}", self.breakdown());
+        }
+        Ok(())
+    }
+
+    pub fn record_success(&self) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        self.consecutive.store(0, Ordering::Relaxed);
+    }
+
+    /// Count an iteration that completed with `status`, which may be a client-detected
+    /// failure (a read mismatch, unexpected affected rows), failing if that breaches a
+    /// threshold.
+    pub fn record_status(&self, status: &Status) -> Result<()> {
+        if status.kind() == StatusKind::Success {
+            self.record_success();
+            return Ok(());
+        }
+        self.record_failure(
+            status.code(),
+            &format!("{} {}", kind_name(status), status.code()),
+        )
+    }
+
+    /// Count an iteration that failed with `err`, failing if that breaches a threshold.
+    pub fn record_error(&self, err: &anyhow::Error) -> Result<()> {
+        self.record_failure(error_code(err), &format!("{err:#}"))
+    }
+
+    /// Count a failed iteration with `code`, described by `last` if it breaches a threshold.
+    fn record_failure(&self, code: i64, last: &str) -> Result<()> {
+        *self.by_code.lock().unwrap().entry(code).or_default() += 1;
+        let total = self.total.fetch_add(1, Ordering::Relaxed) + 1;
+        let errors = self.errors.fetch_add(1, Ordering::Relaxed) + 1;
+        let consecutive = self.consecutive.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let breach = if self.max_consecutive.is_some_and(|max| consecutive > max) {
+            Some(format!("{consecutive} consecutive errors"))
+        } else {
+            let rate = errors as f64 / total as f64;
+            self.max_rate
+                .filter(|&max| total >= MIN_SAMPLES && rate > max)
+                .map(|max| format!("error rate {:.2}% above {:.2}%", rate * 100.0, max * 100.0))
+        };
+        if let Some(reason) = breach {
+            self.tripped.store(true, Ordering::Relaxed);
+            bail!("{reason} (last error: {last}); {}", self.breakdown());
+        }
+        Ok(())
+    }
+
+    fn breakdown(&self) -> String {
+        let mut out = format!(
+            "{} of {} iterations failed; errors by code:",
+            self.errors.load(Ordering::Relaxed),
+            self.total.load(Ordering::Relaxed)
+        );
+        for (code, count) in self.by_code.lock().unwrap().iter() {
+            let _ = write!(out, " {code}={count}");
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(code: u16) -> anyhow::Error {
+        mysql_async::Error::Server(mysql_async::ServerError {
+            code,
+            message: String::new(),
+            state: "HY000".to_owned(),
+        })
+        .into()
+    }
+
+    fn connection_closed() -> anyhow::Error {
+        mysql_async::Error::Driver(mysql_async::DriverError::ConnectionClosed).into()
+    }
+
+    #[test]
+    fn errors_map_to_their_codes_and_statuses() {
+        for code in [1213, 1205, 9007, 8022] {
+            let err = server(code).context("cannot commit");
+            assert!(is_transient(&err), "{code}");
+            assert_eq!(error_code(&err), i64::from(code));
+            let status = error_status(&err);
+            assert_eq!(status.kind(), StatusKind::ServerError);
+            assert_eq!(status.code(), i64::from(code));
+        }
+        assert_eq!(lock_conflict_code(&server(9007)), Some(9007));
+        assert_eq!(lock_conflict_code(&server(8022)), None);
+
+        let lost = connection_closed();
+        assert!(is_transient(&lost));
+        assert_eq!(error_code(&lost), CONNECTION_LOST);
+        let status = error_status(&lost);
+        assert_eq!(status.kind(), StatusKind::ClientError);
+        assert_eq!(status.code(), 2013);
+
+        let missing = server(NO_SUCH_TABLE);
+        assert!(!is_transient(&missing));
+        assert_eq!(error_status(&missing).code(), 1146);
+
+        let client = anyhow::anyhow!("read back a different row");
+        assert!(!is_transient(&client));
+        assert_eq!(error_code(&client), CLIENT_ERROR);
+        let status = error_status(&client);
+        assert_eq!(status.kind(), StatusKind::ClientError);
+        assert_eq!(status.code(), CLIENT_ERROR);
+    }
+
+    #[test]
+    fn consecutive_errors_trip_past_the_limit() {
+        let guard = ErrorGuard::new(None, Some(2));
+        guard.record_error(&server(9007)).unwrap();
+        guard.record_error(&server(9007)).unwrap();
+        guard.record_success();
+        guard.record_error(&server(9007)).unwrap();
+        guard.record_error(&server(1213)).unwrap();
+        assert!(guard.check().is_ok());
+
+        let err = guard.record_error(&server(1213)).unwrap_err().to_string();
+        assert!(err.contains("3 consecutive errors"), "{err}");
+        assert!(err.contains("1213=2 9007=3"), "{err}");
+        assert!(guard.check().is_err());
+    }
+
+    #[test]
+    fn error_rate_is_enforced_after_enough_samples() {
+        let guard = ErrorGuard::new(Some(0.5), None);
+        for _ in 0..50 {
+            guard.record_success();
+        }
+        // 100 iterations at exactly the limit.
+        for _ in 0..50 {
+            guard.record_error(&connection_closed()).unwrap();
+        }
+        assert!(guard.check().is_ok());
+
+        let err = guard
+            .record_error(&connection_closed())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("error rate 50.50% above 50.00%"), "{err}");
+        assert!(err.contains("51 of 101 iterations failed"), "{err}");
+        assert!(guard.check().is_err());
+    }
+
+    #[test]
+    fn early_errors_do_not_trip_the_rate() {
+        let guard = ErrorGuard::new(Some(0.1), None);
+        for _ in 0..MIN_SAMPLES - 1 {
+            guard.record_error(&server(9007)).unwrap();
+        }
+        assert!(guard.record_error(&server(9007)).is_err());
+    }
+}
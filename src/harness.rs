@@ -0,0 +1,983 @@
+//! Per-iteration driver shared by every benchmark suite.
+
+use std::future::Future;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use hdrhistogram::Histogram;
+use rlt::{IterInfo, IterReport, Status};
+use tokio::time::Instant;
+
+use crate::ddl::BackgroundDdl;
+use crate::errors::{error_status, is_transient, server_code, ErrorGuard, Fatal, NO_SUCH_TABLE};
+use crate::interrupt::Interrupt;
+use crate::latency_log::LatencyLog;
+use crate::load::{Pacer, Ramp, RampPlan, RampSchedule, ThinkTime};
+use crate::metrics::Metrics;
+use crate::plan::{PlanCapture, PlanStatement};
+use crate::results::Results;
+use crate::server_time::{LatencySource, ServerTime};
+use crate::slow_queries::SlowQueries;
+use crate::trace::SlowTrace;
+use crate::{parse_fraction, parse_percentile, status, ConfigFingerprint, DbOpts};
+
+/// Percentiles of the summaries printed after the run, unless `--percentiles` is given.
+const DEFAULT_PERCENTILES: [f64; 2] = [50.0, 99.0];
+
+/// Significant decimal digits the latency summaries keep: within 0.1% of the exact value.
+const LATENCY_DIGITS: u8 = 3;
+
+/// Options controlling how iterations are driven, shared by all binaries.
+#[derive(clap::Args, Clone)]
+pub struct RunOpts {
+    /// Open-loop target rate in iterations per second, shared by all workers.
+    ///
+    /// Unlike `--rate`, latency is measured from each iteration's scheduled start, so
+    /// queuing delay is included when the server falls behind.
+    #[clap(long, value_name = "OPS")]
+    pub target_rate: Option<NonZeroU32>,
+
+    /// Number of iterations allowed to start ahead of the `--target-rate` schedule.
+    #[clap(long, default_value = "1", requires = "target_rate")]
+    pub rate_burst: NonZeroU32,
+
+    /// Pause after each iteration, excluded from latency but not from throughput.
+    ///
+    /// Closed-loop alternative to `--target-rate`; the two cannot be combined.
+    #[clap(
+        long,
+        value_parser = humantime::parse_duration,
+        default_value = "0s",
+        conflicts_with = "target_rate"
+    )]
+    pub think_time: Duration,
+
+    /// Random jitter applied to `--think-time`, in percent.
+    #[clap(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub think_time_jitter: u8,
+
+    /// Start with one worker and release the rest linearly over this window.
+    ///
+    /// Successful iterations during the ramp are reported with status code 100.
+    #[clap(long, value_parser = humantime::parse_duration, conflicts_with = "ramp_schedule")]
+    pub ramp_up: Option<Duration>,
+
+    /// Stepwise ramp as PERCENT:TIME steps, e.g. `10:30s,50:60s,100:120s`.
+    ///
+    /// Each step keeps that share of the workers active until its end time.
+    #[clap(long, value_name = "SCHEDULE")]
+    pub ramp_schedule: Option<RampSchedule>,
+
+    /// Abort the run once more than this fraction of iterations have failed.
+    ///
+    /// Enforced after the first 100 iterations. Setting either error limit makes transient
+    /// errors count against it; any other error still aborts the run.
+    #[clap(long, value_parser = parse_fraction)]
+    pub max_error_rate: Option<f64>,
+
+    /// Abort the run after this many consecutive failed iterations across all workers.
+    #[clap(long)]
+    pub max_consecutive_errors: Option<u64>,
+
+    /// Recognized transient errors the run may absorb in total, across all workers.
+    ///
+    /// Once spent, the next one aborts the run, whether or not an error limit is set.
+    #[clap(long, value_name = "N")]
+    pub total_retry_budget: Option<u64>,
+
+    /// Time the begin, statement and commit phases of explicit transactions separately.
+    ///
+    /// The statement phase becomes the reported latency; begin and commit latencies are
+    /// summarized after the run. Auto-commit iterations have one phase and are unaffected.
+    #[clap(long)]
+    pub phase_latency: bool,
+
+    /// Print these latency percentiles after the run, e.g. `50,90,99,99.9`; each in (0, 100].
+    ///
+    /// They also replace the p50/p99 of the `--phase-latency` summary. rlt's own report
+    /// keeps its fixed set.
+    #[clap(
+        long,
+        value_name = "LIST",
+        value_delimiter = ',',
+        value_parser = parse_percentile
+    )]
+    pub percentiles: Vec<f64>,
+
+    /// `server` also reads the execution time of the benchmark's statements from TiDB's
+    /// statement summary and prints it after the run next to the client latency.
+    ///
+    /// The latency reported to rlt stays the client's: TiDB has no per-statement time to
+    /// attach to each iteration.
+    #[clap(long, value_enum, default_value = "client")]
+    pub latency_source: LatencySource,
+
+    /// Print the configuration fingerprint and every setting it covers as JSON on stdout,
+    /// then exit without connecting.
+    #[clap(long)]
+    pub fingerprint: bool,
+
+    /// This long after the first iteration, add an index on the benchmark table and drop it
+    /// again while the workload continues.
+    ///
+    /// The DDL timeline and the latency before, during and after each step are printed
+    /// after the run. Not allowed on a `--no-create` table.
+    #[clap(
+        long,
+        value_name = "DELAY",
+        value_parser = humantime::parse_duration,
+        conflicts_with = "no_create"
+    )]
+    pub background_ddl: Option<Duration>,
+
+    /// Look up the plan of iterations slower than this in TiDB's slow query log, decoded
+    /// with `TIDB_DECODE_PLAN`.
+    ///
+    /// Plans are logged as they come in and printed after the run. Statements only reach
+    /// the slow query log above the server's `tidb_slow_log_threshold` (300ms by default).
+    #[clap(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub trace_slow: Option<Duration>,
+
+    /// Once `--warmup` iterations have completed, run `EXPLAIN ANALYZE` on a representative
+    /// instance of each benchmark statement on a separate connection.
+    ///
+    /// Prepared statements are also explained in their parameterized form, as the plan cache
+    /// holds them. Writes run on a sacrificial key and are rolled back. The plans are printed
+    /// after the run and added to `--results-json`.
+    #[clap(long)]
+    pub capture_plan: bool,
+
+    /// Capture the plans again at the end of the run, before the tables are dropped.
+    #[clap(long, requires = "capture_plan")]
+    pub capture_plan_at_end: bool,
+
+    /// After the run, write the records of TiDB's slow query log that belong to the
+    /// benchmark's database and fall into the run's time window to this file: CSV if its name
+    /// ends in `.csv`, a JSON array otherwise.
+    ///
+    /// Records come from `INFORMATION_SCHEMA.CLUSTER_SLOW_QUERY`, or `SLOW_QUERY` if that
+    /// cannot be read, and are limited to the first 10000.
+    #[clap(long, value_name = "PATH")]
+    pub collect_slow_queries: Option<std::path::PathBuf>,
+
+    /// Lower `tidb_slow_log_threshold` to this on every TiDB instance connected to for the
+    /// run, and restore it afterwards, so `--collect-slow-queries` finds the statements.
+    ///
+    /// The variable is instance-wide, so other sessions on those instances are logged too.
+    #[clap(
+        long,
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        requires = "collect_slow_queries"
+    )]
+    pub slow_log_threshold: Option<Duration>,
+
+    /// Write the latency of every iteration, in nanoseconds, to this HdrHistogram log.
+    ///
+    /// Besides the histogram of all iterations, the log holds one per status kind and, with
+    /// explicit transactions, one per phase, as tagged intervals.
+    #[clap(long, value_name = "PATH", visible_alias = "histogram-out")]
+    pub hdr_out: Option<std::path::PathBuf>,
+
+    /// Significant decimal digits `--hdr-out` keeps for every value (1-5).
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t = 3,
+        value_parser = clap::value_parser!(u8).range(1..=5),
+        requires = "hdr_out"
+    )]
+    pub hdr_digits: u8,
+
+    /// Highest latency `--hdr-out` tracks exactly; slower iterations are recorded as this
+    /// value. Without it the histograms grow to fit any latency.
+    #[clap(
+        long,
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        requires = "hdr_out"
+    )]
+    pub hdr_max: Option<Duration>,
+
+    /// Write the configuration, server version, status counts, latency percentiles,
+    /// throughput and per-worker totals to this file as one JSON document.
+    ///
+    /// Unlike rlt's `--output json` report, it is also written when the run is interrupted
+    /// or aborted, marked `"partial": true`.
+    #[clap(long, value_name = "PATH")]
+    pub results_json: Option<std::path::PathBuf>,
+
+    /// Write one CSV line per iteration to this file: timestamp, worker, sequence number,
+    /// latency in microseconds, status, items and bytes.
+    #[clap(long, value_name = "PATH")]
+    pub latency_log: Option<std::path::PathBuf>,
+
+    /// Fraction of iterations written to `--latency-log`, e.g. `0.1`; the same iterations
+    /// are picked on every run.
+    #[clap(
+        long,
+        value_name = "FRACTION",
+        default_value_t = 1.0,
+        value_parser = parse_fraction,
+        requires = "latency_log"
+    )]
+    pub latency_log_sample: f64,
+
+    /// Serve live metrics in the Prometheus text format on this address during the run,
+    /// e.g. `0.0.0.0:9184`: iterations by status, latency histograms, items and bytes per
+    /// worker, and the number of workers mid-iteration.
+    #[clap(long, value_name = "ADDR")]
+    pub metrics_listen: Option<std::net::SocketAddr>,
+}
+
+impl RunOpts {
+    /// Effective settings, for the run header.
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = Vec::new();
+        if let Some(rate) = self.target_rate {
+            settings.push((
+                "target rate",
+                format!("{rate}/s (burst {})", self.rate_burst),
+            ));
+        }
+        if !self.think_time.is_zero() {
+            settings.push((
+                "think time",
+                format!(
+                    "{} ±{}%",
+                    humantime::format_duration(self.think_time),
+                    self.think_time_jitter
+                ),
+            ));
+        }
+        if let Some(rate) = self.max_error_rate {
+            settings.push(("max error rate", format!("{:.2}%", rate * 100.0)));
+        }
+        if let Some(max) = self.max_consecutive_errors {
+            settings.push(("max consecutive errors", max.to_string()));
+        }
+        if let Some(budget) = self.total_retry_budget {
+            settings.push(("retry budget", budget.to_string()));
+        }
+        if let Some(window) = self.ramp_up {
+            settings.push(("ramp-up", humantime::format_duration(window).to_string()));
+        }
+        if let Some(schedule) = &self.ramp_schedule {
+            settings.push(("ramp schedule", schedule.to_string()));
+        }
+        if self.phase_latency {
+            settings.push(("latency", "statement phase only".into()));
+        }
+        if !self.percentiles.is_empty() {
+            let list: Vec<_> = self.percentiles.iter().map(f64::to_string).collect();
+            settings.push(("percentiles", list.join(", ")));
+        }
+        if self.latency_source == LatencySource::Server {
+            settings.push((
+                "latency source",
+                "client, with server statement summary".into(),
+            ));
+        }
+        if let Some(delay) = self.background_ddl {
+            settings.push((
+                "background DDL",
+                format!("add/drop index after {}", humantime::format_duration(delay)),
+            ));
+        }
+        if self.capture_plan {
+            let when = if self.capture_plan_at_end {
+                "after warmup and at the end"
+            } else {
+                "after warmup"
+            };
+            settings.push(("capture plan", when.into()));
+        }
+        if let Some(threshold) = self.slow_log_threshold {
+            settings.push((
+                "slow log threshold",
+                format!("{} during the run", humantime::format_duration(threshold)),
+            ));
+        }
+        if let Some(threshold) = self.trace_slow {
+            settings.push((
+                "trace slow",
+                format!("over {}", humantime::format_duration(threshold)),
+            ));
+        }
+        settings
+    }
+
+    fn error_guard(&self) -> Option<ErrorGuard> {
+        (self.max_error_rate.is_some() || self.max_consecutive_errors.is_some())
+            .then(|| ErrorGuard::new(self.max_error_rate, self.max_consecutive_errors))
+    }
+
+    fn ramp_plan(&self) -> Option<RampPlan> {
+        match (self.ramp_up, &self.ramp_schedule) {
+            (Some(window), _) => Some(RampPlan::Linear(window)),
+            (None, Some(schedule)) => Some(RampPlan::Steps(schedule.clone())),
+            (None, None) => None,
+        }
+    }
+}
+
+/// What a single iteration did, before timing is attached.
+pub struct Outcome {
+    pub status: Status,
+    pub bytes: u64,
+    pub items: u64,
+    /// Latency measured by the suite itself, replacing the harness measurement.
+    pub elapsed: Option<Duration>,
+    /// Phases of the explicit transaction the iteration ran, if it ran one.
+    pub phases: Option<Phases>,
+}
+
+impl Outcome {
+    pub fn success(items: u64, bytes: u64) -> Self {
+        Self {
+            status: Status::success(status::OK),
+            bytes,
+            items,
+            elapsed: None,
+            phases: None,
+        }
+    }
+
+    pub fn failure(status: Status) -> Self {
+        Self {
+            status,
+            bytes: 0,
+            items: 0,
+            elapsed: None,
+            phases: None,
+        }
+    }
+
+    pub fn with_elapsed(self, elapsed: Duration) -> Self {
+        Self {
+            elapsed: Some(elapsed),
+            ..self
+        }
+    }
+
+    pub fn with_phases(self, phases: Option<Phases>) -> Self {
+        Self { phases, ..self }
+    }
+}
+
+/// Time spent in each phase of an explicit transaction.
+#[derive(Debug, Clone, Copy)]
+pub struct Phases {
+    pub begin: Duration,
+    pub statement: Duration,
+    pub commit: Duration,
+}
+
+/// Takes the timestamps between the phases of an explicit transaction.
+pub struct PhaseClock {
+    start: Instant,
+    begun: Instant,
+    executed: Instant,
+}
+
+impl PhaseClock {
+    /// Call right before beginning the transaction.
+    pub fn start() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            begun: now,
+            executed: now,
+        }
+    }
+
+    /// Call once the transaction has begun.
+    pub fn begun(&mut self) {
+        self.begun = Instant::now();
+    }
+
+    /// Call once the statements have run, right before committing.
+    pub fn executed(&mut self) {
+        self.executed = Instant::now();
+    }
+
+    /// Call once the transaction has committed.
+    pub fn committed(&self) -> Phases {
+        Phases {
+            begin: self.begun - self.start,
+            statement: self.executed - self.begun,
+            commit: self.executed.elapsed(),
+        }
+    }
+}
+
+/// Latencies in nanoseconds, one histogram per worker so recording never waits on another
+/// worker, merged for the summaries after the run. Memory stays the same however long the
+/// run is.
+pub(crate) struct Latencies {
+    /// Indexed by worker id.
+    workers: Vec<Mutex<Histogram<u64>>>,
+}
+
+impl Latencies {
+    pub(crate) fn new(workers: u32) -> Self {
+        Self {
+            workers: (0..workers.max(1))
+                .map(|_| Mutex::new(latency_histogram()))
+                .collect(),
+        }
+    }
+
+    /// Record a latency of `worker_id`.
+    pub(crate) fn record(&self, worker_id: u32, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX).max(1);
+        let mut histogram = self.workers[worker_id as usize % self.workers.len()]
+            .lock()
+            .unwrap();
+        // Resizing cannot fail for `u64` counts.
+        let _ = histogram.record(nanos);
+    }
+
+    /// The latencies of all workers.
+    pub(crate) fn merged(&self) -> Histogram<u64> {
+        let mut merged = latency_histogram();
+        for worker in &self.workers {
+            // Adding into a resizing histogram cannot fail.
+            let _ = merged.add(&*worker.lock().unwrap());
+        }
+        merged
+    }
+}
+
+/// An empty histogram of [`LATENCY_DIGITS`] significant digits that grows as needed.
+fn latency_histogram() -> Histogram<u64> {
+    Histogram::new(LATENCY_DIGITS).expect("valid significant digits")
+}
+
+/// Mean, the `percentiles` and max of `latencies` (nanoseconds), as one summary line.
+pub(crate) fn summarize(latencies: &Histogram<u64>, percentiles: &[f64]) -> String {
+    let mean = Duration::from_nanos(latencies.mean().round() as u64);
+    let mut line = format!("mean {mean:>10.2?}");
+    for p in percentiles {
+        let at = Duration::from_nanos(latencies.value_at_quantile(p / 100.0));
+        line.push_str(&format!("  p{p} {at:>10.2?}"));
+    }
+    let max = Duration::from_nanos(latencies.max());
+    line.push_str(&format!("  max {max:>10.2?}"));
+    line
+}
+
+/// Latency of every iteration, for the `--percentiles` summary.
+struct LatencyRecorder {
+    latencies: Latencies,
+}
+
+impl LatencyRecorder {
+    fn print(&self, percentiles: &[f64]) {
+        let latencies = self.latencies.merged();
+        if latencies.is_empty() {
+            eprintln!("latency: no iteration completed");
+            return;
+        }
+        eprintln!("latency ({} iterations):", latencies.len());
+        eprintln!("  {}", summarize(&latencies, percentiles));
+    }
+}
+
+/// Phases of every transactional iteration, for the `--phase-latency` summary.
+struct PhaseRecorder {
+    begin: Latencies,
+    statement: Latencies,
+    commit: Latencies,
+}
+
+impl PhaseRecorder {
+    fn new(workers: u32) -> Self {
+        Self {
+            begin: Latencies::new(workers),
+            statement: Latencies::new(workers),
+            commit: Latencies::new(workers),
+        }
+    }
+
+    fn record(&self, worker_id: u32, phases: Phases) {
+        self.begin.record(worker_id, phases.begin);
+        self.statement.record(worker_id, phases.statement);
+        self.commit.record(worker_id, phases.commit);
+    }
+
+    fn print(&self, percentiles: &[f64]) {
+        let phases = [
+            ("begin", self.begin.merged()),
+            ("statement", self.statement.merged()),
+            ("commit", self.commit.merged()),
+        ];
+        if phases[0].1.is_empty() {
+            eprintln!("phase latency: no iteration ran an explicit transaction");
+            return;
+        }
+        eprintln!("phase latency ({} transactions):", phases[0].1.len());
+        for (name, latencies) in phases {
+            eprintln!("  {name:<9}  {}", summarize(&latencies, percentiles));
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Harness {
+    pacer: Option<Arc<Pacer>>,
+    think: Option<ThinkTime>,
+    ramp: Option<Arc<Ramp>>,
+    errors: Option<Arc<ErrorGuard>>,
+    /// What is left of `--total-retry-budget`.
+    retry_budget: Option<Arc<AtomicU64>>,
+    phases: Option<Arc<PhaseRecorder>>,
+    latencies: Option<Arc<LatencyRecorder>>,
+    /// Percentiles of the summaries printed after the run.
+    percentiles: Arc<[f64]>,
+    interrupt: Arc<Interrupt>,
+    ddl: Option<Arc<BackgroundDdl>>,
+    server: Option<Arc<ServerTime>>,
+    slow: Option<Arc<SlowTrace>>,
+    plans: Option<Arc<PlanCapture>>,
+    results: Option<Arc<Results>>,
+    slow_queries: Option<Arc<SlowQueries>>,
+    latency_log: Option<Arc<LatencyLog>>,
+    metrics: Option<Arc<Metrics>>,
+    hdr: Option<Arc<crate::hdr::HdrRecorder>>,
+}
+
+impl Harness {
+    pub fn new(opts: &RunOpts, db: &DbOpts, workers: u32) -> Self {
+        Self {
+            pacer: opts
+                .target_rate
+                .map(|rate| Arc::new(Pacer::new(rate, opts.rate_burst))),
+            think: (!opts.think_time.is_zero())
+                .then(|| ThinkTime::new(opts.think_time, opts.think_time_jitter)),
+            ramp: opts
+                .ramp_plan()
+                .map(|plan| Arc::new(Ramp::new(plan, workers))),
+            errors: opts.error_guard().map(Arc::new),
+            retry_budget: opts
+                .total_retry_budget
+                .map(|budget| Arc::new(AtomicU64::new(budget))),
+            phases: opts
+                .phase_latency
+                .then(|| Arc::new(PhaseRecorder::new(workers))),
+            latencies: (!opts.percentiles.is_empty()).then(|| {
+                Arc::new(LatencyRecorder {
+                    latencies: Latencies::new(workers),
+                })
+            }),
+            percentiles: if opts.percentiles.is_empty() {
+                DEFAULT_PERCENTILES.into()
+            } else {
+                opts.percentiles.as_slice().into()
+            },
+            interrupt: Arc::default(),
+            ddl: opts
+                .background_ddl
+                .map(|delay| Arc::new(BackgroundDdl::new(db, delay, workers))),
+            server: (opts.latency_source == LatencySource::Server)
+                .then(|| Arc::new(ServerTime::new(db))),
+            slow: opts
+                .trace_slow
+                .map(|threshold| Arc::new(SlowTrace::new(db, threshold))),
+            plans: opts
+                .capture_plan
+                .then(|| Arc::new(PlanCapture::new(db, opts.capture_plan_at_end))),
+            results: opts
+                .results_json
+                .clone()
+                .map(|path| Arc::new(Results::new(path, db, &opts.percentiles, workers))),
+            slow_queries: opts
+                .collect_slow_queries
+                .clone()
+                .map(|path| Arc::new(SlowQueries::new(db, path, opts.slow_log_threshold))),
+            latency_log: opts
+                .latency_log
+                .clone()
+                .map(|path| Arc::new(LatencyLog::new(path, opts.latency_log_sample))),
+            metrics: opts
+                .metrics_listen
+                .map(|addr| Arc::new(Metrics::new(addr, workers))),
+            hdr: opts.hdr_out.clone().map(|path| {
+                let (digits, max) = (opts.hdr_digits, opts.hdr_max);
+                Arc::new(crate::hdr::HdrRecorder::new(path, workers, digits, max))
+            }),
+        }
+    }
+
+    /// Drive the whole run (`rlt::cli::run`), stopping it on Ctrl-C; returns whether it was
+    /// interrupted. See [`crate::interrupt`].
+    ///
+    /// The `--results-json` document, describing the run by its `fingerprint`, and the
+    /// `--collect-slow-queries` records are written and the `--latency-log` closed here, so an
+    /// interrupted or aborted run writes them too.
+    /// The `--metrics-listen` endpoint is served for the duration of the run, and
+    /// `--capture-plan` waits for rlt's `--warmup` iterations, read from the fingerprint,
+    /// which `--results-json` leaves out.
+    pub async fn run<F>(&self, fingerprint: &ConfigFingerprint, run: F) -> Result<bool>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        let warmup = fingerprint.value("warmup").and_then(|w| w.parse().ok());
+        let warmup = warmup.unwrap_or(0);
+        if let Some(results) = &self.results {
+            results.start(warmup).await;
+        }
+        if let Some(log) = &self.latency_log {
+            log.start()?;
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.start(&fingerprint.bench).await?;
+        }
+        if let Some(plans) = &self.plans {
+            plans.start(warmup);
+        }
+        // Last, so nothing fails between lowering the slow log threshold and the run.
+        if let Some(slow_queries) = &self.slow_queries {
+            slow_queries.start().await;
+        }
+        let slow_queries = self.slow_queries.clone();
+        let restore = async move {
+            if let Some(slow_queries) = slow_queries {
+                slow_queries.restore_threshold().await;
+            }
+        };
+        let interrupted = self.interrupt.run(run, restore).await;
+        if let Some(metrics) = &self.metrics {
+            metrics.stop();
+        }
+        if let Some(server) = &self.server {
+            server.finish().await;
+        }
+        if let Some(slow) = &self.slow {
+            slow.finish().await;
+        }
+        if let Some(plans) = &self.plans {
+            plans.finish().await;
+        }
+        let mut written = Vec::new();
+        if let Some(log) = &self.latency_log {
+            written.push(log.finish());
+        }
+        if let Some(slow_queries) = &self.slow_queries {
+            written.push(slow_queries.finish().await);
+        }
+        if let Some(results) = &self.results {
+            let plans = self.plans.as_ref().map(|plans| plans.json());
+            let partial = !matches!(interrupted, Ok(false));
+            written.push(results.write(fingerprint, partial, plans));
+        }
+        for result in written {
+            match (&interrupted, result) {
+                (Ok(_), Err(e)) => return Err(e),
+                (Err(_), Err(e)) => eprintln!("warning: {e:#}"),
+                (_, Ok(())) => {}
+            }
+        }
+        interrupted
+    }
+
+    /// Write the `--hdr-out` histogram and print the `--percentiles`, `--phase-latency`,
+    /// `--background-ddl`, `--latency-source server`, `--trace-slow` and
+    /// `--collect-slow-queries` summaries and the `--capture-plan` plans, whichever were
+    /// requested. Call once, after the run.
+    pub fn report(&self) -> Result<()> {
+        if let Some(hdr) = &self.hdr {
+            hdr.write()?;
+        }
+        if let Some(latencies) = &self.latencies {
+            latencies.print(&self.percentiles);
+        }
+        if let Some(phases) = &self.phases {
+            phases.print(&self.percentiles);
+        }
+        if let Some(ddl) = &self.ddl {
+            ddl.print(&self.percentiles);
+        }
+        if let Some(server) = &self.server {
+            server.print();
+        }
+        if let Some(slow) = &self.slow {
+            slow.print();
+        }
+        if let Some(slow_queries) = &self.slow_queries {
+            slow_queries.print();
+        }
+        if let Some(plans) = &self.plans {
+            plans.print();
+        }
+        Ok(())
+    }
+
+    /// Hand `--capture-plan` the statements of an iteration. Call from setup, once the tables
+    /// are ready; `statements` is only called when plans are captured.
+    pub fn plan_statements(&self, statements: impl FnOnce() -> Vec<PlanStatement>) {
+        if let Some(plans) = &self.plans {
+            plans.set_statements(statements());
+        }
+    }
+
+    /// Capture the plans again with `--capture-plan-at-end`. Call from the teardown of the
+    /// last worker, before the tables are dropped.
+    pub async fn capture_final_plans(&self) {
+        if let Some(plans) = &self.plans {
+            plans.capture_at_end().await;
+        }
+    }
+
+    /// Take one unit of `--total-retry-budget`; `false` once it is spent.
+    fn spend_retry(&self) -> bool {
+        self.retry_budget.as_ref().is_none_or(|left| {
+            left.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_ok()
+        })
+    }
+
+    /// Count transient error `e` against the error limits and `--total-retry-budget`, and
+    /// fail the iteration with it unless that breaches one of them.
+    fn absorb(&self, e: anyhow::Error) -> Result<Outcome> {
+        if let Some(guard) = &self.errors {
+            guard.record_error(&e)?;
+        }
+        if !self.spend_retry() {
+            return Err(e.context("--total-retry-budget is spent"));
+        }
+        Ok(Outcome::failure(error_status(&e)))
+    }
+
+    /// Run one iteration, timing it from its scheduled start.
+    ///
+    /// Work done before calling this (e.g. building SQL text) is not measured. Transient
+    /// errors (see [`is_transient`]) are reported as failed iterations, counted against the
+    /// error limits if any are set; any other error aborts the run.
+    pub async fn iter<F>(&self, info: &IterInfo, iteration: F) -> Result<IterReport>
+    where
+        F: Future<Output = Result<Outcome>>,
+    {
+        if let Some(guard) = &self.errors {
+            guard.check()?;
+        }
+        if let Some(server) = &self.server {
+            server.start().await;
+        }
+        let ramping = match &self.ramp {
+            Some(ramp) => ramp.admit(info.worker_id).await,
+            None => false,
+        };
+        let start = match &self.pacer {
+            Some(pacer) => pacer.wait().await,
+            None => Instant::now(),
+        };
+        let _in_flight = self.interrupt.admit().await;
+        let _active = self.metrics.as_ref().map(|metrics| metrics.enter());
+        if let Some(ddl) = &self.ddl {
+            ddl.start();
+        }
+        let mut outcome =
+            match (iteration.await, &self.errors) {
+                (Ok(outcome), guard) => {
+                    if let Some(guard) = guard {
+                        guard.record_status(&outcome.status)?;
+                    }
+                    outcome
+                }
+                (Err(e), _) if e.is::<Fatal>() => return Err(e),
+                (Err(e), _) if is_transient(&e) => self.absorb(e)?,
+                (Err(e), _) if server_code(&e) == Some(NO_SUCH_TABLE) => return Err(e.context(
+                    "a benchmark table disappeared during the run; was it dropped by another run \
+                     with the same --table?",
+                )),
+                (Err(e), _) => return Err(e),
+            };
+        if ramping && outcome.status == Status::success(status::OK) {
+            outcome.status = Status::success(status::RAMP_UP);
+        }
+        let duration = match (&self.phases, outcome.phases) {
+            (Some(recorder), Some(phases)) => {
+                recorder.record(info.worker_id, phases);
+                phases.statement
+            }
+            _ => outcome.elapsed.unwrap_or_else(|| start.elapsed()),
+        };
+        if let Some(latencies) = &self.latencies {
+            latencies.latencies.record(info.worker_id, duration);
+        }
+        if let Some(ddl) = &self.ddl {
+            ddl.record(info.worker_id, duration);
+        }
+        if let Some(server) = &self.server {
+            server.record(duration);
+        }
+        if let Some(slow) = &self.slow {
+            slow.observe(duration);
+        }
+        if let Some(hdr) = &self.hdr {
+            hdr.record(info.worker_id, duration, &outcome.status, outcome.phases);
+        }
+        let report = IterReport {
+            duration,
+            status: outcome.status,
+            bytes: outcome.bytes,
+            items: outcome.items,
+        };
+        if let Some(results) = &self.results {
+            results.record(info.worker_id, &report);
+        }
+        if let Some(log) = &self.latency_log {
+            log.record(info, &report);
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.record(info.worker_id, &report);
+        }
+        if let Some(plans) = &self.plans {
+            plans.observe();
+        }
+        if let Some(think) = &self.think {
+            think.pause().await;
+        }
+        Ok(report)
+    }
+}
+
+/// Iterations observed before a mismatch on every one of them is warned about.
+const ROW_COUNT_SAMPLES: u64 = 100;
+
+/// Tracks iterations whose row count differs from what they requested.
+///
+/// A mismatch on every iteration is almost always a setup problem (an under-seeded or
+/// wrong table), so it is warned about once as soon as it becomes evident, and summarized
+/// after the run.
+#[derive(Default)]
+pub struct RowCounts {
+    checked: AtomicU64,
+    mismatched: AtomicU64,
+    warned: AtomicBool,
+}
+
+impl RowCounts {
+    /// Record an iteration that got `actual` rows out of `expected`; returns whether they match.
+    pub fn check(&self, expected: u64, actual: u64) -> bool {
+        let checked = self.checked.fetch_add(1, Ordering::Relaxed) + 1;
+        let matched = actual == expected;
+        let mismatched = if matched {
+            self.mismatched.load(Ordering::Relaxed)
+        } else {
+            self.mismatched.fetch_add(1, Ordering::Relaxed) + 1
+        };
+        if checked >= ROW_COUNT_SAMPLES
+            && mismatched == checked
+            && !self.warned.swap(true, Ordering::Relaxed)
+        {
+            eprintln!(
+                "warning: none of the first {checked} iterations got the requested number of rows \
+                 (last: {actual} of {expected}); is the table set up as expected?"
+            );
+        }
+        matched
+    }
+
+    /// Print how many iterations came up with a different row count, if any. Call once,
+    /// after the run.
+    pub fn print(&self) {
+        let checked = self.checked.load(Ordering::Relaxed);
+        let mismatched = self.mismatched.load(Ordering::Relaxed);
+        if mismatched == 0 {
+            return;
+        }
+        let hint = if mismatched == checked {
+            "; this usually means the table is not set up as expected"
+        } else {
+            ""
+        };
+        eprintln!(
+            "warning: {mismatched} of {checked} iterations got a different number of rows \
+             than requested{hint}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latencies_of_all_workers_are_merged() {
+        let latencies = Latencies::new(4);
+        for i in 1..=1000u64 {
+            latencies.record((i % 4) as u32, Duration::from_micros(i));
+        }
+        let merged = latencies.merged();
+        assert_eq!(merged.len(), 1000);
+        // Within the histogram's 0.1% precision.
+        let near = |value: u64, micros: u64| value.abs_diff(micros * 1000) <= micros;
+        assert!(near(merged.value_at_quantile(0.5), 500));
+        assert!(near(merged.value_at_quantile(0.99), 990));
+        assert!(near(merged.max(), 1000));
+        let summary = summarize(&merged, &[50.0, 99.0]);
+        assert!(summary.starts_with("mean"), "{summary}");
+        assert!(summary.contains("p99"), "{summary}");
+    }
+
+    #[derive(clap::Parser)]
+    struct Cli {
+        #[command(flatten)]
+        run: RunOpts,
+        #[command(flatten)]
+        db: DbOpts,
+    }
+
+    fn harness(args: &[&str]) -> Harness {
+        use clap::Parser;
+        let cli = Cli::parse_from(std::iter::once("test").chain(args.iter().copied()));
+        Harness::new(&cli.run, &cli.db, 1)
+    }
+
+    fn deadlock() -> anyhow::Error {
+        mysql_async::Error::Server(mysql_async::ServerError {
+            code: 1213,
+            message: String::new(),
+            state: "40001".to_owned(),
+        })
+        .into()
+    }
+
+    #[test]
+    fn retry_budget_applies_with_and_without_error_limits() {
+        for limits in [&[][..], &["--max-consecutive-errors", "100"]] {
+            let harness = harness(&[&["--total-retry-budget", "2"], limits].concat());
+            for _ in 0..2 {
+                let outcome = harness.absorb(deadlock()).unwrap();
+                assert_eq!(outcome.status.code(), 1213);
+            }
+            let err = harness
+                .absorb(deadlock())
+                .err()
+                .expect("the error is not absorbed");
+            assert!(
+                format!("{err:#}").contains("--total-retry-budget is spent"),
+                "{err:#}"
+            );
+        }
+    }
+
+    #[test]
+    fn error_limits_apply_before_the_budget() {
+        let harness = harness(&["--max-consecutive-errors", "1", "--total-retry-budget", "5"]);
+        harness.absorb(deadlock()).unwrap();
+        let err = harness
+            .absorb(deadlock())
+            .err()
+            .expect("the error is not absorbed");
+        assert!(
+            format!("{err:#}").contains("2 consecutive errors"),
+            "{err:#}"
+        );
+    }
+}
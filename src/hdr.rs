@@ -0,0 +1,151 @@
+//! Raw latency histograms written as an HdrHistogram interval log (`--hdr-out`).
+//!
+//! Each worker records into its own histograms, merged when the log is written. The log
+//! holds the histogram of all iterations, untagged, followed by tagged ones per status kind
+//! and, for explicit transactions, per phase.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use hdrhistogram::serialization::interval_log::{IntervalLogWriterBuilder, Tag};
+use hdrhistogram::serialization::V2DeflateSerializer;
+use hdrhistogram::Histogram;
+use rlt::Status;
+use tokio::time::Instant;
+
+use crate::harness::Phases;
+use crate::status::kind_name;
+
+/// Tags of the histograms besides the untagged one of all iterations: status kinds
+/// ([`kind_name`]), then transaction phases.
+const TAGS: [&str; 7] = [
+    "success",
+    "client_error",
+    "server_error",
+    "error",
+    "begin",
+    "statement",
+    "commit",
+];
+
+/// Index of the first phase in [`TAGS`].
+const PHASES: usize = 4;
+
+/// Histograms of one worker: all iterations, then one per tag.
+struct Histograms {
+    all: Histogram<u64>,
+    tagged: Vec<Histogram<u64>>,
+}
+
+/// Iteration latencies of all workers, in nanoseconds.
+pub struct HdrRecorder {
+    path: PathBuf,
+    start: SystemTime,
+    started: Instant,
+    /// Indexed by worker id.
+    workers: Vec<Mutex<Histograms>>,
+}
+
+impl HdrRecorder {
+    /// Histograms keeping `digits` significant decimal digits, up to `max` if given and
+    /// resizing themselves as needed otherwise.
+    pub fn new(path: PathBuf, workers: u32, digits: u8, max: Option<Duration>) -> Self {
+        let histogram = || {
+            match max {
+                Some(max) => {
+                    let high = u64::try_from(max.as_nanos()).unwrap_or(u64::MAX).max(2);
+                    Histogram::new_with_bounds(1, high, digits)
+                }
+                None => Histogram::new(digits),
+            }
+            .expect("valid histogram bounds and significant digits")
+        };
+        Self {
+            path,
+            start: SystemTime::now(),
+            started: Instant::now(),
+            workers: (0..workers.max(1))
+                .map(|_| {
+                    Mutex::new(Histograms {
+                        all: histogram(),
+                        tagged: TAGS.iter().map(|_| histogram()).collect(),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Record an iteration of `worker_id`, with the phases of its transaction if timed.
+    pub fn record(
+        &self,
+        worker_id: u32,
+        latency: Duration,
+        status: &Status,
+        phases: Option<Phases>,
+    ) {
+        let nanos = |d: Duration| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX).max(1);
+        let kind = TAGS
+            .iter()
+            .position(|&tag| tag == kind_name(status))
+            .expect("every status kind has a tag");
+        let mut worker = self.workers[worker_id as usize % self.workers.len()]
+            .lock()
+            .unwrap();
+        record(&mut worker.all, nanos(latency));
+        record(&mut worker.tagged[kind], nanos(latency));
+        if let Some(p) = phases {
+            for (i, phase) in [p.begin, p.statement, p.commit].into_iter().enumerate() {
+                record(&mut worker.tagged[PHASES + i], nanos(phase));
+            }
+        }
+    }
+
+    /// Write everything recorded so far as a single interval covering the run, per tag.
+    pub fn write(&self) -> Result<()> {
+        let write = || -> Result<()> {
+            let (mut all, mut tagged) = {
+                let first = self.workers[0].lock().unwrap();
+                (first.all.clone(), first.tagged.clone())
+            };
+            for worker in &self.workers[1..] {
+                let worker = worker.lock().unwrap();
+                all.add(&worker.all)?;
+                for (merged, histogram) in tagged.iter_mut().zip(&worker.tagged) {
+                    merged.add(histogram)?;
+                }
+            }
+
+            let mut file = BufWriter::new(File::create(&self.path)?);
+            let mut serializer = V2DeflateSerializer::new();
+            let mut log = IntervalLogWriterBuilder::new()
+                .with_start_time(self.start)
+                .with_base_time(self.start)
+                // Max values in the log are for humans; show them in milliseconds.
+                .with_max_value_divisor(1_000_000.0)
+                .begin_log_with(&mut file, &mut serializer)?;
+            let elapsed = self.started.elapsed();
+            log.write_histogram(&all, Duration::ZERO, elapsed, None)?;
+            for (tag, histogram) in TAGS.iter().zip(&tagged) {
+                if !histogram.is_empty() {
+                    log.write_histogram(histogram, Duration::ZERO, elapsed, Tag::new(tag))?;
+                }
+            }
+            Ok(())
+        };
+        write().with_context(|| format!("cannot write histogram to {}", self.path.display()))
+    }
+}
+
+/// Record `nanos`, clamped to the `--hdr-max` of a bounded histogram; unbounded ones resize.
+fn record(histogram: &mut Histogram<u64>, nanos: u64) {
+    if histogram.is_auto_resize() {
+        // Resizing cannot fail for `u64` counts.
+        let _ = histogram.record(nanos);
+    } else {
+        histogram.saturating_record(nanos);
+    }
+}
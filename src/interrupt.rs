@@ -0,0 +1,131 @@
+//! Ctrl-C handling: stop the workers, let the run clean up, exit with a distinct code.
+//!
+//! The first Ctrl-C stops workers from starting new iterations and gives the ones in flight
+//! [`GRACE`] to finish before the run is abandoned, so `main` can drop the benchmark tables
+//! and print what was collected. A second Ctrl-C exits immediately, skipping all of that but
+//! restoring the server settings the run changed.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::Notify;
+
+use crate::DbOpts;
+
+/// Exit code of an interrupted run (128 + SIGINT, as shells report it).
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// How long iterations in flight at the first Ctrl-C get to finish.
+pub const GRACE: Duration = Duration::from_secs(2);
+
+/// Shared between the iterations of all workers and the task driving the run.
+#[derive(Default)]
+pub struct Interrupt {
+    stopping: AtomicBool,
+    in_flight: AtomicU64,
+    drained: Notify,
+}
+
+/// Marks one iteration as in flight until dropped.
+pub struct InFlight<'a>(&'a Interrupt);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::AcqRel) == 1
+            && self.0.stopping.load(Ordering::Acquire)
+        {
+            self.0.drained.notify_waiters();
+        }
+    }
+}
+
+impl Interrupt {
+    /// Admit an iteration; once the run is stopping this never returns, leaving the worker
+    /// parked until the run is abandoned.
+    pub async fn admit(&self) -> InFlight<'_> {
+        if self.stopping.load(Ordering::Acquire) {
+            std::future::pending::<()>().await;
+        }
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        InFlight(self)
+    }
+
+    /// Stop admitting iterations and wait until none is in flight.
+    async fn drain(&self) {
+        self.stopping.store(true, Ordering::Release);
+        loop {
+            let drained = self.drained.notified();
+            if self.in_flight.load(Ordering::Acquire) == 0 {
+                return;
+            }
+            drained.await;
+        }
+    }
+
+    /// Drive `run` to completion, or stop it on Ctrl-C; returns whether it was interrupted.
+    ///
+    /// If the run finishes on its own during the grace period (e.g. rlt reacting to the same
+    /// signal), its report is printed as usual and it counts as interrupted all the same.
+    /// `restore` undoes server settings changed for the run; a second Ctrl-C gives it up to
+    /// [`GRACE`] before exiting.
+    pub async fn run<F, R>(&self, run: F, restore: R) -> Result<bool>
+    where
+        F: Future<Output = Result<()>>,
+        R: Future<Output = ()> + Send + 'static,
+    {
+        tokio::pin!(run);
+        tokio::select! {
+            result = &mut run => return result.map(|()| false),
+            signal = tokio::signal::ctrl_c() => signal?,
+        }
+        eprintln!(
+            "interrupted; waiting up to {} for in-flight iterations (Ctrl-C again to exit now)",
+            humantime::format_duration(GRACE)
+        );
+        tokio::spawn(async {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                eprintln!("interrupted again; exiting without cleanup");
+                let _ = tokio::time::timeout(GRACE, restore).await;
+                std::process::exit(EXIT_INTERRUPTED);
+            }
+        });
+        tokio::select! {
+            result = &mut run => result?,
+            _ = tokio::time::timeout(GRACE, self.drain()) => {}
+        }
+        Ok(true)
+    }
+}
+
+/// Drop those of `tables` (unquoted) the run created, unless it keeps them, and a database
+/// it created with `--drop-database`, then exit with [`EXIT_INTERRUPTED`].
+///
+/// The workers never reached their teardown, so this is the only cleanup the run gets.
+/// Failures are reported but do not change the exit code.
+pub async fn exit_interrupted(db: &DbOpts, tables: &[String]) -> ! {
+    clean_up_abandoned(db, tables).await;
+    std::process::exit(EXIT_INTERRUPTED);
+}
+
+/// Clean up after a run that ended without its workers reaching their teardown, interrupted
+/// or aborted (e.g. by `--max-error-rate`): drop those of `tables` (unquoted) the run
+/// created, unless it keeps them, and a database it created with `--drop-database`.
+///
+/// Failures are reported, not returned.
+pub async fn clean_up_abandoned(db: &DbOpts, tables: &[String]) {
+    if db.drops_table() {
+        let cleanup = async {
+            let mut conn = db.connect().await?;
+            for table in tables {
+                db.drop_table(&mut conn, table).await?;
+            }
+            anyhow::Ok(())
+        };
+        if let Err(e) = cleanup.await {
+            eprintln!("warning: cleanup after the run failed: {e:#}");
+        }
+    }
+    db.drop_created_database().await;
+}
@@ -0,0 +1,101 @@
+//! Raw per-iteration samples as CSV (`--latency-log`).
+//!
+//! Percentile summaries hide multimodal distributions; the log keeps every iteration (or a
+//! `--latency-log-sample` of them) for offline analysis. Lines are formatted on the worker
+//! and handed to a writer thread, so file I/O never runs on the measured path.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use rlt::{IterInfo, IterReport};
+
+use crate::distributions::unit;
+use crate::status::kind_name;
+
+const HEADER: &str = "timestamp_us,worker,seq,duration_us,status_kind,status_code,items,bytes";
+
+/// Shared by all workers.
+pub struct LatencyLog {
+    path: PathBuf,
+    sample: f64,
+    /// Gone before the run starts and after it ends.
+    lines: RwLock<Option<Sender<String>>>,
+    writer: Mutex<Option<JoinHandle<std::io::Result<()>>>>,
+}
+
+impl LatencyLog {
+    pub fn new(path: PathBuf, sample: f64) -> Self {
+        Self {
+            path,
+            sample,
+            lines: RwLock::default(),
+            writer: Mutex::default(),
+        }
+    }
+
+    /// Create the file and start the writer. Call once, before the run.
+    pub fn start(&self) -> Result<()> {
+        let file = File::create(&self.path)
+            .with_context(|| format!("cannot create latency log {}", self.path.display()))?;
+        let (tx, rx) = mpsc::channel::<String>();
+        let writer = std::thread::spawn(move || {
+            let mut out = BufWriter::new(file);
+            writeln!(out, "{HEADER}")?;
+            for line in rx {
+                out.write_all(line.as_bytes())?;
+            }
+            out.flush()
+        });
+        *self.lines.write().unwrap() = Some(tx);
+        *self.writer.lock().unwrap() = Some(writer);
+        Ok(())
+    }
+
+    /// Log an iteration that just completed, if it falls into the sample.
+    ///
+    /// The sample is drawn from the worker id and sequence number, so the same iterations
+    /// are kept on every run.
+    pub fn record(&self, info: &IterInfo, report: &IterReport) {
+        if self.sample < 1.0
+            && unit(((info.worker_id as u64) << 40) ^ info.worker_seq) >= self.sample
+        {
+            return;
+        }
+        let Some(lines) = &*self.lines.read().unwrap() else {
+            return;
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        // The writer only goes away once the run is over.
+        let _ = lines.send(format!(
+            "{timestamp},{},{},{},{},{},{},{}\n",
+            info.worker_id,
+            info.worker_seq,
+            report.duration.as_micros(),
+            kind_name(&report.status),
+            report.status.code(),
+            report.items,
+            report.bytes
+        ));
+    }
+
+    /// Flush and close the file. Call once, after the run, interrupted or not.
+    pub fn finish(&self) -> Result<()> {
+        self.lines.write().unwrap().take();
+        let Some(writer) = self.writer.lock().unwrap().take() else {
+            return Ok(());
+        };
+        writer
+            .join()
+            .map_err(|_| anyhow::anyhow!("the latency log writer panicked"))?
+            .with_context(|| format!("cannot write latency log {}", self.path.display()))
+    }
+}
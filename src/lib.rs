@@ -1,9 +1,74 @@
-use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::builder::BoolishValueParser;
 use mysql_async::prelude::*;
-use mysql_async::{Conn, Opts, OptsBuilder};
+use mysql_async::{Conn, Opts, OptsBuilder, Row, SslOpts, Transaction, Value};
+
+use crate::errors::Fatal;
+use crate::session::{ConnPool, Session};
+
+pub mod affected;
+pub mod ddl;
+pub mod distributions;
+pub mod errors;
+pub mod harness;
+pub mod hdr;
+pub mod interrupt;
+pub mod latency_log;
+pub mod lifecycle;
+pub mod load;
+pub mod metrics;
+pub mod payload;
+pub mod plan;
+pub mod preflight;
+pub mod results;
+pub mod seed;
+pub mod server_time;
+pub mod session;
+pub mod slow_queries;
+pub mod status;
+pub mod suites;
+pub mod trace;
+
+pub use suites::{
+    BenchOpts, Benchmark, GapLockBench, GapLockOpts, InsertBench, InsertOpts, ParamsBench,
+    ParamsOpts, RywBench, RywOpts, SavepointBench, SavepointOpts, SelectBench, SelectOpts,
+    SubqueryBench, SubqueryOpts,
+};
 
 pub const DEFAULT_PORT: u16 = 4000;
 
+/// Connection attempts per recovery before leaving it to the next failed iteration.
+const RECONNECT_ATTEMPTS: u32 = 3;
+
+/// Pause before the second connection attempt, doubled for each further one.
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Smallest `max_allowed_packet` MySQL and TiDB accept.
+const MIN_PACKET: u64 = 1024;
+
+/// MySQL `ER_ACCESS_DENIED_ERROR`.
+const ACCESS_DENIED: u16 = 1045;
+
+/// MySQL `ER_PARSE_ERROR`, returned by servers that do not know a syntax extension.
+const PARSE_ERROR: u16 = 1064;
+
+/// Start of the comment on every table the benchmarks create, marking it as safe to drop.
+pub const TABLE_TAG: &str = "tidb-bench";
+
+/// MySQL `ER_BAD_DB_ERROR`, for a `--database` that does not exist.
+const UNKNOWN_DATABASE: u16 = 1049;
+
+/// Most existing databases or tables named in an error message.
+const LISTED_NAMES: usize = 20;
+
 #[derive(Debug, Clone, clap::ValueEnum)]
 pub enum TxMode {
     /// No explicit transaction; each query auto-commits.
@@ -14,14 +79,254 @@ pub enum TxMode {
     Pessimistic,
 }
 
+/// How read-only transactions end (`--read-txn-end`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReadTxnEnd {
+    /// `COMMIT`, the way a write transaction ends.
+    Commit,
+    /// `ROLLBACK`, which has nothing to undo and skips the commit protocol.
+    Rollback,
+}
+
+impl ReadTxnEnd {
+    /// End `tx`, which only read; `locking` says whether it took locks (`FOR UPDATE`),
+    /// which always commits so the locks are released the way the application would.
+    pub async fn finish(self, tx: Transaction<'_>, locking: bool) -> Result<()> {
+        match self {
+            ReadTxnEnd::Rollback if !locking => tx.rollback().await?,
+            _ => tx.commit().await?,
+        }
+        Ok(())
+    }
+}
+
+/// How statement parameters reach the server (`--protocol`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Protocol {
+    /// Prepared statements executed with bound values; the driver prepares each distinct
+    /// statement once per connection, and again on the new connection after a reconnect.
+    Binary,
+    /// Values escaped and spliced into the SQL text, parsed by the server every time.
+    Text,
+}
+
+impl Protocol {
+    /// Run `query`, whose `?` placeholders stand for `params`, and collect its rows.
+    pub async fn query<Q, T>(self, q: &mut Q, query: &str, params: Vec<Value>) -> Result<Vec<T>>
+    where
+        Q: Queryable,
+        T: FromRow + Send + 'static,
+    {
+        Ok(match self {
+            Protocol::Binary => q.exec(query, params).await?,
+            Protocol::Text => q.query(splice(query, &params)).await?,
+        })
+    }
+}
+
+/// `query` with each `?` replaced by the next of `params` as an SQL literal.
+pub(crate) fn splice(query: &str, params: &[Value]) -> String {
+    let mut parts = query.split('?');
+    let mut sql = parts.next().unwrap_or_default().to_owned();
+    for (part, value) in parts.zip(params) {
+        sql.push_str(&value.as_sql(false));
+        sql.push_str(part);
+    }
+    sql
+}
+
+/// Authentication plugins supported by the driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[clap(rename_all = "snake_case")]
+pub enum AuthPlugin {
+    /// Whatever the server asks for, except cleartext.
+    Auto,
+    /// `mysql_native_password`; negotiated automatically when the server requests it.
+    MysqlNativePassword,
+    /// `caching_sha2_password`; negotiated automatically when the server requests it.
+    CachingSha2Password,
+    /// `mysql_clear_password`, e.g. for LDAP authentication; requires `--tls`.
+    MysqlClearPassword,
+}
+
+/// One `host[:port]` entry of `--host`; entries without a port use `--port`.
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl FromStr for Endpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        // `[v6addr]:port` keeps the colons of an IPv6 address apart from the port.
+        let (host, port) = match s.strip_prefix('[') {
+            Some(rest) => {
+                let (host, rest) = rest
+                    .split_once(']')
+                    .ok_or_else(|| format!("'{s}': missing ']'"))?;
+                match rest {
+                    "" => (host, None),
+                    _ => (
+                        host,
+                        Some(
+                            rest.strip_prefix(':')
+                                .ok_or_else(|| format!("'{s}': expected ':' after ']'"))?,
+                        ),
+                    ),
+                }
+            }
+            None => match s.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (s, None),
+            },
+        };
+        if host.is_empty() {
+            return Err(format!("'{s}': empty host"));
+        }
+        let port = port
+            .map(|p| match p.parse::<u16>() {
+                Ok(port) if port != 0 => Ok(port),
+                _ => Err(format!("'{s}': invalid port '{p}'")),
+            })
+            .transpose()?;
+        Ok(Self {
+            host: host.to_owned(),
+            port,
+        })
+    }
+}
+
+/// Session variables `--set` refuses, with the reason.
+const RESERVED_VARS: [(&str, &str); 3] = [
+    ("autocommit", "it changes what --tx-mode measures"),
+    ("tidb_txn_mode", "use --tx-mode"),
+    (
+        "tidb_snapshot",
+        "it turns every session into a read-only historical read",
+    ),
+];
+
+/// One `var=value` entry of `--set`, applied as `SET SESSION var = value`.
+#[derive(Debug, Clone)]
+pub struct SessionVar {
+    name: String,
+    value: String,
+}
+
+impl SessionVar {
+    fn statement(&self) -> String {
+        // Numbers and keywords such as ON or DEFAULT go in as-is, anything else as a string.
+        let bare = self.value.parse::<f64>().is_ok()
+            || self
+                .value
+                .chars()
+                .all(|c| c.is_ascii_alphabetic() || c == '_');
+        let value = if bare {
+            self.value.clone()
+        } else {
+            quote_str(&self.value)
+        };
+        format!("SET SESSION {} = {value}", self.name)
+    }
+}
+
+impl std::fmt::Display for SessionVar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+impl FromStr for SessionVar {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("'{s}': expected VAR=VALUE"))?;
+        let name = name.trim().to_ascii_lowercase();
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(format!("'{s}': '{name}' is not a variable name"));
+        }
+        if let Some((_, reason)) = RESERVED_VARS.iter().find(|(var, _)| *var == name) {
+            return Err(format!("'{s}': {name} cannot be set with --set; {reason}"));
+        }
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(format!("'{s}': missing value"));
+        }
+        Ok(Self {
+            name,
+            value: value.to_owned(),
+        })
+    }
+}
+
+/// A collation name from `--collation`, such as `utf8mb4_bin`.
+///
+/// Only lowercase letters, digits and underscores are accepted, so it can go into DDL and
+/// `SET NAMES` unquoted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collation(String);
+
+impl Collation {
+    /// The character set the collation belongs to: the part before the first underscore.
+    pub fn charset(&self) -> &str {
+        self.0.split('_').next().unwrap_or_default()
+    }
+}
+
+impl std::fmt::Display for Collation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Collation {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let name = s.trim().to_ascii_lowercase();
+        let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        match name.split_once('_') {
+            Some((charset, rest)) if valid && !charset.is_empty() && !rest.is_empty() => {
+                Ok(Self(name))
+            }
+            _ => Err(format!(
+                "'{s}' is not a collation name (expected e.g. utf8mb4_bin or utf8mb4_general_ci)"
+            )),
+        }
+    }
+}
+
+/// Which kind of endpoint a connection is for; see `--read-host` and `--write-host`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Read,
+    Write,
+}
+
 /// Common database connection and benchmark options.
 #[derive(clap::Args, Clone)]
 pub struct DbOpts {
-    /// TiDB server host.
-    #[clap(long, default_value = "localhost")]
-    pub host: String,
+    /// TiDB server host, or a comma-separated list of `host[:port]` to spread workers over.
+    ///
+    /// Workers are assigned to hosts round-robin by worker id.
+    #[clap(long, value_delimiter = ',', default_value = "localhost")]
+    pub host: Vec<Endpoint>,
+
+    /// Hosts for read-only benchmarks, e.g. a read proxy; defaults to `--host`.
+    #[clap(long, value_delimiter = ',', value_name = "HOST")]
+    pub read_host: Option<Vec<Endpoint>>,
 
-    /// TiDB server port.
+    /// Hosts for benchmarks that write, and for table setup; defaults to `--host`.
+    #[clap(long, value_delimiter = ',', value_name = "HOST")]
+    pub write_host: Option<Vec<Endpoint>>,
+
+    /// TiDB server port, for hosts given without one.
     #[clap(long, default_value_t = DEFAULT_PORT)]
     pub port: u16,
 
@@ -30,50 +335,1367 @@ pub struct DbOpts {
     pub user: String,
 
     /// Password for authentication.
-    #[clap(long, default_value = "")]
-    pub password: String,
+    ///
+    /// Without it no password is sent at all; `--password ""` sends an empty one.
+    #[clap(long, env = "TIDB_PASSWORD", hide_env_values = true)]
+    pub password: Option<String>,
+
+    /// Read the password from this file (trailing newline trimmed).
+    ///
+    /// Takes precedence over `--password` and `TIDB_PASSWORD`.
+    #[clap(long, value_name = "PATH")]
+    pub password_file: Option<PathBuf>,
+
+    /// Prompt for the password (without echo) when none is given and stdin is a terminal.
+    #[cfg(feature = "prompt-password")]
+    #[clap(long)]
+    pub prompt_password: bool,
+
+    /// Authentication plugin.
+    #[clap(long, value_enum, default_value = "auto")]
+    pub auth_plugin: AuthPlugin,
+
+    /// Connect over TLS, verifying the server certificate.
+    #[clap(long)]
+    pub tls: bool,
+
+    /// Client-side `max_allowed_packet` in bytes; the server's value if omitted.
+    ///
+    /// Batch inserts are split into several statements to stay under it.
+    #[clap(
+        long,
+        value_name = "BYTES",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(MIN_PACKET..)
+    )]
+    pub max_packet: Option<usize>,
 
     /// Database name.
     #[clap(long, default_value = "test")]
     pub database: String,
 
+    /// Create `--database` before the run if it does not exist yet.
+    #[clap(long)]
+    pub create_database: bool,
+
+    /// Drop `--database` after the run, if `--create-database` created it.
+    ///
+    /// A database that already existed is never dropped.
+    #[clap(long, requires = "create_database", conflicts_with = "keep_table")]
+    pub drop_database: bool,
+
     /// Benchmark table name.
     #[clap(long, default_value = "bench_table")]
     pub table: String,
 
+    /// Keep the benchmark table after the run instead of dropping it.
+    ///
+    /// A later run with the same data requirements reuses it without reseeding.
+    #[clap(long)]
+    pub keep_table: bool,
+
+    /// Only create and seed the benchmark tables, keep them and exit without running any
+    /// iteration; a later run reuses them with `--keep-table`.
+    #[clap(long, conflicts_with_all = ["no_create", "drop_database"])]
+    pub setup_only: bool,
+
+    /// Use an externally managed table as-is: never create, seed or drop it.
+    ///
+    /// The table must already exist with the benchmark's schema and enough rows.
+    #[clap(long)]
+    pub no_create: bool,
+
+    /// Create the benchmark tables with this TiDB placement policy.
+    ///
+    /// The policy must already exist (`CREATE PLACEMENT POLICY`).
+    #[clap(long, value_name = "NAME", conflicts_with = "no_create")]
+    pub placement_policy: Option<String>,
+
+    /// Create the benchmark tables with this TiDB `AUTO_ID_CACHE`, the number of
+    /// `AUTO_INCREMENT` ids each TiDB node reserves at a time.
+    ///
+    /// Larger caches mean fewer allocation round trips but larger id gaps, e.g. after a
+    /// TiDB restart; `1` makes ids strictly sequential across nodes.
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with = "no_create",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub auto_id_cache: Option<u64>,
+
+    /// Give the tables of select, insert and savepoint a `created_at` column (true/false).
+    ///
+    /// It is filled by the server on every insert and never read back.
+    #[clap(
+        long,
+        value_name = "BOOL",
+        default_value = "true",
+        value_parser = BoolishValueParser::new(),
+        action = clap::ArgAction::Set
+    )]
+    pub timestamps: bool,
+
+    /// Collation of the benchmark tables and of every connection, e.g. `utf8mb4_bin`.
+    ///
+    /// Tables are created with it as their default (so it applies to every string column),
+    /// and each connection runs `SET NAMES <charset> COLLATE <collation>`, the charset being
+    /// the collation's prefix. Without it the server defaults apply.
+    #[clap(long, value_name = "NAME")]
+    pub collation: Option<Collation>,
+
     /// Transaction mode.
+    ///
+    /// `optimistic` and `pessimistic` set `tidb_txn_mode` once per connection, when it is
+    /// opened; every iteration then runs a plain `BEGIN ... COMMIT` in that mode.
     #[clap(long, short = 'm', value_enum, default_value = "auto-commit")]
     pub tx_mode: TxMode,
+
+    /// Set `tidb_enable_async_commit` for the session (on/off); server default if omitted.
+    #[clap(long, value_name = "BOOL", value_parser = BoolishValueParser::new())]
+    pub async_commit: Option<bool>,
+
+    /// Set `tidb_enable_1pc` for the session (on/off); server default if omitted.
+    #[clap(long = "one-pc", value_name = "BOOL", value_parser = BoolishValueParser::new())]
+    pub one_pc: Option<bool>,
+
+    /// Set the session `innodb_lock_wait_timeout`: how long a pessimistic lock waits for
+    /// another transaction before failing with error 1205 (1-3600); server default if omitted.
+    #[clap(
+        long,
+        value_name = "SECONDS",
+        value_parser = clap::value_parser!(u64).range(1..=3600)
+    )]
+    pub lock_wait_timeout: Option<u64>,
+
+    /// Set the session `wait_timeout`, so idle connections survive long pacing gaps.
+    #[clap(long, value_parser = humantime::parse_duration)]
+    pub wait_timeout: Option<Duration>,
+
+    /// Set the session `interactive_timeout`.
+    #[clap(long, value_parser = humantime::parse_duration)]
+    pub interactive_timeout: Option<Duration>,
+
+    /// Set a session variable on every connection, as VAR=VALUE; repeatable.
+    ///
+    /// For variables without a dedicated option, e.g. `--set tidb_opt_agg_push_down=1`.
+    #[clap(long = "set", value_name = "VAR=VALUE")]
+    pub session_vars: Vec<SessionVar>,
+
+    /// Let setup replace an existing table that holds rows but was not created by these
+    /// benchmarks.
+    #[clap(long, conflicts_with = "no_create")]
+    pub force_drop: bool,
+
+    /// Stop the run once a worker has had to reconnect more than this many times.
+    #[clap(long)]
+    pub max_reconnects: Option<u64>,
+
+    /// Ping every worker connection after setup and reconnect the ones that died, so the
+    /// first measured iteration does not pay for it.
+    #[clap(long)]
+    pub ping_before_run: bool,
+
+    /// Skip the connectivity, authentication and privilege checks run before the workers
+    /// start, e.g. where the probes themselves are unwelcome.
+    #[clap(long)]
+    pub skip_preflight: bool,
+
+    /// Share this many connections between all workers instead of one per worker.
+    ///
+    /// Iterations wait for a free connection, and the wait counts as latency. Setting it
+    /// lower than `--concurrency` models a connection-starved client pool.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub connections: Option<u32>,
+
+    /// Reconnects per worker, shared by every clone of these options.
+    #[clap(skip)]
+    reconnects: Arc<Mutex<BTreeMap<u32, u64>>>,
+
+    /// Tables (unquoted) this run created or reused from an earlier run, the only ones it
+    /// drops; shared by every clone of these options.
+    #[clap(skip)]
+    created: Arc<Mutex<BTreeSet<String>>>,
+
+    /// The `--connections` pool, shared by every clone of these options.
+    #[clap(skip)]
+    pool: Arc<ConnPool>,
+
+    /// Whether `--create-database` created the database, shared by every clone of these
+    /// options.
+    #[clap(skip)]
+    database_created: Arc<AtomicBool>,
 }
 
 impl DbOpts {
+    /// Every configured `(host, port)` for `role`, in command-line order.
+    pub fn endpoints(&self, role: Role) -> Vec<(&str, u16)> {
+        let hosts = match role {
+            Role::Read => self.read_host.as_ref(),
+            Role::Write => self.write_host.as_ref(),
+        };
+        hosts
+            .unwrap_or(&self.host)
+            .iter()
+            .map(|e| (e.host.as_str(), e.port.unwrap_or(self.port)))
+            .collect()
+    }
+
+    /// Every distinct `(host, port)` of either role.
+    pub fn all_endpoints(&self) -> Vec<(&str, u16)> {
+        let mut all = self.endpoints(Role::Write);
+        for endpoint in self.endpoints(Role::Read) {
+            if !all.contains(&endpoint) {
+                all.push(endpoint);
+            }
+        }
+        all
+    }
+
+    /// The `role` endpoint worker `worker_id` connects to.
+    pub fn endpoint(&self, worker_id: u32, role: Role) -> (&str, u16) {
+        let endpoints = self.endpoints(role);
+        endpoints[worker_id as usize % endpoints.len()]
+    }
+
+    /// Open a connection to the first write host with the session settings from
+    /// [`DbOpts::session_init`] applied; used for setup and other shared work.
     pub async fn connect(&self) -> Result<Conn> {
-        let opts = OptsBuilder::default()
-            .ip_or_hostname(&self.host)
-            .tcp_port(self.port)
+        self.connect_to(self.endpoint(0, Role::Write)).await
+    }
+
+    /// Open a connection for worker `worker_id` to its round-robin `role` host.
+    ///
+    /// The session settings ([`DbOpts::session_init`]) are applied to every connection as
+    /// it opens, reconnects included, and the critical ones are read back (see
+    /// [`DbOpts::verify_session`]); a connection that does not hold them fails with
+    /// [`Fatal`] rather than benchmark under server defaults.
+    pub async fn connect_worker(&self, worker_id: u32, role: Role) -> Result<Conn> {
+        let endpoint = self.endpoint(worker_id, role);
+        let mut conn = self.connect_to(endpoint).await?;
+        if let Err(e) = self.verify_session(&mut conn).await {
+            return Err(Fatal(format!("worker {worker_id}: {e:#}")).into());
+        }
+        if self.endpoints(role).len() > 1 {
+            eprintln!(
+                "worker {worker_id} connected to {}:{}",
+                endpoint.0, endpoint.1
+            );
+        }
+        Ok(conn)
+    }
+
+    /// Return a worker's connection to a clean state after a failed iteration.
+    ///
+    /// Rolls back whatever transaction the failure left open, so the next iteration starts
+    /// a fresh one. If that fails too, the connection is replaced by a new one to the same
+    /// host, with the session settings applied again (prepared statements are re-prepared
+    /// on first use). Reconnecting is retried with backoff a few times; if it still fails,
+    /// the next failed iteration tries again. Fails with [`Fatal`] once the worker has
+    /// reconnected more than `--max-reconnects` times.
+    pub async fn recover(&self, conn: &mut Conn, worker_id: u32, role: Role) -> Result<()> {
+        if conn.query_drop("ROLLBACK").await.is_ok() {
+            return Ok(());
+        }
+        let mut backoff = RECONNECT_BACKOFF;
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            match self.connect_worker(worker_id, role).await {
+                Ok(fresh) => {
+                    *conn = fresh;
+                    let count = {
+                        let mut reconnects = self.reconnects.lock().unwrap();
+                        let count = reconnects.entry(worker_id).or_default();
+                        *count += 1;
+                        *count
+                    };
+                    if let Some(max) = self.max_reconnects.filter(|&max| count > max) {
+                        return Err(Fatal(format!(
+                            "worker {worker_id} reconnected {count} times, more than \
+                             --max-reconnects {max}"
+                        ))
+                        .into());
+                    }
+                    return Ok(());
+                }
+                Err(e) if e.is::<Fatal>() => return Err(e),
+                Err(e) => eprintln!(
+                    "warning: worker {worker_id} cannot reconnect (attempt {attempt}/{RECONNECT_ATTEMPTS}): {e:#}"
+                ),
+            }
+            if attempt < RECONNECT_ATTEMPTS {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+        Ok(())
+    }
+
+    /// With `--ping-before-run`, make sure a worker's connection is still alive right before
+    /// its first iteration, e.g. after idling past `wait_timeout` while another worker seeded.
+    ///
+    /// A dead connection is replaced as in [`DbOpts::recover`], and counts as a reconnect.
+    pub async fn ping(&self, conn: &mut Conn, worker_id: u32, role: Role) -> Result<()> {
+        if !self.ping_before_run {
+            return Ok(());
+        }
+        if let Err(e) = conn.ping().await {
+            eprintln!("warning: worker {worker_id} connection failed its ping, reconnecting: {e}");
+            self.recover(conn, worker_id, role).await?;
+        }
+        Ok(())
+    }
+
+    /// Turn the connection a worker opened during setup into what it holds for the run: the
+    /// connection itself, or a share of the `--connections` pool.
+    pub async fn session(&self, conn: Conn) -> Session {
+        Session::new(conn, &self.pool, self.connections.map(|n| n as usize)).await
+    }
+
+    /// Print how often each worker reconnected, if any did. Call once, after the run.
+    pub fn print_reconnects(&self) {
+        let reconnects = self.reconnects.lock().unwrap();
+        if reconnects.is_empty() {
+            return;
+        }
+        let total: u64 = reconnects.values().sum();
+        let per_worker = reconnects
+            .iter()
+            .map(|(worker, count)| format!("worker {worker}: {count}"))
+            .collect::<Vec<_>>();
+        eprintln!("{total} reconnects ({})", per_worker.join(", "));
+    }
+
+    /// With `--create-database`, create the database unless it exists, connecting to the
+    /// first write host without a default database. Call once, before any other connection
+    /// is opened.
+    pub async fn ensure_database(&self) -> Result<()> {
+        if !self.create_database {
+            return Ok(());
+        }
+        let (host, port) = self.endpoint(0, Role::Write);
+        let opts = self.opts_builder(host, port)?.db_name(None::<String>);
+        let mut conn = self.open(opts).await?;
+        let existing: Option<String> = conn
+            .exec_first(
+                "SELECT SCHEMA_NAME FROM INFORMATION_SCHEMA.SCHEMATA WHERE SCHEMA_NAME = ?",
+                (&self.database,),
+            )
+            .await?;
+        if existing.is_none() {
+            conn.query_drop(format!(
+                "CREATE DATABASE IF NOT EXISTS {}",
+                self.quoted_db()
+            ))
+            .await
+            .with_context(|| format!("cannot create database {}", self.quoted_db()))?;
+            self.database_created.store(true, Ordering::Relaxed);
+            eprintln!("created database {}", self.quoted_db());
+        }
+        conn.disconnect().await?;
+        Ok(())
+    }
+
+    /// With `--drop-database`, drop the database if `--create-database` created it. Failures
+    /// are reported but not returned, since the run itself is over. Only the first call
+    /// does anything.
+    pub async fn drop_created_database(&self) {
+        if !self.drop_database || !self.database_created.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let drop = async {
+            let (host, port) = self.endpoint(0, Role::Write);
+            let opts = self.opts_builder(host, port)?.db_name(None::<String>);
+            let mut conn = self.open(opts).await?;
+            conn.query_drop(format!("DROP DATABASE IF EXISTS {}", self.quoted_db()))
+                .await?;
+            anyhow::Ok(())
+        };
+        match drop.await {
+            Ok(()) => eprintln!("dropped database {}", self.quoted_db()),
+            Err(e) => eprintln!("warning: cannot drop database {}: {e:#}", self.quoted_db()),
+        }
+    }
+
+    async fn connect_to(&self, (host, port): (&str, u16)) -> Result<Conn> {
+        let opts = self.opts_builder(host, port)?.init(self.session_init());
+        self.open(opts).await
+    }
+
+    async fn open(&self, opts: OptsBuilder) -> Result<Conn> {
+        match Conn::new(Opts::from(opts.clone())).await {
+            Ok(conn) => Ok(conn),
+            Err(mysql_async::Error::Server(e)) if e.code == UNKNOWN_DATABASE => {
+                // Without a default database the login itself may still work.
+                let databases = match Conn::new(Opts::from(opts.db_name(None::<String>))).await {
+                    Ok(mut conn) => list_names(&mut conn, "SHOW DATABASES").await,
+                    Err(_) => None,
+                };
+                Err(
+                    anyhow::Error::new(mysql_async::Error::Server(e)).context(format!(
+                        "database {} does not exist; create it first or pick another with \
+                         --database{}",
+                        self.quoted_db(),
+                        databases.map_or(String::new(), |names| format!(" ({names})"))
+                    )),
+                )
+            }
+            Err(mysql_async::Error::Server(e)) if e.code == ACCESS_DENIED => {
+                let sent = match self.password()? {
+                    None => "no password was sent",
+                    Some(password) if password.is_empty() => "an empty password was sent",
+                    Some(_) => "a password was sent",
+                };
+                Err(
+                    anyhow::Error::new(mysql_async::Error::Server(e)).context(format!(
+                        "access denied for user {}; {sent}",
+                        quote_str(&self.user)
+                    )),
+                )
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Connection options without any session settings.
+    fn opts_builder(&self, host: &str, port: u16) -> Result<OptsBuilder> {
+        let cleartext = self.auth_plugin == AuthPlugin::MysqlClearPassword;
+        if cleartext && !self.tls {
+            bail!("--auth-plugin mysql_clear_password sends the password as-is and requires --tls");
+        }
+        Ok(OptsBuilder::default()
+            .ip_or_hostname(host)
+            .tcp_port(port)
             .user(Some(&self.user))
-            .pass(Some(&self.password))
-            .db_name(Some(&self.database));
-        Ok(Conn::new(Opts::from(opts)).await?)
+            .pass(self.password()?)
+            .db_name(Some(&self.database))
+            .enable_cleartext_plugin(cleartext)
+            .max_allowed_packet(self.max_packet)
+            .ssl_opts(self.tls.then(SslOpts::default)))
+    }
+
+    /// Ask for the password on the terminal if `--prompt-password` is set and no password
+    /// was given any other way. Call once, before connections are opened.
+    pub fn prompt_for_password(&mut self) -> Result<()> {
+        #[cfg(feature = "prompt-password")]
+        {
+            use std::io::IsTerminal;
+
+            if self.prompt_password
+                && self.password.is_none()
+                && self.password_file.is_none()
+                && std::io::stdin().is_terminal()
+            {
+                let (host, port) = self.endpoint(0, Role::Write);
+                let prompt = format!("Password for {}@{host}:{port}: ", self.user);
+                self.password = Some(rpassword::prompt_password(prompt)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Password from `--password-file` if given, else `--password` / `TIDB_PASSWORD`.
+    /// `None` when no password was given at all, so none is sent.
+    fn password(&self) -> Result<Option<String>> {
+        let Some(path) = &self.password_file else {
+            return Ok(self.password.clone());
+        };
+        let password = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read password file {}", path.display()))?;
+        Ok(Some(password.trim_end_matches(['\r', '\n']).to_owned()))
+    }
+
+    /// Open a connection to `host:port` without applying the session settings, for checks
+    /// that must work against servers that reject them.
+    pub async fn connect_plain(&self, host: &str, port: u16) -> Result<Conn> {
+        self.open(self.opts_builder(host, port)?).await
+    }
+
+    /// Statements setting the connection collation, the TiDB transaction mode and commit
+    /// options for a session.
+    ///
+    /// They run as part of establishing every connection (and again whenever one is reset
+    /// or re-established), so the transaction mode is never set inside a timed iteration.
+    pub fn session_init(&self) -> Vec<String> {
+        let mut init = Vec::new();
+        if let Some(collation) = &self.collation {
+            init.push(format!(
+                "SET NAMES {} COLLATE {collation}",
+                collation.charset()
+            ));
+        }
+        match self.tx_mode {
+            TxMode::AutoCommit => {}
+            TxMode::Optimistic => init.push("SET SESSION tidb_txn_mode = 'optimistic'".into()),
+            TxMode::Pessimistic => init.push("SET SESSION tidb_txn_mode = 'pessimistic'".into()),
+        }
+        for (var, value) in [
+            ("tidb_enable_async_commit", self.async_commit),
+            ("tidb_enable_1pc", self.one_pc),
+        ] {
+            if let Some(on) = value {
+                init.push(format!("SET SESSION {var} = {}", on_off(on)));
+            }
+        }
+        if let Some(seconds) = self.lock_wait_timeout {
+            init.push(format!("SET SESSION innodb_lock_wait_timeout = {seconds}"));
+        }
+        for (var, value) in [
+            ("wait_timeout", self.wait_timeout),
+            ("interactive_timeout", self.interactive_timeout),
+        ] {
+            if let Some(timeout) = value {
+                init.push(format!("SET SESSION {var} = {}", timeout.as_secs().max(1)));
+            }
+        }
+        init.extend(self.session_vars.iter().map(SessionVar::statement));
+        init
     }
 
-    /// Set TiDB transaction mode for the session (once per connection).
-    pub async fn init_tx_mode(&self, conn: &mut Conn) -> Result<()> {
+    /// Read back the session variables the run's results depend on: the transaction mode,
+    /// `--async-commit`, `--one-pc`, `--lock-wait-timeout` and `--collation`.
+    ///
+    /// `--set` variables are not compared, since the server may report a value in another
+    /// form than it was given in (`1` for `ON`, a normalized size or list).
+    pub async fn verify_session(&self, conn: &mut Conn) -> Result<()> {
+        let mut expected = Vec::new();
         match self.tx_mode {
             TxMode::AutoCommit => {}
-            TxMode::Optimistic => {
-                conn.query_drop("SET SESSION tidb_txn_mode = 'optimistic'")
-                    .await?;
+            TxMode::Optimistic => expected.push(("tidb_txn_mode", "optimistic".to_owned())),
+            TxMode::Pessimistic => expected.push(("tidb_txn_mode", "pessimistic".to_owned())),
+        }
+        for (var, value) in [
+            ("tidb_enable_async_commit", self.async_commit),
+            ("tidb_enable_1pc", self.one_pc),
+        ] {
+            if let Some(on) = value {
+                expected.push((var, on_off(on).to_owned()));
+            }
+        }
+        if let Some(seconds) = self.lock_wait_timeout {
+            expected.push(("innodb_lock_wait_timeout", seconds.to_string()));
+        }
+        if let Some(collation) = &self.collation {
+            expected.push(("collation_connection", collation.to_string()));
+        }
+        if expected.is_empty() {
+            return Ok(());
+        }
+        let columns = expected
+            .iter()
+            .map(|(var, _)| format!("@@SESSION.{var}"))
+            .collect::<Vec<_>>();
+        let row: Row = conn
+            .query_first(format!("SELECT {}", columns.join(", ")))
+            .await?
+            .context("reading back the session settings returned no row")?;
+        for (i, (var, want)) in expected.iter().enumerate() {
+            let actual: String = row.get_opt(i).and_then(Result::ok).unwrap_or_default();
+            // Boolean variables read back as 1 and 0.
+            let normalized = match actual.as_str() {
+                "1" => "ON",
+                "0" => "OFF",
+                other => other,
+            };
+            if !normalized.eq_ignore_ascii_case(want) {
+                bail!(
+                    "session variable {var} is '{actual}' instead of '{want}' on this connection"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop and recreate `table` (unquoted) with `schema`, or with `--no-create` just check
+    /// that it exists.
+    pub async fn create_table(&self, conn: &mut Conn, table: &str, schema: &str) -> Result<()> {
+        self.create_table_with(conn, table, schema, "").await
+    }
+
+    /// [`DbOpts::create_table`] with table options (e.g. `SHARD_ROW_ID_BITS = 4`) after the
+    /// column definitions.
+    pub async fn create_table_with(
+        &self,
+        conn: &mut Conn,
+        table: &str,
+        schema: &str,
+        options: &str,
+    ) -> Result<()> {
+        if self.no_create {
+            return self.require_table(conn, table, schema).await;
+        }
+        let quoted = quote_ident(table);
+        self.check_droppable(conn, table).await?;
+        conn.query_drop(format!("DROP TABLE IF EXISTS {quoted}"))
+            .await?;
+        let mut options = options.to_owned();
+        if let Some(cache) = self.auto_id_cache {
+            // A TiDB-only option in TiDB's feature comment, which MySQL ignores.
+            options.push_str(&format!(" /*T![auto_id_cache] AUTO_ID_CACHE = {cache} */"));
+        }
+        if let Some(collation) = &self.collation {
+            options.push_str(&format!(
+                " DEFAULT CHARSET = {} COLLATE = {collation}",
+                collation.charset()
+            ));
+        }
+        let create = format!("CREATE TABLE {quoted} ({schema}) {options} COMMENT = '{TABLE_TAG}'");
+        match &self.placement_policy {
+            None => conn.query_drop(create).await?,
+            Some(policy) => {
+                let placed = format!("{create} PLACEMENT POLICY = {}", quote_ident(policy));
+                match conn.query_drop(placed).await {
+                    Ok(()) => {}
+                    Err(mysql_async::Error::Server(e)) if e.code == PARSE_ERROR => {
+                        eprintln!(
+                            "warning: server does not support placement policies; \
+                             creating {quoted} without --placement-policy"
+                        );
+                        conn.query_drop(create).await?;
+                    }
+                    Err(e) => {
+                        return Err(anyhow::Error::new(e).context(format!(
+                            "cannot create {quoted} with placement policy {}; \
+                             it must exist beforehand (CREATE PLACEMENT POLICY)",
+                            quote_ident(policy)
+                        )))
+                    }
+                }
             }
-            TxMode::Pessimistic => {
-                conn.query_drop("SET SESSION tidb_txn_mode = 'pessimistic'")
-                    .await?;
+        }
+        self.mark_created(table);
+        Ok(())
+    }
+
+    /// Fail unless dropping `table` (unquoted) loses nothing the benchmarks did not create:
+    /// it must be missing, empty or tagged with [`TABLE_TAG`], or `--force-drop` given.
+    async fn check_droppable(&self, conn: &mut Conn, table: &str) -> Result<()> {
+        let comment: Option<String> = conn
+            .exec_first(
+                "SELECT TABLE_COMMENT FROM information_schema.TABLES \
+                 WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ?",
+                (table,),
+            )
+            .await?;
+        if comment.is_none_or(|comment| comment.starts_with(TABLE_TAG)) {
+            return Ok(());
+        }
+        let quoted = quote_ident(table);
+        let rows: Option<u8> = conn
+            .query_first(format!("SELECT 1 FROM {quoted} LIMIT 1"))
+            .await?;
+        if rows.is_none() {
+            return Ok(());
+        }
+        let qualified = format!("{}.{quoted}", self.quoted_db());
+        if !self.force_drop {
+            bail!(
+                "refusing to drop {qualified}: it holds rows and was not created by tidb-bench; \
+                 check --database and --table, or pass --force-drop to replace it"
+            );
+        }
+        eprintln!("warning: --force-drop: dropping {qualified}, which tidb-bench did not create");
+        Ok(())
+    }
+
+    /// Record that this run created `table` (unquoted), or took it over from an earlier run.
+    pub fn mark_created(&self, table: &str) {
+        self.created.lock().unwrap().insert(table.to_owned());
+    }
+
+    /// Run `setup`; if it fails, drop the tables this run created so far before returning its
+    /// error, so a half-built table never outlives the run.
+    ///
+    /// Tables that cannot be dropped, or are kept with `--keep-table`, are named on stderr.
+    /// The original error is returned either way.
+    pub async fn undo_on_error<F>(&self, setup: F) -> Result<()>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        let Err(e) = setup.await else {
+            return Ok(());
+        };
+        let created: Vec<String> = std::mem::take(&mut *self.created.lock().unwrap())
+            .into_iter()
+            .collect();
+        if created.is_empty() {
+            return Err(e);
+        }
+        let db = self.quoted_db();
+        if !self.drops_table() {
+            for table in &created {
+                eprintln!(
+                    "setup failed; keeping {db}.{} (--keep-table)",
+                    quote_ident(table)
+                );
+            }
+            return Err(e);
+        }
+        let mut conn = match self.connect().await {
+            Ok(conn) => Some(conn),
+            Err(connect) => {
+                eprintln!("warning: cannot connect to clean up after failed setup: {connect:#}");
+                None
+            }
+        };
+        for table in &created {
+            let dropped = match &mut conn {
+                Some(conn) => conn
+                    .query_drop(format!("DROP TABLE IF EXISTS {}", quote_ident(table)))
+                    .await
+                    .map_err(anyhow::Error::from),
+                None => Err(anyhow::anyhow!("no connection")),
+            };
+            if let Err(drop) = dropped {
+                eprintln!(
+                    "warning: setup failed and left {db}.{} behind ({drop:#}); drop it manually",
+                    quote_ident(table)
+                );
             }
         }
+        Err(e)
+    }
+
+    /// Drop `table` (unquoted) at the end of the run, unless tables are kept or this run did
+    /// not create it.
+    pub async fn drop_table(&self, conn: &mut Conn, table: &str) -> Result<()> {
+        if !self.drops_table() || !self.created.lock().unwrap().contains(table) {
+            return Ok(());
+        }
+        conn.query_drop(format!("DROP TABLE IF EXISTS {}", quote_ident(table)))
+            .await?;
         Ok(())
     }
 
+    /// Fail unless `table` (unquoted) exists in the benchmark database with the columns of
+    /// `schema`, as the benchmark would have created it.
+    ///
+    /// Only the column names and data types are compared. Extra columns, indexes and columns
+    /// with a `DEFAULT` (filled by the server, never written or read) are not checked.
+    pub async fn require_table(&self, conn: &mut Conn, table: &str, schema: &str) -> Result<()> {
+        let found: BTreeMap<String, String> = conn
+            .exec_map(
+                "SELECT COLUMN_NAME, DATA_TYPE FROM information_schema.COLUMNS \
+                 WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ?",
+                (table,),
+                |(name, data_type): (String, String)| {
+                    (name.to_lowercase(), data_type.to_lowercase())
+                },
+            )
+            .await?
+            .into_iter()
+            .collect();
+        if found.is_empty() {
+            let tables = list_names(conn, "SHOW TABLES").await;
+            bail!(
+                "table {}.{} does not exist; --no-create expects it to be provisioned already, \
+                 check --table and --database or drop --no-create to let the benchmark create \
+                 it{}",
+                self.quoted_db(),
+                quote_ident(table),
+                tables.map_or(String::new(), |names| format!(" ({names})"))
+            );
+        }
+        let mismatches: Vec<String> = schema_columns(schema)
+            .into_iter()
+            .filter_map(|(name, expected)| match found.get(&name) {
+                None => Some(format!("missing column {} {expected}", quote_ident(&name))),
+                Some(actual) if *actual != expected => Some(format!(
+                    "column {} is {actual}, expected {expected}",
+                    quote_ident(&name)
+                )),
+                Some(_) => None,
+            })
+            .collect();
+        if !mismatches.is_empty() {
+            bail!(
+                "table {}.{} does not match the benchmark's schema: {}",
+                self.quoted_db(),
+                quote_ident(table),
+                mismatches.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// The `created_at` column definition to append to a schema, if `--timestamps` is on.
+    pub fn timestamp_column(&self) -> &'static str {
+        if self.timestamps {
+            ",\n created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP"
+        } else {
+            ""
+        }
+    }
+
+    /// Whether teardown should drop the benchmark tables.
+    pub fn drops_table(&self) -> bool {
+        !self.keep_table && !self.no_create
+    }
+
+    /// Effective settings, for the run header.
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        let servers = |role| {
+            let list = self
+                .endpoints(role)
+                .iter()
+                .map(|(host, port)| format!("{host}:{port}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{list}{}", if self.tls { " (tls)" } else { "" })
+        };
+        let mut settings = if self.read_host.is_some() || self.write_host.is_some() {
+            vec![
+                ("read server", servers(Role::Read)),
+                ("write server", servers(Role::Write)),
+            ]
+        } else {
+            vec![("server", servers(Role::Write))]
+        };
+        settings.extend([
+            (
+                "database",
+                match (self.create_database, self.drop_database) {
+                    (false, _) => self.database.clone(),
+                    (true, false) => format!("{} (created if missing)", self.database),
+                    (true, true) => format!("{} (created if missing, then dropped)", self.database),
+                },
+            ),
+            (
+                "table",
+                if self.no_create {
+                    format!("{} (externally managed)", self.table)
+                } else {
+                    self.table.clone()
+                },
+            ),
+            (
+                "placement policy",
+                self.placement_policy.as_deref().unwrap_or("none").into(),
+            ),
+            (
+                "auto id cache",
+                self.auto_id_cache
+                    .map_or("default".into(), |cache| cache.to_string()),
+            ),
+            ("timestamps", self.timestamps.to_string()),
+            (
+                "collation",
+                self.collation
+                    .as_ref()
+                    .map_or("server default".into(), Collation::to_string),
+            ),
+            ("tx mode", format!("{:?}", self.tx_mode)),
+            (
+                "async commit",
+                self.async_commit.map_or("default", on_off).into(),
+            ),
+            ("1pc", self.one_pc.map_or("default", on_off).into()),
+            (
+                "lock wait timeout",
+                self.lock_wait_timeout
+                    .map_or("default".into(), |seconds| format!("{seconds}s")),
+            ),
+        ]);
+        if let Some(connections) = self.connections {
+            settings.push(("connections", format!("{connections} (shared pool)")));
+        }
+        if !self.session_vars.is_empty() {
+            let vars = self.session_vars.iter().map(SessionVar::to_string);
+            settings.push(("session vars", vars.collect::<Vec<_>>().join(", ")));
+        }
+        settings
+    }
+
     pub fn quoted_table(&self) -> String {
-        format!("`{}`", self.table)
+        quote_ident(&self.table)
+    }
+
+    pub fn quoted_db(&self) -> String {
+        quote_ident(&self.database)
+    }
+
+    /// Name of an auxiliary table derived from `--table`, e.g. `bench_table_inner`.
+    pub fn aux_table(&self, suffix: &str) -> String {
+        format!("{}_{suffix}", self.table)
+    }
+
+    pub fn quoted_aux_table(&self, suffix: &str) -> String {
+        quote_ident(&self.aux_table(suffix))
+    }
+}
+
+/// Bits of a worker-scoped key reserved for the worker's own sequence number.
+pub const WORKER_SEQ_BITS: u32 = 40;
+
+/// Key `seq` of worker `worker_id`, unique across workers.
+///
+/// The worker id occupies the bits above [`WORKER_SEQ_BITS`], so each worker owns the
+/// disjoint range `worker_id << 40 ..` and can generate 2^40 keys. Asking for a key past
+/// that range, which would belong to the next worker, fails.
+pub fn worker_key(worker_id: u32, seq: u64) -> Result<u64> {
+    ensure!(
+        seq < 1 << WORKER_SEQ_BITS,
+        "worker {worker_id} has used up its 2^{WORKER_SEQ_BITS} keys; key {seq} would fall \
+         into the range of worker {}",
+        u64::from(worker_id) + 1
+    );
+    ensure!(
+        u64::from(worker_id) < 1 << (64 - WORKER_SEQ_BITS),
+        "worker {worker_id} is past the last key range"
+    );
+    Ok(u64::from(worker_id) << WORKER_SEQ_BITS | seq)
+}
+
+/// Quote an identifier with backticks, doubling any embedded backtick.
+///
+/// Reserved words (`order`) and special characters (`my-table`) are safe once quoted.
+pub fn quote_ident(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+/// Quote a string literal, escaping quotes and backslashes.
+pub fn quote_str(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "''"))
+}
+
+/// Names and lowercase data types of the columns `schema` defines that a benchmark touches:
+/// index definitions and columns with a `DEFAULT` are skipped.
+fn schema_columns(schema: &str) -> Vec<(String, String)> {
+    let mut definitions = vec![String::new()];
+    let mut depth = 0;
+    for c in schema.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                definitions.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        definitions.last_mut().unwrap().push(c);
+    }
+    definitions
+        .iter()
+        .filter_map(|definition| {
+            let mut words = definition.split_whitespace();
+            let name = words.next()?.to_lowercase();
+            let data_type = words.next()?.to_lowercase();
+            let index =
+                ["key", "index", "primary", "unique", "constraint"].contains(&name.as_str());
+            let defaulted = definition.to_uppercase().contains(" DEFAULT ");
+            let data_type = data_type.split('(').next().unwrap_or_default().to_owned();
+            (!index && !defaulted).then_some((name, data_type))
+        })
+        .collect()
+}
+
+/// The names `stmt` (`SHOW DATABASES`, `SHOW TABLES`) returns, for an error message; `None`
+/// if the user may not list them.
+async fn list_names(conn: &mut Conn, stmt: &str) -> Option<String> {
+    let names: Vec<String> = conn.query(stmt).await.ok()?;
+    if names.is_empty() {
+        return Some("there are none".into());
+    }
+    let mut listed = names
+        .iter()
+        .take(LISTED_NAMES)
+        .map(|name| quote_ident(name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if names.len() > LISTED_NAMES {
+        listed.push_str(&format!(" and {} more", names.len() - LISTED_NAMES));
+    }
+    Some(format!("available: {listed}"))
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+/// Set a named savepoint in the current transaction.
+pub async fn savepoint<Q: Queryable>(conn: &mut Q, name: &str) -> Result<()> {
+    conn.query_drop(format!("SAVEPOINT {}", quote_ident(name)))
+        .await?;
+    Ok(())
+}
+
+/// Undo everything done since the named savepoint, keeping the transaction open.
+pub async fn rollback_to_savepoint<Q: Queryable>(conn: &mut Q, name: &str) -> Result<()> {
+    conn.query_drop(format!("ROLLBACK TO SAVEPOINT {}", quote_ident(name)))
+        .await?;
+    Ok(())
+}
+
+/// Drop the named savepoint, keeping the work done since it.
+pub async fn release_savepoint<Q: Queryable>(conn: &mut Q, name: &str) -> Result<()> {
+    conn.query_drop(format!("RELEASE SAVEPOINT {}", quote_ident(name)))
+        .await?;
+    Ok(())
+}
+
+/// Print the effective run settings to stderr ahead of the benchmark output.
+pub fn print_run_header(bench: &str, settings: &[(&str, String)]) {
+    let width = settings.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+    eprintln!("{bench}");
+    for (key, value) in settings {
+        eprintln!("  {key:<width$}  {value}");
+    }
+}
+
+/// Arguments left out of the configuration fingerprint: credentials, and where and how
+/// results are written, none of which changes the workload.
+const UNFINGERPRINTED: [&str; 17] = [
+    "password",
+    "prompt_password",
+    "skip_preflight",
+    "setup_only",
+    "fingerprint",
+    "hdr_out",
+    "hdr_digits",
+    "hdr_max",
+    "results_json",
+    "latency_log",
+    "latency_log_sample",
+    "collect_slow_queries",
+    "metrics_listen",
+    "output",
+    "output_file",
+    "quiet",
+    "help",
+];
+
+/// Hash of a run's effective configuration: the benchmark, the crate version and the value
+/// of every argument, defaults included. Two runs with the same fingerprint ran the same
+/// workload against the same endpoints.
+pub struct ConfigFingerprint {
+    bench: String,
+    /// Argument id and value(s), sorted by id.
+    config: BTreeMap<String, String>,
+}
+
+impl ConfigFingerprint {
+    fn new(bench: &str, command: &clap::Command, matches: &clap::ArgMatches) -> Self {
+        let config = command
+            .get_arguments()
+            .map(|arg| arg.get_id().as_str())
+            .filter(|id| !UNFINGERPRINTED.contains(id))
+            .filter_map(|id| {
+                let values = matches.try_get_raw(id).ok()??;
+                let values: Vec<_> = values.map(|v| v.to_string_lossy()).collect();
+                Some((id.to_owned(), values.join(",")))
+            })
+            .collect();
+        Self {
+            bench: bench.to_owned(),
+            config,
+        }
+    }
+
+    /// The hash, as 16 hex digits.
+    pub fn hash(&self) -> String {
+        self.hash_of_version(env!("CARGO_PKG_VERSION"))
+    }
+
+    /// The hash over the bench, `version` and one `id=value` line per argument.
+    fn hash_of_version(&self, version: &str) -> String {
+        use std::fmt::Write as _;
+
+        let mut input = String::new();
+        writeln!(input, "{}", self.bench).unwrap();
+        writeln!(input, "{version}").unwrap();
+        for (id, value) in &self.config {
+            writeln!(input, "{id}={value}").unwrap();
+        }
+        format!("{:016x}", stable_hash(input.as_bytes()))
+    }
+
+    /// The value of argument `id` as fingerprinted, if it has one.
+    pub(crate) fn value(&self, id: &str) -> Option<&str> {
+        self.config.get(id).map(String::as_str)
+    }
+
+    /// Print the fingerprint and everything it covers as one JSON object on stdout.
+    pub fn print_json(&self) {
+        println!(
+            "{}",
+            serde_json::to_string(self).expect("a fingerprint serializes")
+        );
+    }
+}
+
+/// The `bench`, `version`, `fingerprint` and `config` members of a JSON object.
+impl serde::Serialize for ConfigFingerprint {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut object = serializer.serialize_struct("ConfigFingerprint", 4)?;
+        object.serialize_field("bench", &self.bench)?;
+        object.serialize_field("version", env!("CARGO_PKG_VERSION"))?;
+        object.serialize_field("fingerprint", &self.hash())?;
+        object.serialize_field("config", &self.config)?;
+        object.end()
+    }
+}
+
+/// Command line of a `bench-*` binary: the suite's options and rlt's.
+#[derive(clap::Parser)]
+struct Cli<O: clap::Args> {
+    #[command(flatten)]
+    opts: O,
+
+    #[command(flatten)]
+    bench_opts: rlt::cli::BenchCli,
+}
+
+/// Parse the command line like [`clap::Parser::parse`], also fingerprinting the resulting
+/// configuration of `B`.
+fn parse_cli<B: Benchmark>() -> (Cli<B::Opts>, ConfigFingerprint) {
+    use clap::{CommandFactory, FromArgMatches};
+
+    // The help text is the suite's, not that of the wrapper.
+    let opts = B::Opts::command();
+    let mut command = Cli::<B::Opts>::command();
+    if let Some(about) = opts.get_about() {
+        command = command.about(about.clone());
+    }
+    if let Some(about) = opts.get_long_about() {
+        command = command.long_about(about.clone());
+    }
+    let matches = command.clone().get_matches();
+    let cli =
+        Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.format(&mut command.clone()).exit());
+    (cli, ConfigFingerprint::new(B::NAME, &command, &matches))
+}
+
+/// The `main` of a `bench-*` binary: parse the command line, prepare the database, run `B`
+/// and report, dropping a database the run created however it ended.
+pub async fn run_main<B: Benchmark>() -> Result<()>
+where
+    B::WorkerState: Send + Sync + 'static,
+{
+    let (mut cli, fingerprint) = parse_cli::<B>();
+    if cli.opts.run().fingerprint {
+        fingerprint.print_json();
+        return Ok(());
+    }
+    cli.opts.validate()?;
+    cli.opts.db_mut().prompt_for_password()?;
+    let mut settings = cli.opts.settings();
+    settings.push(("fingerprint", fingerprint.hash()));
+    print_run_header(B::NAME, &settings);
+    cli.opts.db().ensure_database().await?;
+    let db = cli.opts.db().clone();
+    let result = run_bench::<B>(cli, &fingerprint).await;
+    // However the run ended, a database it created goes with it.
+    db.drop_created_database().await;
+    result
+}
+
+async fn run_bench<B: Benchmark>(cli: Cli<B::Opts>, fingerprint: &ConfigFingerprint) -> Result<()>
+where
+    B::WorkerState: Send + Sync + 'static,
+{
+    let db = cli.opts.db();
+    preflight::preflight(db, B::PRIVILEGES).await?;
+    let bench = B::new(&cli.opts, cli.bench_opts.concurrency.get());
+    if db.setup_only {
+        return lifecycle::setup_only(bench).await;
+    }
+    let run = bench
+        .harness()
+        .run(fingerprint, rlt::cli::run(cli.bench_opts, bench.clone()))
+        .await;
+    let reported = bench.report();
+    let interrupted = match run {
+        Ok(interrupted) => interrupted,
+        // Aborted, e.g. by an error limit: the workers skipped their teardown.
+        Err(e) => {
+            interrupt::clean_up_abandoned(db, &bench.tables()).await;
+            return Err(e);
+        }
+    };
+    reported?;
+    if interrupted {
+        interrupt::exit_interrupted(db, &bench.tables()).await;
+    }
+    Ok(())
+}
+
+/// 64-bit FNV-1a hash, stable across builds and releases (unlike `DefaultHasher`).
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Parse a fraction in `(0, 1]`, for use as a clap value parser.
+pub fn parse_fraction(s: &str) -> std::result::Result<f64, String> {
+    let v: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if v > 0.0 && v <= 1.0 {
+        Ok(v)
+    } else {
+        Err(format!("{v} is not in (0, 1]"))
+    }
+}
+
+/// Parse a percentile in `(0, 100]`, for use as a clap value parser.
+pub fn parse_percentile(s: &str) -> std::result::Result<f64, String> {
+    let v: f64 = s.trim().parse().map_err(|e| format!("{e}"))?;
+    if v > 0.0 && v <= 100.0 {
+        Ok(v)
+    } else {
+        Err(format!("{v} is not in (0, 100]"))
+    }
+}
+
+/// Parse a probability in `[0, 1]`, for use as a clap value parser.
+pub fn parse_probability(s: &str) -> std::result::Result<f64, String> {
+    let v: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if (0.0..=1.0).contains(&v) {
+        Ok(v)
+    } else {
+        Err(format!("{v} is not in [0, 1]"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        db: DbOpts,
+    }
+
+    fn db_opts(args: &[&str]) -> DbOpts {
+        Cli::parse_from(std::iter::once("test").chain(args.iter().copied())).db
+    }
+
+    #[test]
+    fn quote_ident_handles_reserved_words_and_special_characters() {
+        assert_eq!(quote_ident("order"), "`order`");
+        assert_eq!(quote_ident("my-table"), "`my-table`");
+        assert_eq!(quote_ident("odd`name"), "`odd``name`");
+    }
+
+    #[test]
+    fn table_and_database_names_are_quoted() {
+        let db = db_opts(&["--table", "order", "--database", "my-db"]);
+        assert_eq!(db.quoted_table(), "`order`");
+        assert_eq!(db.quoted_db(), "`my-db`");
+        assert_eq!(db.quoted_aux_table("inner"), "`order_inner`");
+
+        let db = db_opts(&["--table", "my-table"]);
+        assert_eq!(
+            format!("DROP TABLE IF EXISTS {}", db.quoted_table()),
+            "DROP TABLE IF EXISTS `my-table`"
+        );
+    }
+
+    /// The password `opts_builder` would send for `args`.
+    fn sent_password(args: &[&str]) -> Option<String> {
+        let builder = db_opts(args).opts_builder("localhost", 4000).unwrap();
+        Opts::from(builder).pass().map(str::to_owned)
+    }
+
+    #[test]
+    fn no_password_is_sent_unless_one_is_given() {
+        assert_eq!(sent_password(&[]), None);
+        assert_eq!(sent_password(&["--password", ""]), Some(String::new()));
+        assert_eq!(
+            sent_password(&["--password", "secret"]),
+            Some("secret".to_owned())
+        );
+    }
+
+    #[test]
+    fn password_file_wins_without_its_trailing_newline() {
+        let path = std::env::temp_dir().join(format!("tidb-bench-pw-{}", std::process::id()));
+        std::fs::write(&path, "from-file\n").unwrap();
+        let password = sent_password(&[
+            "--password",
+            "from-flag",
+            "--password-file",
+            path.to_str().unwrap(),
+        ]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(password, Some("from-file".to_owned()));
+    }
+
+    #[test]
+    fn worker_keys_stay_in_their_worker_range() {
+        let last = (1 << WORKER_SEQ_BITS) - 1;
+        assert_eq!(worker_key(0, 0).unwrap(), 0);
+        assert_eq!(worker_key(0, last).unwrap(), last);
+        assert_eq!(worker_key(1, 0).unwrap(), last + 1);
+        assert!(worker_key(0, last + 1).is_err());
+        assert!(worker_key(3, u64::MAX).is_err());
+        assert!(worker_key(u32::MAX, 0).is_err());
+    }
+
+    fn set(arg: &str) -> std::result::Result<String, String> {
+        arg.parse::<SessionVar>().map(|var| var.statement())
+    }
+
+    #[test]
+    fn fingerprint_hash_is_pinned() {
+        let fingerprint = ConfigFingerprint {
+            bench: "bench-select".into(),
+            config: BTreeMap::from([
+                ("table".into(), "t,u".into()),
+                ("select_count".into(), "100".into()),
+            ]),
+        };
+        // FNV-1a of "bench-select\n1.2.3\nselect_count=100\ntable=t,u\n".
+        assert_eq!(fingerprint.hash_of_version("1.2.3"), "883401a934e4e6f8");
+    }
+
+    #[test]
+    fn session_vars_become_set_statements() {
+        assert_eq!(
+            set(" TiDB_Mem_Quota_Query = 1073741824 ").unwrap(),
+            "SET SESSION tidb_mem_quota_query = 1073741824"
+        );
+        assert_eq!(
+            set("tidb_enable_1pc=ON").unwrap(),
+            "SET SESSION tidb_enable_1pc = ON"
+        );
+        assert_eq!(
+            set("sql_mode=DEFAULT").unwrap(),
+            "SET SESSION sql_mode = DEFAULT"
+        );
+        assert_eq!(
+            set("tidb_isolation_read_engines=tikv,tidb").unwrap(),
+            "SET SESSION tidb_isolation_read_engines = 'tikv,tidb'"
+        );
+        // Quotes and backslashes cannot end the string early.
+        assert_eq!(
+            set(r"time_zone=it's\").unwrap(),
+            r"SET SESSION time_zone = 'it''s\\'"
+        );
+        // Only the first `=` separates the name.
+        assert_eq!(set("sql_mode=a=b").unwrap(), "SET SESSION sql_mode = 'a=b'");
+    }
+
+    #[test]
+    fn malformed_and_reserved_session_vars_are_rejected() {
+        let err = set("tidb_mem_quota_query").unwrap_err();
+        assert!(err.contains("expected VAR=VALUE"), "{err}");
+        assert!(set("tidb_mem_quota_query=")
+            .unwrap_err()
+            .contains("missing value"));
+        for name in ["", "1pc", "a-b", "a b", "x;DROP TABLE t", "`x`", "@@x"] {
+            let err = set(&format!("{name}=1")).unwrap_err();
+            assert!(err.contains("is not a variable name"), "{name}: {err}");
+        }
+        for name in ["autocommit", "TIDB_TXN_MODE", "tidb_snapshot"] {
+            let err = set(&format!("{name}=1")).unwrap_err();
+            assert!(err.contains("cannot be set with --set"), "{name}: {err}");
+        }
     }
 }
@@ -0,0 +1,126 @@
+//! Run-wide setup and teardown coordination between workers.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::Result;
+use rlt::BenchSuite;
+use tokio::sync::OnceCell;
+use tokio::time::Instant;
+
+/// Makes table setup happen exactly once before any worker starts, and table teardown
+/// exactly once after every worker has finished.
+///
+/// `setup` and `teardown` of [`rlt::BenchSuite`] run per worker; each worker passes its
+/// shared work through here and only one of them actually performs it.
+pub struct Lifecycle {
+    setup: OnceCell<()>,
+    /// How long the setup that succeeded took.
+    setup_time: OnceLock<Duration>,
+    running: AtomicU32,
+}
+
+impl Lifecycle {
+    pub fn new(workers: u32) -> Self {
+        Self {
+            setup: OnceCell::new(),
+            setup_time: OnceLock::new(),
+            running: AtomicU32::new(workers),
+        }
+    }
+
+    /// Run `setup` if no worker has completed it yet, otherwise wait for the worker that is.
+    ///
+    /// Futures are lazy, so the workers that do not run setup never poll theirs. If setup
+    /// fails, the next waiting worker retries it and reports its own error.
+    pub async fn setup_once<F>(&self, setup: F) -> Result<()>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        self.setup
+            .get_or_try_init(|| async {
+                let started = Instant::now();
+                setup.await?;
+                let took = started.elapsed();
+                eprintln!("setup took {took:.2?}");
+                let _ = self.setup_time.set(took);
+                Ok::<_, anyhow::Error>(())
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Print how long setup took. Call once, after the run.
+    pub fn print_setup(&self) {
+        if let Some(took) = self.setup_time.get() {
+            eprintln!("setup (tables created and seeded before the first iteration): {took:.2?}");
+        }
+    }
+
+    /// Mark the calling worker as finished; returns `true` for the last one, which should
+    /// run the shared teardown. Call it exactly once per worker.
+    pub fn finish(&self) -> bool {
+        self.running.fetch_sub(1, Ordering::AcqRel) == 1
+    }
+}
+
+/// `--setup-only`: run the setup of one worker, which creates and seeds the tables, and
+/// leave them in place without running any iteration.
+pub async fn setup_only<B: BenchSuite>(mut bench: B) -> Result<()> {
+    let started = Instant::now();
+    bench.setup(0).await?;
+    eprintln!(
+        "setup took {:.2?}; the tables are kept for later runs with --keep-table",
+        started.elapsed()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn only_the_last_worker_to_finish_tears_down() {
+        const WORKERS: u32 = 16;
+        let lifecycle = Arc::new(Lifecycle::new(WORKERS));
+        let setups = Arc::new(AtomicUsize::new(0));
+        let finished = Arc::new(AtomicU32::new(0));
+        let workers: Vec<_> = (0..WORKERS)
+            .map(|worker_id| {
+                let (lifecycle, setups, finished) =
+                    (lifecycle.clone(), setups.clone(), finished.clone());
+                tokio::spawn(async move {
+                    lifecycle
+                        .setup_once(async {
+                            setups.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(10)).await;
+                            Ok(())
+                        })
+                        .await
+                        .unwrap();
+                    // Workers stop at different times, as they do after an interrupt.
+                    tokio::time::sleep(Duration::from_millis(u64::from(worker_id % 5))).await;
+                    finished.fetch_add(1, Ordering::SeqCst);
+                    let last = lifecycle.finish();
+                    // The worker told to tear down must see every other one already stopped.
+                    if last {
+                        assert_eq!(finished.load(Ordering::SeqCst), WORKERS);
+                    }
+                    last
+                })
+            })
+            .collect();
+        let mut teardowns = 0;
+        for worker in workers {
+            teardowns += worker.await.unwrap() as u32;
+        }
+        assert_eq!(setups.load(Ordering::SeqCst), 1);
+        assert_eq!(teardowns, 1);
+    }
+}
@@ -0,0 +1,195 @@
+//! Load shaping applied around benchmark iterations.
+
+use std::fmt;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::time::Instant;
+
+/// Open-loop scheduler handing out iteration start slots at a fixed global rate.
+///
+/// Slots are assigned in request order from a single schedule shared by all workers, so
+/// the budget is split fairly between them. The schedule never slips: when the system
+/// falls behind, late iterations keep their original slot as the intended start time and
+/// the queuing delay is charged to their latency (no coordinated omission).
+pub struct Pacer {
+    interval: Duration,
+    /// How far ahead of its slot an iteration may start.
+    tolerance: Duration,
+    next: Mutex<Option<Instant>>,
+}
+
+impl Pacer {
+    pub fn new(rate: NonZeroU32, burst: NonZeroU32) -> Self {
+        let interval = Duration::from_secs(1) / rate.get();
+        Self {
+            interval,
+            tolerance: interval * (burst.get() - 1),
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Wait for the next slot and return the instant latency should be measured from.
+    pub async fn wait(&self) -> Instant {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = next.unwrap_or_else(Instant::now);
+            *next = Some(slot + self.interval);
+            slot
+        };
+        let release = slot.checked_sub(self.tolerance).unwrap_or(slot);
+        tokio::time::sleep_until(release).await;
+        slot.min(Instant::now())
+    }
+}
+
+/// Client-side pause between iterations, emulating an interactive user.
+#[derive(Clone, Copy)]
+pub struct ThinkTime {
+    base: Duration,
+    /// Relative jitter in `[0, 1]`; the pause is drawn uniformly from `base * (1 ± jitter)`.
+    jitter: f64,
+}
+
+impl ThinkTime {
+    pub fn new(base: Duration, jitter_percent: u8) -> Self {
+        Self {
+            base,
+            jitter: jitter_percent as f64 / 100.0,
+        }
+    }
+
+    pub async fn pause(&self) {
+        let factor = if self.jitter > 0.0 {
+            rand::thread_rng().gen_range(1.0 - self.jitter..=1.0 + self.jitter)
+        } else {
+            1.0
+        };
+        tokio::time::sleep(self.base.mul_f64(factor)).await;
+    }
+}
+
+/// One step of a `--ramp-schedule`: `percent` of the workers run until `until`.
+#[derive(Debug, Clone, Copy)]
+pub struct RampStep {
+    pub percent: u8,
+    pub until: Duration,
+}
+
+/// Stepwise concurrency schedule, e.g. `10:30s,50:60s,100:120s`.
+#[derive(Debug, Clone)]
+pub struct RampSchedule(Vec<RampStep>);
+
+impl FromStr for RampSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut steps: Vec<RampStep> = Vec::new();
+        for part in s.split(',') {
+            let (percent, until) = part
+                .split_once(':')
+                .ok_or_else(|| format!("invalid step '{part}', expected PERCENT:TIME"))?;
+            let percent: u8 = percent
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid percentage in '{part}': {e}"))?;
+            let until = humantime::parse_duration(until.trim())
+                .map_err(|e| format!("invalid time in '{part}': {e}"))?;
+            if !(1..=100).contains(&percent) {
+                return Err(format!("percentage in '{part}' must be in 1..=100"));
+            }
+            if let Some(prev) = steps.last() {
+                if percent < prev.percent || until <= prev.until {
+                    return Err(format!(
+                        "step '{part}' must not lower concurrency and must end after the previous step"
+                    ));
+                }
+            }
+            steps.push(RampStep { percent, until });
+        }
+        Ok(Self(steps))
+    }
+}
+
+impl fmt::Display for RampSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, step) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(
+                f,
+                "{}:{}",
+                step.percent,
+                humantime::format_duration(step.until)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How workers are released at the start of a run.
+#[derive(Debug, Clone)]
+pub enum RampPlan {
+    /// Start with one worker and release the rest evenly over the window.
+    Linear(Duration),
+    /// Hold each step's share of workers until the step ends.
+    Steps(RampSchedule),
+}
+
+/// Gate delaying each worker's first iteration according to a [`RampPlan`].
+pub struct Ramp {
+    plan: RampPlan,
+    workers: u32,
+    start: OnceLock<Instant>,
+}
+
+impl Ramp {
+    pub fn new(plan: RampPlan, workers: u32) -> Self {
+        Self {
+            plan,
+            workers,
+            start: OnceLock::new(),
+        }
+    }
+
+    /// Offset from the start of the run at which `worker_id` becomes active.
+    fn release_at(&self, worker_id: u32) -> Duration {
+        match &self.plan {
+            RampPlan::Linear(window) if self.workers > 1 => {
+                window.mul_f64(worker_id as f64 / (self.workers - 1) as f64)
+            }
+            RampPlan::Linear(_) => Duration::ZERO,
+            RampPlan::Steps(RampSchedule(steps)) => {
+                let mut from = Duration::ZERO;
+                for step in steps {
+                    let active = (self.workers as u64 * step.percent as u64).div_ceil(100);
+                    if (worker_id as u64) < active {
+                        return from;
+                    }
+                    from = step.until;
+                }
+                from
+            }
+        }
+    }
+
+    fn end(&self) -> Duration {
+        match &self.plan {
+            RampPlan::Linear(window) => *window,
+            RampPlan::Steps(RampSchedule(steps)) => {
+                steps.last().map_or(Duration::ZERO, |s| s.until)
+            }
+        }
+    }
+
+    /// Wait until `worker_id` is allowed to run; returns whether the ramp is still in progress.
+    pub async fn admit(&self, worker_id: u32) -> bool {
+        let start = *self.start.get_or_init(Instant::now);
+        tokio::time::sleep_until(start + self.release_at(worker_id)).await;
+        start.elapsed() < self.end()
+    }
+}
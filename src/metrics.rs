@@ -0,0 +1,265 @@
+//! Live Prometheus metrics (`--metrics-listen`).
+//!
+//! A minimal HTTP endpoint on the run's own runtime serves the metrics in the Prometheus
+//! text format to any request, for scraping while the run goes on. Without the option
+//! nothing is recorded or served.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rlt::IterReport;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use crate::status::kind_name;
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const BUCKETS: [f64; 14] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A scraper gets this long to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counters of one worker; only that worker and the endpoint lock them.
+#[derive(Default, Clone)]
+struct Worker {
+    /// Iterations by status kind and code.
+    statuses: BTreeMap<(&'static str, i64), u64>,
+    items: u64,
+    bytes: u64,
+    /// Cumulative counts per bucket of [`BUCKETS`].
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// Shared by all workers and the endpoint.
+pub struct Metrics {
+    listen: SocketAddr,
+    bench: Mutex<String>,
+    /// Indexed by worker id.
+    workers: Vec<Mutex<Worker>>,
+    active: AtomicI64,
+    server: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// Counts a worker as active while held.
+pub struct Active<'a>(&'a AtomicI64);
+
+impl Drop for Active<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    pub fn new(listen: SocketAddr, workers: u32) -> Self {
+        Self {
+            listen,
+            bench: Mutex::default(),
+            workers: (0..workers.max(1)).map(|_| Mutex::default()).collect(),
+            active: AtomicI64::new(0),
+            server: Mutex::default(),
+        }
+    }
+
+    /// Bind the endpoint and start serving, labeling the metrics with `bench`. Call once,
+    /// before the run.
+    pub async fn start(self: &Arc<Self>, bench: &str) -> Result<()> {
+        *self.bench.lock().unwrap() = bench.to_owned();
+        let listener = TcpListener::bind(self.listen)
+            .await
+            .with_context(|| format!("cannot listen on {} for --metrics-listen", self.listen))?;
+        eprintln!("serving metrics on http://{}/metrics", self.listen);
+        let metrics = self.clone();
+        let server = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let metrics = metrics.clone();
+                tokio::spawn(async move { metrics.serve(stream).await });
+            }
+        });
+        *self.server.lock().unwrap() = Some(server);
+        Ok(())
+    }
+
+    /// Stop serving. Call once, after the run.
+    pub fn stop(&self) {
+        if let Some(server) = self.server.lock().unwrap().take() {
+            server.abort();
+        }
+    }
+
+    /// Count the calling worker as active until the guard is dropped.
+    pub fn enter(&self) -> Active<'_> {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        Active(&self.active)
+    }
+
+    /// Record an iteration of `worker_id` that just completed.
+    pub fn record(&self, worker_id: u32, report: &IterReport) {
+        let secs = report.duration.as_secs_f64();
+        let mut worker = self.workers[worker_id as usize % self.workers.len()]
+            .lock()
+            .unwrap();
+        *worker
+            .statuses
+            .entry((kind_name(&report.status), report.status.code()))
+            .or_default() += 1;
+        worker.items += report.items;
+        worker.bytes += report.bytes;
+        for (bucket, le) in worker.buckets.iter_mut().zip(BUCKETS) {
+            if secs <= le {
+                *bucket += 1;
+            }
+        }
+        worker.count += 1;
+        worker.sum += secs;
+    }
+
+    /// Answer one request with the metrics, whatever it asked for.
+    async fn serve(&self, mut stream: TcpStream) {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        let read = tokio::time::timeout(REQUEST_TIMEOUT, async {
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+        });
+        if read.await.is_err() {
+            return;
+        }
+        let body = self.render();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        let _ = stream.shutdown().await;
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    fn render(&self) -> String {
+        let bench = self.bench.lock().unwrap().clone();
+        // Each worker's counters are copied out at once, so they agree with each other;
+        // workers that have not completed an iteration yet are left out.
+        let workers: Vec<(usize, Worker)> = self
+            .workers
+            .iter()
+            .enumerate()
+            .filter_map(|(id, worker)| {
+                let worker = worker.lock().unwrap();
+                (worker.count > 0).then(|| (id, worker.clone()))
+            })
+            .collect();
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP tidb_bench_iterations_total Iterations completed, by status.\n\
+             # TYPE tidb_bench_iterations_total counter"
+        );
+        for (id, worker) in workers.iter() {
+            for ((kind, code), count) in &worker.statuses {
+                let _ = writeln!(
+                    out,
+                    "tidb_bench_iterations_total{{bench=\"{bench}\",worker=\"{id}\",\
+                     status=\"{kind}\",code=\"{code}\"}} {count}"
+                );
+            }
+        }
+        let counter = |out: &mut String, name: &str, help: &str, value: fn(&Worker) -> u64| {
+            let _ = writeln!(
+                out,
+                "# HELP tidb_bench_{name}_total {help}\n# TYPE tidb_bench_{name}_total counter"
+            );
+            for (id, worker) in workers.iter() {
+                let _ = writeln!(
+                    out,
+                    "tidb_bench_{name}_total{{bench=\"{bench}\",worker=\"{id}\"}} {}",
+                    value(worker)
+                );
+            }
+        };
+        counter(
+            &mut out,
+            "items",
+            "Items (rows) reported by iterations.",
+            |w| w.items,
+        );
+        counter(
+            &mut out,
+            "bytes",
+            "Logical payload bytes reported by iterations.",
+            |w| w.bytes,
+        );
+        let _ = writeln!(
+            out,
+            "# HELP tidb_bench_iteration_latency_seconds Latency of completed iterations.\n\
+             # TYPE tidb_bench_iteration_latency_seconds histogram"
+        );
+        for (id, worker) in workers.iter() {
+            let labels = format!("bench=\"{bench}\",worker=\"{id}\"");
+            for (le, count) in BUCKETS.iter().zip(worker.buckets) {
+                let _ = writeln!(
+                    out,
+                    "tidb_bench_iteration_latency_seconds_bucket{{{labels},le=\"{le}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "tidb_bench_iteration_latency_seconds_bucket{{{labels},le=\"+Inf\"}} {}\n\
+                 tidb_bench_iteration_latency_seconds_sum{{{labels}}} {}\n\
+                 tidb_bench_iteration_latency_seconds_count{{{labels}}} {}",
+                worker.count, worker.sum, worker.count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP tidb_bench_active_workers Workers currently running an iteration.\n\
+             # TYPE tidb_bench_active_workers gauge\n\
+             tidb_bench_active_workers{{bench=\"{bench}\"}} {}",
+            self.active.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rlt::Status;
+
+    use super::*;
+    use crate::status;
+
+    #[test]
+    fn render_shows_each_worker_that_completed_an_iteration() {
+        let metrics = Metrics::new("127.0.0.1:0".parse().unwrap(), 3);
+        let report = IterReport {
+            duration: Duration::from_millis(2),
+            status: Status::success(status::OK),
+            bytes: 10,
+            items: 1,
+        };
+        metrics.record(0, &report);
+        metrics.record(2, &report);
+        metrics.record(2, &report);
+
+        let out = metrics.render();
+        assert!(out.contains("tidb_bench_items_total{bench=\"\",worker=\"0\"} 1\n"));
+        assert!(out.contains("tidb_bench_items_total{bench=\"\",worker=\"2\"} 2\n"));
+        assert!(!out.contains("worker=\"1\""));
+        assert!(out.contains(
+            "tidb_bench_iteration_latency_seconds_bucket{bench=\"\",worker=\"2\",le=\"0.0025\"} 2\n"
+        ));
+    }
+}
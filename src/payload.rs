@@ -0,0 +1,29 @@
+//! Logical payload sizes reported as `IterReport::bytes`.
+//!
+//! Bytes count the column values a benchmark actually sent or received, in their MySQL
+//! storage encoding (fixed-width integers, string bytes without length prefixes). Protocol
+//! framing and SQL text are not included, and `mysql_async` exposes no wire-level byte
+//! counters to report alongside them.
+
+/// `BIGINT` column value.
+pub const BIGINT: u64 = 8;
+
+/// `INT` column value.
+pub const INT: u64 = 4;
+
+/// `VARCHAR`/`TEXT` column value.
+pub fn string(value: &str) -> u64 {
+    value.len() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_count_utf8_bytes() {
+        assert_eq!(string(""), 0);
+        assert_eq!(string("bench_data_7"), 12);
+        assert_eq!(string("caf\u{e9}"), 5);
+    }
+}
@@ -0,0 +1,356 @@
+//! Execution plans of the benchmark statements (`--capture-plan`).
+//!
+//! Once the warmup is over, a separate connection runs `EXPLAIN ANALYZE` on a representative
+//! instance of each statement the benchmark sends, with the values spliced into the text.
+//! Statements the benchmark prepares are also executed as prepared statements and explained
+//! with `EXPLAIN FOR CONNECTION`, which shows the plan the parameterized form got from (or
+//! put into) the plan cache. Statements that write or lock run on a sacrificial key inside a
+//! transaction that is rolled back, so the data under test is left alone.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use anyhow::Result;
+use mysql_async::prelude::*;
+use mysql_async::{Row, Value};
+use serde::Serialize;
+use tokio::task::JoinHandle;
+
+use crate::{splice, DbOpts, Protocol, WORKER_SEQ_BITS};
+
+/// Worker id whose keys the plans of writes use: the last key range that fits a signed
+/// `BIGINT`, far above the range of any real worker.
+pub const SACRIFICIAL_WORKER: u32 = (1 << (63 - WORKER_SEQ_BITS)) - 1;
+
+/// One statement of an iteration, with representative values.
+pub struct PlanStatement {
+    /// What the statement does in an iteration, e.g. `range scan`.
+    pub label: &'static str,
+    pub sql: String,
+    /// Values bound to the placeholders of `sql`, if the benchmark prepares it.
+    pub params: Option<Vec<Value>>,
+    /// Changes rows or locks them, so it must only touch a sacrificial key.
+    pub write: bool,
+}
+
+impl PlanStatement {
+    /// A statement sent the way `protocol` sends it, with `params` for the `?` in `query`.
+    pub fn new(label: &'static str, protocol: Protocol, query: &str, params: Vec<Value>) -> Self {
+        let (sql, params) = match protocol {
+            Protocol::Binary => (query.to_owned(), Some(params)),
+            Protocol::Text => (splice(query, &params), None),
+        };
+        Self {
+            label,
+            sql,
+            params,
+            write: false,
+        }
+    }
+
+    /// A statement in the SQL text, without placeholders.
+    pub fn text(label: &'static str, sql: String) -> Self {
+        Self {
+            label,
+            sql,
+            params: None,
+            write: false,
+        }
+    }
+
+    /// Mark the statement as a write.
+    pub fn write(self) -> Self {
+        Self {
+            write: true,
+            ..self
+        }
+    }
+}
+
+/// The plan of a statement in one form.
+struct Plan {
+    label: &'static str,
+    /// `literal` or `prepared`.
+    form: &'static str,
+    /// When it was captured: `warmup` or `end`.
+    when: &'static str,
+    sql: String,
+    /// Whether the second prepared execution reused a cached plan; known for prepared reads.
+    from_cache: Option<bool>,
+    /// The plan as a table, or why it is unknown.
+    plan: Result<String, String>,
+}
+
+/// A [`Plan`] as written to `--results-json`.
+#[derive(Serialize)]
+struct PlanJson<'a> {
+    statement: &'a str,
+    form: &'a str,
+    when: &'a str,
+    sql: &'a str,
+    from_plan_cache: Option<bool>,
+    plan: Option<&'a str>,
+    error: Option<&'a str>,
+}
+
+/// Shared by all workers.
+pub struct PlanCapture {
+    db: DbOpts,
+    at_end: bool,
+    statements: OnceLock<Vec<PlanStatement>>,
+    /// Iterations to complete before capturing; `u64::MAX` until the run starts.
+    warmup: AtomicU64,
+    completed: AtomicU64,
+    pending: Mutex<Option<JoinHandle<()>>>,
+    plans: Mutex<Vec<Plan>>,
+}
+
+impl PlanCapture {
+    pub fn new(db: &DbOpts, at_end: bool) -> Self {
+        Self {
+            db: db.clone(),
+            at_end,
+            statements: OnceLock::new(),
+            warmup: AtomicU64::new(u64::MAX),
+            completed: AtomicU64::new(0),
+            pending: Mutex::default(),
+            plans: Mutex::default(),
+        }
+    }
+
+    /// Set the statements to explain. Call once, when setup has made the tables ready.
+    pub fn set_statements(&self, statements: Vec<PlanStatement>) {
+        let _ = self.statements.set(statements);
+    }
+
+    /// Capture once `warmup` iterations have completed. Call once, before the run.
+    pub fn start(&self, warmup: u64) {
+        self.warmup.store(warmup, Ordering::Relaxed);
+    }
+
+    /// Count an iteration that just completed, and capture the plans after the last warmup
+    /// iteration, in the background.
+    pub fn observe(self: &Arc<Self>) {
+        let completed = self.completed.fetch_add(1, Ordering::Relaxed) + 1;
+        if completed != self.warmup.load(Ordering::Relaxed).saturating_add(1) {
+            return;
+        }
+        let capture = self.clone();
+        *self.pending.lock().unwrap() = Some(tokio::spawn(async move {
+            capture.capture("warmup").await;
+        }));
+    }
+
+    /// Capture the plans again if `--capture-plan-at-end` asks for it. Call once, before the
+    /// tables are dropped.
+    pub async fn capture_at_end(&self) {
+        self.finish().await;
+        if self.at_end {
+            self.capture("end").await;
+        }
+    }
+
+    /// Wait for the capture in flight, if any. Call once, after the run.
+    pub async fn finish(&self) {
+        let pending = self.pending.lock().unwrap().take();
+        if let Some(task) = pending {
+            let _ = task.await;
+        }
+    }
+
+    async fn capture(&self, when: &'static str) {
+        let Some(statements) = self.statements.get() else {
+            return;
+        };
+        for statement in statements {
+            let literal = self.explain_literal(statement).await;
+            self.push(when, statement, "literal", literal_sql(statement), literal);
+            if statement.params.is_some() {
+                let prepared = self.explain_prepared(statement).await;
+                self.push(when, statement, "prepared", statement.sql.clone(), prepared);
+            }
+        }
+        eprintln!(
+            "captured the plans of {} statements ({when})",
+            statements.len()
+        );
+    }
+
+    fn push(
+        &self,
+        when: &'static str,
+        statement: &PlanStatement,
+        form: &'static str,
+        sql: String,
+        result: Result<(String, Option<bool>)>,
+    ) {
+        let (plan, from_cache) = match result {
+            Ok((plan, from_cache)) => (Ok(plan), from_cache),
+            Err(e) => {
+                eprintln!(
+                    "warning: cannot capture the {form} plan of {}: {e:#}",
+                    statement.label
+                );
+                (Err(format!("{e:#}")), None)
+            }
+        };
+        self.plans.lock().unwrap().push(Plan {
+            label: statement.label,
+            form,
+            when,
+            sql,
+            from_cache,
+            plan,
+        });
+    }
+
+    /// `EXPLAIN ANALYZE` of `statement` with its values spliced in, in a transaction that is
+    /// rolled back if it writes.
+    async fn explain_literal(&self, statement: &PlanStatement) -> Result<(String, Option<bool>)> {
+        let mut conn = self.db.connect().await?;
+        if statement.write {
+            conn.query_drop("BEGIN").await?;
+        }
+        let rows = conn
+            .query(format!("EXPLAIN ANALYZE {}", literal_sql(statement)))
+            .await;
+        if statement.write {
+            conn.query_drop("ROLLBACK").await?;
+        }
+        let _ = conn.disconnect().await;
+        Ok((table(rows?), None))
+    }
+
+    /// Execute `statement` as a prepared statement and explain that execution from a second
+    /// connection. Reads run twice, so the second execution can hit the plan cache; writes
+    /// run once, in a transaction that is rolled back.
+    async fn explain_prepared(&self, statement: &PlanStatement) -> Result<(String, Option<bool>)> {
+        let params = statement.params.clone().unwrap_or_default();
+        let mut conn = self.db.connect().await?;
+        let mut explainer = self.db.connect().await?;
+        let id: Option<u64> = conn.query_first("SELECT CONNECTION_ID()").await?;
+        let id = id.ok_or_else(|| anyhow::anyhow!("no connection id"))?;
+        let explained = async {
+            if statement.write {
+                conn.query_drop("BEGIN").await?;
+                conn.exec_drop(&statement.sql, params).await?;
+            } else {
+                for _ in 0..2 {
+                    conn.exec_drop(&statement.sql, params.clone()).await?;
+                }
+            }
+            let rows: Vec<Row> = explainer
+                .query(format!("EXPLAIN FOR CONNECTION {id}"))
+                .await?;
+            let from_cache = if statement.write {
+                None
+            } else {
+                conn.query_first("SELECT @@last_plan_from_cache").await?
+            };
+            anyhow::Ok((table(rows), from_cache))
+        }
+        .await;
+        if statement.write {
+            conn.query_drop("ROLLBACK").await?;
+        }
+        let _ = conn.disconnect().await;
+        let _ = explainer.disconnect().await;
+        explained
+    }
+
+    /// Print the plans captured. Call once, after [`PlanCapture::finish`].
+    pub fn print(&self) {
+        let plans = self.plans.lock().unwrap();
+        eprintln!("plans ({} captured)", plans.len());
+        for plan in plans.iter() {
+            let cache = match plan.from_cache {
+                Some(true) => ", from the plan cache",
+                Some(false) => ", not from the plan cache",
+                None => "",
+            };
+            eprintln!(
+                "  {} ({}, {}{cache}): {}",
+                plan.label, plan.form, plan.when, plan.sql
+            );
+            match &plan.plan {
+                Ok(text) => {
+                    for line in text.lines() {
+                        eprintln!("    {line}");
+                    }
+                }
+                Err(e) => eprintln!("    {e}"),
+            }
+        }
+    }
+
+    /// The plans as a JSON array, for `--results-json`.
+    pub fn json(&self) -> serde_json::Value {
+        let plans = self.plans.lock().unwrap();
+        let plans: Vec<_> = plans
+            .iter()
+            .map(|p| PlanJson {
+                statement: p.label,
+                form: p.form,
+                when: p.when,
+                sql: &p.sql,
+                from_plan_cache: p.from_cache,
+                plan: p.plan.as_deref().ok(),
+                error: p.plan.as_ref().err().map(String::as_str),
+            })
+            .collect();
+        serde_json::to_value(plans).expect("plans serialize")
+    }
+}
+
+/// `statement` with its values spliced into the text.
+fn literal_sql(statement: &PlanStatement) -> String {
+    match &statement.params {
+        Some(params) => splice(&statement.sql, params),
+        None => statement.sql.clone(),
+    }
+}
+
+/// EXPLAIN output as an aligned table with a header line.
+fn table(rows: Vec<Row>) -> String {
+    let Some(first) = rows.first() else {
+        return String::new();
+    };
+    let header: Vec<String> = first
+        .columns_ref()
+        .iter()
+        .map(|c| c.name_str().into_owned())
+        .collect();
+    let mut lines = vec![header];
+    for row in rows {
+        let cells = (0..row.len())
+            .map(|i| match row.as_ref(i) {
+                Some(Value::Bytes(bytes)) => String::from_utf8_lossy(bytes).into_owned(),
+                Some(Value::NULL) | None => String::new(),
+                Some(value) => value.as_sql(false),
+            })
+            .collect();
+        lines.push(cells);
+    }
+    let columns = lines[0].len();
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            lines
+                .iter()
+                .map(|l| l.get(i).map_or(0, |c| c.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    lines
+        .iter()
+        .map(|cells| {
+            let padded: Vec<_> = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{cell:<width$}"))
+                .collect();
+            padded.join(" | ").trim_end().to_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,441 @@
+//! Connection checks run once before any worker starts.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use mysql_async::prelude::*;
+use mysql_async::Conn;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::{quote_ident, quote_str, DbOpts, DEFAULT_PORT};
+
+/// Port MySQL listens on by default, the usual mix-up with TiDB's 4000.
+const MYSQL_PORT: u16 = 3306;
+
+/// How long to wait for a greeting when probing the other well-known port.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Protocol version byte that opens a MySQL (and TiDB) initial handshake packet.
+const HANDSHAKE_V10: u8 = 10;
+
+/// How long a host gets to accept a TCP connection before it counts as unreachable.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Privileges setup needs to replace, seed and finally drop the benchmark tables.
+const SETUP_PRIVILEGES: [&str; 4] = ["CREATE", "DROP", "INSERT", "SELECT"];
+
+/// Outcome of one preflight check.
+enum Verdict {
+    Pass(String),
+    Warn(String),
+    /// What failed, and how to fix it.
+    Fail(String, String),
+}
+
+/// Checks run so far, printed as a summary once all are done.
+#[derive(Default)]
+struct Report {
+    checks: Vec<(String, Verdict)>,
+}
+
+impl Report {
+    fn add(&mut self, check: impl Into<String>, verdict: Verdict) {
+        self.checks.push((check.into(), verdict));
+    }
+
+    fn print(&self) {
+        eprintln!("preflight:");
+        for (check, verdict) in &self.checks {
+            match verdict {
+                Verdict::Pass(detail) => eprintln!("  ok    {check:<32} {detail}"),
+                Verdict::Warn(detail) => eprintln!("  warn  {check:<32} {detail}"),
+                Verdict::Fail(detail, hint) => {
+                    eprintln!("  FAIL  {check:<32} {detail}");
+                    eprintln!("        {:<32} hint: {hint}", "");
+                }
+            }
+        }
+    }
+
+    /// Fail naming every failed check, if any.
+    fn result(self) -> Result<()> {
+        let failures: Vec<_> = self
+            .checks
+            .into_iter()
+            .filter_map(|(check, verdict)| match verdict {
+                Verdict::Fail(detail, _) => Some(format!("{check}: {detail}")),
+                _ => None,
+            })
+            .collect();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("preflight failed: {}", failures.join("; ")))
+        }
+    }
+}
+
+/// Check every configured host before any worker starts, turning common misconfigurations
+/// into clear errors, unless `--skip-preflight` is set.
+///
+/// Each host must accept a TCP connection within [`CONNECT_TIMEOUT`] and authenticate. If
+/// nothing answers on a host's port, the other well-known port is probed and suggested when
+/// a MySQL-protocol server responds there. A server that does not support `tidb_version()`
+/// is reported with a warning, since TiDB-specific settings will not apply to it. On the
+/// first host, the grants of the current user are checked for `privileges` (those the
+/// benchmark's iterations need) on the database, plus what setup needs unless the table is
+/// `--no-create`. A summary of all checks is printed, and any failed one fails preflight.
+pub async fn preflight(db: &DbOpts, privileges: &[&str]) -> Result<()> {
+    if db.skip_preflight {
+        return Ok(());
+    }
+    let mut report = Report::default();
+    for (i, (host, port)) in db.all_endpoints().into_iter().enumerate() {
+        let Some(mut conn) = check_endpoint(db, host, port, &mut report).await else {
+            continue;
+        };
+        if i == 0 {
+            let mut needed: Vec<&str> = privileges.to_vec();
+            if !db.no_create {
+                needed.extend(SETUP_PRIVILEGES);
+            }
+            needed.sort_unstable();
+            needed.dedup();
+            let check = format!("privileges on `{}`", db.database);
+            let verdict = match check_privileges(&mut conn, &db.database, &needed).await {
+                Ok(verdict) => verdict,
+                Err(e) => Verdict::Warn(format!("cannot read the grants: {e}")),
+            };
+            report.add(check, verdict);
+        }
+        // Best effort: the checks are done.
+        let _ = conn.disconnect().await;
+    }
+    report.print();
+    report.result()
+}
+
+/// Reach, authenticate against and identify one host; returns the connection if that
+/// worked.
+async fn check_endpoint(db: &DbOpts, host: &str, port: u16, report: &mut Report) -> Option<Conn> {
+    let endpoint = format!("{host}:{port}");
+    match timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => report.add(
+            format!("{endpoint} reachable"),
+            Verdict::Pass(String::new()),
+        ),
+        Ok(Err(e)) => {
+            let hint = refused_hint(host, other_port(port)).await;
+            report.add(
+                format!("{endpoint} reachable"),
+                Verdict::Fail(format!("cannot connect: {e}"), hint),
+            );
+            return None;
+        }
+        Err(_) => {
+            report.add(
+                format!("{endpoint} reachable"),
+                Verdict::Fail(
+                    format!(
+                        "no TCP answer within {}",
+                        humantime::format_duration(CONNECT_TIMEOUT)
+                    ),
+                    "check --host and any firewall or security group in between".into(),
+                ),
+            );
+            return None;
+        }
+    }
+
+    let mut conn = match db.connect_plain(host, port).await {
+        Ok(conn) => {
+            report.add(
+                format!("{endpoint} authentication"),
+                Verdict::Pass(format!("as {}", db.user)),
+            );
+            conn
+        }
+        Err(e) => {
+            let hint = if is_io_error(&e) {
+                "the server closed the connection; check --tls and the server's TLS settings"
+            } else {
+                "check --user, --password and the host the user may connect from"
+            };
+            report.add(
+                format!("{endpoint} authentication"),
+                Verdict::Fail(format!("{e:#}"), hint.into()),
+            );
+            return None;
+        }
+    };
+
+    let verdict = match conn.query_first::<String, _>("SELECT tidb_version()").await {
+        Ok(Some(version)) => Verdict::Pass(version.lines().next().unwrap_or_default().into()),
+        _ => Verdict::Warn(
+            "not TiDB (tidb_version() is not available); TiDB-specific options will fail or \
+             be ignored"
+                .into(),
+        ),
+    };
+    report.add(format!("{endpoint} server"), verdict);
+    Some(conn)
+}
+
+/// Compare the grants of the current user with the privileges needed on `database`.
+async fn check_privileges(conn: &mut Conn, database: &str, needed: &[&str]) -> Result<Verdict> {
+    let grants: Vec<String> = conn.query("SHOW GRANTS").await?;
+    let mut granted = Vec::new();
+    let mut roles = false;
+    for grant in &grants {
+        match parse_grant(grant) {
+            Some((privileges, object)) if covers(&object, database) => granted.extend(privileges),
+            Some(_) => {}
+            None => roles |= grant.starts_with("GRANT "),
+        }
+    }
+    let all = granted.iter().any(|p| p == "ALL" || p == "ALL PRIVILEGES");
+    let missing: Vec<_> = needed
+        .iter()
+        .filter(|p| !all && !granted.iter().any(|g| g == *p))
+        .copied()
+        .collect();
+    if missing.is_empty() {
+        return Ok(Verdict::Pass(needed.join(", ")));
+    }
+    let missing = missing.join(", ");
+    if roles {
+        return Ok(Verdict::Warn(format!(
+            "{missing} not granted directly; assuming it comes from a role"
+        )));
+    }
+    let user: String = conn
+        .query_first("SELECT CURRENT_USER()")
+        .await?
+        .unwrap_or_default();
+    let grantee = match user.rsplit_once('@') {
+        Some((name, host)) => format!("{}@{}", quote_str(name), quote_str(host)),
+        None => quote_str(&user),
+    };
+    Ok(Verdict::Fail(
+        format!("missing {missing}"),
+        format!(
+            "GRANT {missing} ON {}.* TO {grantee}",
+            quote_ident(database)
+        ),
+    ))
+}
+
+/// Privileges (upper case) and object of a `GRANT <privileges> ON <object> TO ...` line;
+/// `None` for role grants.
+fn parse_grant(grant: &str) -> Option<(Vec<String>, String)> {
+    let rest = grant.strip_prefix("GRANT ")?;
+    let (privileges, rest) = rest.split_once(" ON ")?;
+    let (object, _) = rest.split_once(" TO ")?;
+    let mut depth = 0;
+    let privileges = privileges
+        // Split at the commas between privileges, not those in a column list.
+        .split(|c| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            c == ',' && depth == 0
+        })
+        // Column-level grants such as `SELECT (id, value)` do not cover the whole table.
+        .filter(|p| !p.contains('('))
+        .map(|p| p.trim().to_ascii_uppercase())
+        .collect();
+    Some((privileges, object.replace('`', "")))
+}
+
+/// Whether a grant on `object` (`*.*`, `db.*` or `db.table`, unquoted) applies to the
+/// tables of `database`.
+fn covers(object: &str, database: &str) -> bool {
+    match object.split_once('.') {
+        Some(("*", _)) => true,
+        Some((db, "*")) => db.eq_ignore_ascii_case(database),
+        _ => false,
+    }
+}
+
+fn is_io_error(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<mysql_async::Error>(),
+        Some(mysql_async::Error::Io(_))
+    )
+}
+
+/// The well-known port to probe when `port` refuses connections.
+fn other_port(port: u16) -> u16 {
+    match port {
+        DEFAULT_PORT => MYSQL_PORT,
+        _ => DEFAULT_PORT,
+    }
+}
+
+/// How to fix a refused connection: the `alternative` port if a MySQL-protocol server
+/// answers there.
+async fn refused_hint(host: &str, alternative: u16) -> String {
+    if probe_mysql(host, alternative).await {
+        format!(
+            "a MySQL-protocol server responded on {alternative}; did you mean --port \
+             {alternative}?"
+        )
+    } else {
+        "check --host and --port, and that the server is running".into()
+    }
+}
+
+/// Whether a server on `host:port` greets with a MySQL protocol v10 handshake.
+async fn probe_mysql(host: &str, port: u16) -> bool {
+    let greeting = async {
+        let mut stream = TcpStream::connect((host, port)).await?;
+        // 3-byte payload length, 1-byte sequence id, then the protocol version.
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).await?;
+        Ok::<_, std::io::Error>(header[4])
+    };
+    matches!(
+        timeout(PROBE_TIMEOUT, greeting).await,
+        Ok(Ok(HANDSHAKE_V10))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Serve one connection on a local port, sending `greeting` and closing it.
+    async fn stub(greeting: &'static [u8]) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            if let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.write_all(greeting).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        port
+    }
+
+    /// A local port nothing listens on.
+    async fn closed_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    /// Start of an initial handshake packet: payload length, sequence id, protocol v10.
+    const HANDSHAKE: &[u8] = b"\x4a\x00\x00\x00\x0a8.0.11-TiDB\x00";
+
+    #[tokio::test]
+    async fn a_handshake_suggests_its_port() {
+        let port = stub(HANDSHAKE).await;
+        assert!(probe_mysql("127.0.0.1", port).await);
+
+        let port = stub(HANDSHAKE).await;
+        let hint = refused_hint("127.0.0.1", port).await;
+        assert!(
+            hint.contains(&format!("did you mean --port {port}?")),
+            "{hint}"
+        );
+    }
+
+    #[tokio::test]
+    async fn other_greetings_and_silence_are_not_mysql() {
+        assert!(!probe_mysql("127.0.0.1", stub(b"SSH-2.0-OpenSSH_9.6\r\n").await).await);
+        assert!(!probe_mysql("127.0.0.1", stub(b"").await).await);
+
+        let hint = refused_hint("127.0.0.1", stub(b"HTTP/1.1 400\r\n\r\n").await).await;
+        assert_eq!(
+            hint,
+            "check --host and --port, and that the server is running"
+        );
+    }
+
+    #[test]
+    fn tidb_and_mysql_ports_point_at_each_other() {
+        assert_eq!(other_port(DEFAULT_PORT), MYSQL_PORT);
+        assert_eq!(other_port(MYSQL_PORT), DEFAULT_PORT);
+        assert_eq!(other_port(4001), DEFAULT_PORT);
+    }
+
+    fn grant(line: &str) -> (Vec<String>, String) {
+        parse_grant(line).unwrap_or_else(|| panic!("{line} is a privilege grant"))
+    }
+
+    #[test]
+    fn grants_parse_into_privileges_and_object() {
+        let (privileges, object) =
+            grant("GRANT ALL PRIVILEGES ON *.* TO 'root'@'%' WITH GRANT OPTION");
+        assert_eq!(privileges, ["ALL PRIVILEGES"]);
+        assert_eq!(object, "*.*");
+
+        let (privileges, object) = grant("GRANT Select,Insert ON `bench`.`t` TO 'u'@'%'");
+        assert_eq!(privileges, ["SELECT", "INSERT"]);
+        assert_eq!(object, "bench.t");
+
+        // Column-level privileges do not count, whatever the number of columns.
+        let (privileges, _) = grant("GRANT SELECT (id) ON bench.t TO 'u'@'%'");
+        assert!(privileges.is_empty(), "{privileges:?}");
+        let (privileges, _) =
+            grant("GRANT SELECT (id, value), UPDATE (value), INSERT ON bench.t TO 'u'@'%'");
+        assert_eq!(privileges, ["INSERT"]);
+
+        // Role grants have no object.
+        assert_eq!(parse_grant("GRANT 'app_read'@'%' TO 'u'@'%'"), None);
+        assert_eq!(
+            parse_grant("GRANT `app_read`@`%`,`app_write`@`%` TO `u`@`%`"),
+            None
+        );
+    }
+
+    #[test]
+    fn grants_cover_databases_by_object() {
+        assert!(covers("*.*", "bench"));
+        assert!(covers("bench.*", "bench"));
+        assert!(covers("Bench.*", "bench"));
+        assert!(covers("bench.*", "BENCH"));
+        assert!(!covers("other.*", "bench"));
+        // A table grant does not extend to the tables a run creates.
+        assert!(!covers("bench.t", "bench"));
+        let (_, object) = grant("GRANT INSERT ON `bench`.* TO 'u'@'%'");
+        assert!(covers(&object, "bench"));
+    }
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        db: DbOpts,
+    }
+
+    #[tokio::test]
+    async fn refused_connections_fail_with_a_hint() {
+        let db = Cli::parse_from(["test"]).db;
+        let port = closed_port().await;
+        let mut report = Report::default();
+        assert!(check_endpoint(&db, "127.0.0.1", port, &mut report)
+            .await
+            .is_none());
+        let [(check, Verdict::Fail(detail, hint))] = report.checks.as_slice() else {
+            panic!("expected one failed check");
+        };
+        assert_eq!(check, &format!("127.0.0.1:{port} reachable"));
+        assert!(detail.starts_with("cannot connect: "), "{detail}");
+        // The hint depends on whether a server runs on the TiDB port of this machine.
+        assert!(hint.contains("--port"), "{hint}");
+
+        let hint = refused_hint("127.0.0.1", closed_port().await).await;
+        assert_eq!(
+            hint,
+            "check --host and --port, and that the server is running"
+        );
+        assert!(report.result().is_err());
+    }
+}
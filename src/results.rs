@@ -0,0 +1,442 @@
+//! Machine-readable results document (`--results-json`).
+//!
+//! One JSON object per run, for CI and other tools comparing runs: the configuration and its
+//! fingerprint, the server version, iteration counts by status, latency percentiles,
+//! throughput, per-worker totals, wall-clock timestamps and any `--capture-plan` plans. It is written even when the run
+//! ends early, marked `"partial": true`.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use mysql_async::prelude::*;
+use rlt::{IterReport, StatusKind};
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+
+use crate::harness::Latencies;
+use crate::status::kind_name;
+use crate::{ConfigFingerprint, DbOpts};
+
+/// Version of the document layout; bumped on any change that is not a pure addition.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Percentiles in the document, unless `--percentiles` is given.
+const DEFAULT_PERCENTILES: [f64; 4] = [50.0, 90.0, 99.0, 99.9];
+
+/// Totals of one worker, or of the whole run.
+#[derive(Default, Clone, Copy)]
+struct Totals {
+    iterations: u64,
+    items: u64,
+    bytes: u64,
+    errors: u64,
+    latency: Duration,
+}
+
+impl Totals {
+    fn add(&mut self, report: &IterReport) {
+        self.iterations += 1;
+        self.items += report.items;
+        self.bytes += report.bytes;
+        if report.status.kind() != StatusKind::Success {
+            self.errors += 1;
+        }
+        self.latency += report.duration;
+    }
+
+    fn merge(&mut self, other: &Totals) {
+        self.iterations += other.iterations;
+        self.items += other.items;
+        self.bytes += other.bytes;
+        self.errors += other.errors;
+        self.latency += other.latency;
+    }
+}
+
+/// What one worker recorded; only that worker locks it during the run.
+#[derive(Default)]
+struct Worker {
+    totals: Totals,
+    /// Iterations by status kind and code.
+    statuses: BTreeMap<(&'static str, i64), u64>,
+    first_iteration: Option<SystemTime>,
+    last_iteration: Option<SystemTime>,
+}
+
+/// Collects the measured iterations for the document; shared by all workers.
+pub struct Results {
+    path: PathBuf,
+    db: DbOpts,
+    percentiles: Vec<f64>,
+    started: Mutex<Option<SystemTime>>,
+    server_version: Mutex<Option<String>>,
+    /// Iterations to skip as warmup.
+    warmup: AtomicU64,
+    completed: AtomicU64,
+    /// Indexed by worker id.
+    workers: Vec<Mutex<Worker>>,
+    latencies: Latencies,
+}
+
+/// The document as written.
+#[derive(Serialize)]
+struct Document<'a> {
+    format_version: u32,
+    #[serde(flatten)]
+    fingerprint: &'a ConfigFingerprint,
+    partial: bool,
+    server_version: Option<String>,
+    timestamps: Timestamps,
+    iterations: u64,
+    errors: u64,
+    items: u64,
+    bytes: u64,
+    throughput: Throughput,
+    latency_ns: Option<LatencySummary>,
+    statuses: Vec<StatusCount>,
+    workers: Vec<WorkerTotals>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plans: Option<serde_json::Value>,
+}
+
+/// RFC 3339 wall-clock times of the run.
+#[derive(Serialize)]
+struct Timestamps {
+    started: Option<String>,
+    first_iteration: Option<String>,
+    last_iteration: Option<String>,
+    finished: Option<String>,
+}
+
+/// Bytes in the megabyte of `megabytes_per_sec`: decimal, as in SI units.
+const BYTES_PER_MB: f64 = 1_000_000.0;
+
+/// Rates over the measured span, rounded to three decimals; `None` without a span.
+#[derive(Serialize)]
+struct Throughput {
+    iterations_per_sec: Option<f64>,
+    items_per_sec: Option<f64>,
+    bytes_per_sec: Option<f64>,
+    megabytes_per_sec: Option<f64>,
+}
+
+impl Throughput {
+    fn over(run: &Totals, span: Duration) -> Self {
+        let rate =
+            |n: f64| (!span.is_zero()).then(|| (n / span.as_secs_f64() * 1000.0).round() / 1000.0);
+        Self {
+            iterations_per_sec: rate(run.iterations as f64),
+            items_per_sec: rate(run.items as f64),
+            bytes_per_sec: rate(run.bytes as f64),
+            megabytes_per_sec: rate(run.bytes as f64 / BYTES_PER_MB),
+        }
+    }
+}
+
+/// Mean, percentiles and max in nanoseconds; serialized in that order, each percentile
+/// keyed `p` and its digits, e.g. `p999` for 99.9.
+struct LatencySummary {
+    mean: u64,
+    percentiles: Vec<(String, u64)>,
+    max: u64,
+}
+
+impl Serialize for LatencySummary {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.percentiles.len() + 2))?;
+        map.serialize_entry("mean", &self.mean)?;
+        for (key, value) in &self.percentiles {
+            map.serialize_entry(key, value)?;
+        }
+        map.serialize_entry("max", &self.max)?;
+        map.end()
+    }
+}
+
+#[derive(Serialize)]
+struct StatusCount {
+    kind: &'static str,
+    code: i64,
+    count: u64,
+}
+
+#[derive(Serialize)]
+struct WorkerTotals {
+    worker: u32,
+    iterations: u64,
+    errors: u64,
+    items: u64,
+    bytes: u64,
+    mean_latency_ns: u64,
+}
+
+impl Results {
+    /// Results of `workers` workers, with the latency `percentiles` of `--percentiles`, or
+    /// the default ones if it is empty.
+    pub fn new(path: PathBuf, db: &DbOpts, percentiles: &[f64], workers: u32) -> Self {
+        Self {
+            path,
+            db: db.clone(),
+            percentiles: if percentiles.is_empty() {
+                DEFAULT_PERCENTILES.to_vec()
+            } else {
+                percentiles.to_vec()
+            },
+            started: Mutex::default(),
+            server_version: Mutex::default(),
+            warmup: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            workers: (0..workers.max(1)).map(|_| Mutex::default()).collect(),
+            latencies: Latencies::new(workers),
+        }
+    }
+
+    /// Note the start of the run and the server version, and skip the first `warmup`
+    /// iterations. Call once, before the run.
+    pub async fn start(&self, warmup: u64) {
+        self.warmup.store(warmup, Ordering::Relaxed);
+        let version = async {
+            let mut conn = self.db.connect().await?;
+            let version: Option<String> = conn.query_first("SELECT VERSION()").await?;
+            let _ = conn.disconnect().await;
+            anyhow::Ok(version)
+        };
+        let version = version.await.unwrap_or_else(|e| {
+            eprintln!("warning: cannot read the server version for --results-json: {e:#}");
+            None
+        });
+        *self.started.lock().unwrap() = Some(SystemTime::now());
+        *self.server_version.lock().unwrap() = version;
+    }
+
+    /// Record an iteration that just completed, unless it is part of the warmup.
+    pub fn record(&self, worker_id: u32, report: &IterReport) {
+        let completed = self.completed.fetch_add(1, Ordering::Relaxed) + 1;
+        if completed <= self.warmup.load(Ordering::Relaxed) {
+            return;
+        }
+        let now = SystemTime::now();
+        let mut worker = self.workers[worker_id as usize % self.workers.len()]
+            .lock()
+            .unwrap();
+        worker.first_iteration.get_or_insert(now);
+        worker.last_iteration = Some(now);
+        worker.totals.add(report);
+        *worker
+            .statuses
+            .entry((kind_name(&report.status), report.status.code()))
+            .or_default() += 1;
+        drop(worker);
+        self.latencies.record(worker_id, report.duration);
+    }
+
+    /// Write the document; `partial` marks a run that was interrupted or aborted, and `plans`
+    /// is the JSON array of `--capture-plan`. Call once, after the run.
+    pub fn write(
+        &self,
+        fingerprint: &ConfigFingerprint,
+        partial: bool,
+        plans: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let document = self.document(fingerprint, partial, plans);
+        let write = || -> Result<()> {
+            let mut json = serde_json::to_string(&document)?;
+            json.push('\n');
+            std::fs::write(&self.path, json)?;
+            Ok(())
+        };
+        write().with_context(|| format!("cannot write results to {}", self.path.display()))
+    }
+
+    fn document<'a>(
+        &self,
+        fingerprint: &'a ConfigFingerprint,
+        partial: bool,
+        plans: Option<serde_json::Value>,
+    ) -> Document<'a> {
+        let mut run = Totals::default();
+        let mut statuses = BTreeMap::<_, u64>::new();
+        let (mut first_iteration, mut last_iteration) = (None::<SystemTime>, None::<SystemTime>);
+        let mut workers = Vec::new();
+        for (id, worker) in (0..).zip(&self.workers) {
+            let worker = worker.lock().unwrap();
+            let totals = worker.totals;
+            if totals.iterations == 0 {
+                continue;
+            }
+            run.merge(&totals);
+            for (status, count) in &worker.statuses {
+                *statuses.entry(*status).or_default() += count;
+            }
+            first_iteration = first_iteration
+                .into_iter()
+                .chain(worker.first_iteration)
+                .min();
+            last_iteration = last_iteration
+                .into_iter()
+                .chain(worker.last_iteration)
+                .max();
+            workers.push(WorkerTotals {
+                worker: id,
+                iterations: totals.iterations,
+                errors: totals.errors,
+                items: totals.items,
+                bytes: totals.bytes,
+                mean_latency_ns: (totals.latency.as_nanos() / u128::from(totals.iterations)) as u64,
+            });
+        }
+
+        let time =
+            |t: Option<SystemTime>| t.map(|t| humantime::format_rfc3339_millis(t).to_string());
+        let timestamps = Timestamps {
+            started: time(*self.started.lock().unwrap()),
+            first_iteration: time(first_iteration),
+            last_iteration: time(last_iteration),
+            finished: time(Some(SystemTime::now())),
+        };
+
+        // Throughput over the span between the first and the last measured iteration.
+        let span = match (first_iteration, last_iteration) {
+            (Some(first), Some(last)) => last.duration_since(first).unwrap_or_default(),
+            _ => Duration::ZERO,
+        };
+        let throughput = Throughput::over(&run, span);
+
+        let latencies = self.latencies.merged();
+        let latency_ns = (!latencies.is_empty()).then(|| LatencySummary {
+            mean: latencies.mean().round() as u64,
+            percentiles: self
+                .percentiles
+                .iter()
+                .map(|p| {
+                    let key = format!("p{}", p.to_string().replace('.', ""));
+                    (key, latencies.value_at_quantile(p / 100.0))
+                })
+                .collect(),
+            max: latencies.max(),
+        });
+
+        Document {
+            format_version: FORMAT_VERSION,
+            fingerprint,
+            partial,
+            server_version: self.server_version.lock().unwrap().clone(),
+            timestamps,
+            iterations: run.iterations,
+            errors: run.errors,
+            items: run.items,
+            bytes: run.bytes,
+            throughput,
+            latency_ns,
+            statuses: statuses
+                .into_iter()
+                .map(|((kind, code), count)| StatusCount { kind, code, count })
+                .collect(),
+            workers,
+            plans,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use clap::{CommandFactory, Parser};
+    use rlt::{IterReport, Status};
+
+    use super::*;
+    use crate::status;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        db: DbOpts,
+    }
+
+    fn report(millis: u64) -> IterReport {
+        IterReport {
+            duration: Duration::from_millis(millis),
+            status: Status::success(status::OK),
+            bytes: 0,
+            items: 1,
+        }
+    }
+
+    #[test]
+    fn warmup_is_left_out_and_percentiles_follow_the_option() {
+        let matches = Cli::command().get_matches_from(["test"]);
+        let fingerprint = ConfigFingerprint::new("test", &Cli::command(), &matches);
+        let db = Cli::parse_from(["test"]).db;
+        let results = Results::new(PathBuf::new(), &db, &[50.0, 99.9], 2);
+        results.warmup.store(2, Ordering::Relaxed);
+        for (worker, millis) in [(0, 1000), (1, 1000), (0, 1), (1, 2), (0, 3)] {
+            results.record(worker, &report(millis));
+        }
+
+        let document = serde_json::to_value(results.document(&fingerprint, false, None)).unwrap();
+        assert_eq!(document["iterations"], 3);
+        assert_eq!(document["workers"][0]["iterations"], 2);
+        assert_eq!(document["workers"][1]["iterations"], 1);
+        let latency = document["latency_ns"].as_object().unwrap();
+        let keys: Vec<_> = latency.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["max", "mean", "p50", "p999"]);
+        let max = latency["max"].as_u64().unwrap();
+        assert!(max < 3_010_000, "warmup latency counted: {max}");
+        assert_eq!(document["bench"], "test");
+        assert!(document.get("plans").is_none());
+    }
+
+    #[test]
+    fn throughput_is_taken_over_the_measured_span() {
+        let matches = Cli::command().get_matches_from(["test"]);
+        let fingerprint = ConfigFingerprint::new("test", &Cli::command(), &matches);
+        let db = Cli::parse_from(["test"]).db;
+        let results = Results::new(PathBuf::new(), &db, &[], 2);
+        let mib = 1 << 20;
+        for worker in [0, 1, 0, 1] {
+            let report = IterReport {
+                bytes: mib,
+                items: 250,
+                ..report(1)
+            };
+            results.record(worker, &report);
+        }
+        // The first iteration of one worker to the last of the other: 2 seconds.
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        results.workers[0].lock().unwrap().first_iteration = Some(start);
+        results.workers[0].lock().unwrap().last_iteration = Some(start + Duration::from_secs(1));
+        results.workers[1].lock().unwrap().first_iteration = Some(start + Duration::from_secs(1));
+        results.workers[1].lock().unwrap().last_iteration = Some(start + Duration::from_secs(2));
+
+        let document = serde_json::to_value(results.document(&fingerprint, false, None)).unwrap();
+        let throughput = &document["throughput"];
+        assert_eq!(throughput["iterations_per_sec"], 2.0);
+        assert_eq!(throughput["items_per_sec"], 500.0);
+        assert_eq!(throughput["bytes_per_sec"], 2_097_152.0);
+        // 2 MiB/s is 2.097 MB/s: a megabyte is 10^6 bytes, not 2^20.
+        assert_eq!(throughput["megabytes_per_sec"], 2.097);
+        assert_eq!(document["bytes"], 4 * mib);
+    }
+
+    #[test]
+    fn throughput_needs_a_span() {
+        let run = Totals {
+            iterations: 1,
+            items: 1,
+            bytes: 1_000_000,
+            ..Totals::default()
+        };
+        assert!(Throughput::over(&run, Duration::ZERO)
+            .bytes_per_sec
+            .is_none());
+        let throughput = Throughput::over(&run, Duration::from_millis(500));
+        assert_eq!(throughput.megabytes_per_sec, Some(2.0));
+        assert_eq!(throughput.iterations_per_sec, Some(2.0));
+    }
+}
@@ -0,0 +1,550 @@
+//! Parallel preloading of benchmark tables.
+
+use std::num::NonZeroU32;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use mysql_async::prelude::*;
+use mysql_async::Conn;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+use crate::{quote_ident, quote_str, stable_hash, DbOpts, TABLE_TAG};
+
+/// Rows per multi-row INSERT statement.
+const INSERT_BATCH_SIZE: u64 = 5000;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Options controlling how benchmark tables are preloaded.
+#[derive(clap::Args, Clone)]
+pub struct SeedOpts {
+    /// Number of connections used to preload test data, each loading a disjoint id range.
+    #[clap(long, default_value = "4")]
+    pub seed_concurrency: NonZeroU32,
+
+    /// Drop and reseed the table even if it already holds matching data.
+    #[clap(long)]
+    pub force_reseed: bool,
+
+    /// Do not check the row count and id range of the table before the run starts, for
+    /// tables too large for a quick `COUNT(*)`.
+    #[clap(long)]
+    pub skip_verify_setup: bool,
+
+    /// Fill the `created_at` column of generated rows with whole-second values spread
+    /// evenly over this window, ending when seeding starts, instead of the seeding time.
+    #[clap(long, value_name = "WINDOW", value_parser = humantime::parse_duration)]
+    pub timestamp_spread: Option<Duration>,
+
+    /// End the `--timestamp-spread` window at this UTC time, e.g. `2024-06-01T00:00:00Z`,
+    /// instead of when seeding starts, so every seeding writes the same values.
+    #[clap(
+        long,
+        value_name = "TIME",
+        value_parser = humantime::parse_rfc3339_weak,
+        requires = "timestamp_spread"
+    )]
+    pub timestamp_end: Option<SystemTime>,
+}
+
+/// Options for seeding a table from a CSV file instead of generated data.
+#[derive(clap::Args, Clone)]
+pub struct CsvOpts {
+    /// Load the benchmark table from this CSV file instead of generating rows.
+    #[clap(long, value_name = "PATH")]
+    pub seed_csv: Option<PathBuf>,
+
+    /// The first CSV record is a header naming the table columns.
+    #[clap(long, requires = "seed_csv")]
+    pub csv_has_header: bool,
+
+    /// CSV field delimiter.
+    #[clap(long, default_value_t = ',', requires = "seed_csv")]
+    pub csv_delimiter: char,
+
+    /// Table column for each CSV column, in order; `-` skips a CSV column.
+    ///
+    /// Defaults to the header names with `--csv-has-header`.
+    #[clap(long, value_delimiter = ',', requires = "seed_csv")]
+    pub csv_columns: Option<Vec<String>>,
+
+    /// Load at most this many CSV records.
+    #[clap(long, requires = "seed_csv")]
+    pub csv_limit: Option<u64>,
+}
+
+/// Renders row `i` (0-based) as a parenthesized VALUES tuple.
+///
+/// Rows must be a pure function of their index so the seeded content does not depend on
+/// how the id space is split between loaders.
+pub type RowFn = Arc<dyn Fn(u64) -> String + Send + Sync>;
+
+/// A table to be created and preloaded during setup.
+pub struct SeedSpec<'a> {
+    /// Unquoted table name.
+    pub table: &'a str,
+    /// Column and index definitions for `CREATE TABLE`.
+    pub schema: &'a str,
+    /// Columns filled by `row`.
+    pub columns: &'a str,
+    pub rows: u64,
+    pub row: RowFn,
+    /// Smallest and largest id of the generated rows, checked with the row count after setup.
+    pub ids: RangeInclusive<i64>,
+    /// Identifies how `row` generates data; bump it whenever the generator changes.
+    pub generator: &'a str,
+    /// Load from CSV instead of `row` when `--seed-csv` is given.
+    pub csv: Option<&'a CsvOpts>,
+    /// The table has a `created_at` column, which `--timestamp-spread` fills.
+    pub created_at: bool,
+}
+
+impl SeedSpec<'_> {
+    /// Fingerprint of the data requirements, stored as the table comment once seeded.
+    ///
+    /// The placement policy, `AUTO_ID_CACHE` and collation are included, so changing them
+    /// recreates the table.
+    fn fingerprint(&self, db: &DbOpts, opts: &SeedOpts) -> String {
+        let source = match (self.csv, self.csv_path()) {
+            (Some(csv), Some(path)) => {
+                let meta = std::fs::metadata(path).ok();
+                let size = meta.as_ref().map(|m| m.len());
+                let modified = meta.and_then(|m| m.modified().ok());
+                format!(
+                    "csv {} {size:?} {modified:?} {} {:?} {:?} {:?}",
+                    path.display(),
+                    csv.csv_has_header,
+                    csv.csv_delimiter,
+                    csv.csv_columns,
+                    csv.csv_limit
+                )
+            }
+            _ => match (self.spread(opts), opts.timestamp_end) {
+                (Some(window), None) => format!(
+                    "{} {} created_at {}",
+                    self.rows,
+                    self.generator,
+                    humantime::format_duration(window)
+                ),
+                (Some(window), Some(end)) => format!(
+                    "{} {} created_at {} until {}",
+                    self.rows,
+                    self.generator,
+                    humantime::format_duration(window),
+                    unix_seconds(end)
+                ),
+                (None, _) => format!("{} {}", self.rows, self.generator),
+            },
+        };
+        let mut spec = format!("{}\n{}\n{source}", self.schema, self.columns);
+        if let Some(policy) = &db.placement_policy {
+            spec.push_str(&format!("\nplacement {policy}"));
+        }
+        if let Some(cache) = db.auto_id_cache {
+            spec.push_str(&format!("\nauto id cache {cache}"));
+        }
+        if let Some(collation) = &db.collation {
+            spec.push_str(&format!("\ncollation {collation}"));
+        }
+        format!("{TABLE_TAG} seed {:016x}", stable_hash(spec.as_bytes()))
+    }
+
+    fn csv_path(&self) -> Option<&Path> {
+        self.csv?.seed_csv.as_deref()
+    }
+
+    /// The `--timestamp-spread` window, if it applies to this table's generated rows.
+    fn spread(&self, opts: &SeedOpts) -> Option<Duration> {
+        opts.timestamp_spread
+            .filter(|_| self.created_at && self.csv_path().is_none())
+    }
+}
+
+/// Whole seconds since the Unix epoch at `at`, or 0 before it.
+fn unix_seconds(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Give row `i` of `rows` a `created_at` value `window` apart from the last one's, which is
+/// `end` in Unix seconds. Values are inserted with `FROM_UNIXTIME` by loaders whose session
+/// time zone is UTC, so no daylight-saving fold shifts them.
+fn spread_timestamps(row: RowFn, rows: u64, window: Duration, end: u64) -> RowFn {
+    let start = end - window.as_secs().min(end);
+    let step = (end - start) as f64 / rows.saturating_sub(1).max(1) as f64;
+    Arc::new(move |i| {
+        let tuple = row(i);
+        let tuple = tuple.strip_suffix(')').expect("a generated row is a tuple");
+        let at = start + (i as f64 * step) as u64;
+        format!("{tuple}, FROM_UNIXTIME({at}))")
+    })
+}
+
+/// Row count and id range of a seeded table.
+#[derive(Debug, Clone, Copy)]
+pub struct TableStats {
+    pub rows: u64,
+    pub min_id: i64,
+    pub max_id: i64,
+}
+
+/// Oldest and newest `created_at` in `table`, as Unix seconds, or `None` if it has no rows.
+///
+/// Read independently of the session time zone, for time-range queries against seeded data.
+pub async fn timestamp_bounds(conn: &mut Conn, table: &str) -> Result<Option<(i64, i64)>> {
+    let (min, max): (Option<i64>, Option<i64>) = conn
+        .query_first(format!(
+            "SELECT UNIX_TIMESTAMP(MIN(created_at)), UNIX_TIMESTAMP(MAX(created_at)) FROM {table}"
+        ))
+        .await?
+        .unwrap_or_default();
+    Ok(min.zip(max))
+}
+
+/// Count the rows of `table` and find its id range.
+pub async fn table_stats(conn: &mut Conn, table: &str) -> Result<TableStats> {
+    let (rows, min_id, max_id): (u64, Option<i64>, Option<i64>) = conn
+        .query_first(format!("SELECT COUNT(*), MIN(id), MAX(id) FROM {table}"))
+        .await?
+        .unwrap_or_default();
+    Ok(TableStats {
+        rows,
+        min_id: min_id.unwrap_or_default(),
+        max_id: max_id.unwrap_or_default(),
+    })
+}
+
+/// Create and seed `spec.table`, unless it already holds data seeded from the same spec.
+///
+/// With `--no-create` the table is used as-is and only checked for existence.
+/// The fingerprint is only written after seeding completes, so a run that died mid-seed
+/// never leaves a table that looks reusable.
+pub async fn prepare_table(
+    conn: &mut Conn,
+    db: &DbOpts,
+    opts: &SeedOpts,
+    spec: SeedSpec<'_>,
+) -> Result<()> {
+    if db.no_create {
+        return db.require_table(conn, spec.table, spec.schema).await;
+    }
+    let table = quote_ident(spec.table);
+    let fingerprint = spec.fingerprint(db, opts);
+    if !opts.force_reseed {
+        let comment: Option<String> = conn
+            .exec_first(
+                "SELECT TABLE_COMMENT FROM information_schema.TABLES \
+                 WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ?",
+                (spec.table,),
+            )
+            .await?;
+        if comment.as_deref() == Some(fingerprint.as_str()) {
+            eprintln!("reusing existing data in {table} ({fingerprint})");
+            db.mark_created(spec.table);
+            return verify_seeded(conn, opts, &spec).await;
+        }
+    }
+
+    db.create_table(conn, spec.table, spec.schema).await?;
+    let fill = async {
+        match (spec.csv, spec.csv_path()) {
+            (Some(csv), Some(path)) => {
+                let loaded = load_csv(conn, &table, csv, path).await?;
+                let count = table_stats(conn, &table).await?.rows;
+                if count != loaded {
+                    bail!("{table} holds {count} rows after loading {loaded} CSV records");
+                }
+            }
+            _ => match spec.spread(opts) {
+                Some(window) => {
+                    let end = unix_seconds(opts.timestamp_end.unwrap_or_else(SystemTime::now));
+                    let row = spread_timestamps(spec.row.clone(), spec.rows, window, end);
+                    let columns = format!("{}, created_at", spec.columns);
+                    seed_table(db, opts, &table, &columns, spec.rows, row).await?;
+                }
+                None => {
+                    seed_table(db, opts, &table, spec.columns, spec.rows, spec.row.clone()).await?
+                }
+            },
+        }
+        conn.query_drop(format!("ALTER TABLE {table} COMMENT = '{fingerprint}'"))
+            .await
+            .map_err(Into::into)
+    };
+    // A half-seeded table has no fingerprint and would be reseeded anyway; run setup under
+    // `DbOpts::undo_on_error` so it does not outlive the run.
+    fill.await?;
+    if spec.csv_path().is_none() {
+        verify_seeded(conn, opts, &spec).await?;
+    }
+    Ok(())
+}
+
+/// Check that the generated table holds exactly the rows and ids the benchmark assumes,
+/// unless `--skip-verify-setup` is given.
+///
+/// Catches generator and seeding bugs, and reused tables changed behind the fingerprint's
+/// back, before they turn into plausible-looking but wrong results.
+async fn verify_seeded(conn: &mut Conn, opts: &SeedOpts, spec: &SeedSpec<'_>) -> Result<()> {
+    if opts.skip_verify_setup {
+        return Ok(());
+    }
+    let table = quote_ident(spec.table);
+    let stats = table_stats(conn, &table).await?;
+    if stats.rows != spec.rows
+        || stats.min_id != *spec.ids.start()
+        || stats.max_id != *spec.ids.end()
+    {
+        bail!(
+            "{table} does not hold the data the benchmark expects: expected {} rows with ids \
+             {}..={}, found {} rows with ids {}..={}; pass --force-reseed to seed it again \
+             (or --skip-verify-setup to run anyway)",
+            spec.rows,
+            spec.ids.start(),
+            spec.ids.end(),
+            stats.rows,
+            stats.min_id,
+            stats.max_id
+        );
+    }
+    Ok(())
+}
+
+/// Load rows `0..total` into `table`, fanning out over `--seed-concurrency` connections.
+///
+/// The first failing loader cancels the others; its error names the id range it was
+/// loading. The final row count is verified against `total`.
+pub async fn seed_table(
+    db: &DbOpts,
+    opts: &SeedOpts,
+    table: &str,
+    columns: &str,
+    total: u64,
+    row: RowFn,
+) -> Result<()> {
+    let loaders = (opts.seed_concurrency.get() as u64).min(total.max(1));
+    let chunk = total.div_ceil(loaders);
+    let done = Arc::new(AtomicU64::new(0));
+    let started = Instant::now();
+
+    let mut tasks = JoinSet::new();
+    for lo in (0..total).step_by(chunk.max(1) as usize) {
+        let hi = (lo + chunk).min(total);
+        let (db, table, columns) = (db.clone(), table.to_owned(), columns.to_owned());
+        let (row, done) = (row.clone(), done.clone());
+        tasks.spawn(async move {
+            let load = async {
+                let mut conn = db.connect().await?;
+                // Datetime values in generated rows are UTC; see `spread_timestamps`.
+                conn.query_drop("SET time_zone = '+00:00'").await?;
+                for start in (lo..hi).step_by(INSERT_BATCH_SIZE as usize) {
+                    let end = (start + INSERT_BATCH_SIZE).min(hi);
+                    let values = (start..end).map(&*row).collect::<Vec<_>>().join(", ");
+                    conn.query_drop(format!("INSERT INTO {table} ({columns}) VALUES {values}"))
+                        .await?;
+                    done.fetch_add(end - start, Ordering::Relaxed);
+                }
+                anyhow::Ok(())
+            };
+            load.await
+                .with_context(|| format!("seeding rows {lo}..{hi} of {table} failed"))
+        });
+    }
+
+    let progress = tokio::spawn({
+        let (done, table) = (done.clone(), table.to_owned());
+        async move {
+            let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                log_progress(&table, done.load(Ordering::Relaxed), total, started);
+            }
+        }
+    });
+
+    let mut result = Ok(());
+    while let Some(joined) = tasks.join_next().await {
+        if let Err(e) = joined.map_err(anyhow::Error::from).and_then(|r| r) {
+            tasks.abort_all();
+            result = Err(e);
+            break;
+        }
+    }
+    progress.abort();
+    result?;
+    log_progress(table, total, total, started);
+
+    let mut conn = db.connect().await?;
+    let count: u64 = conn
+        .query_first(format!("SELECT COUNT(*) FROM {table}"))
+        .await?
+        .unwrap_or_default();
+    if count != total {
+        bail!("seeded {table} holds {count} rows, expected {total}");
+    }
+    Ok(())
+}
+
+fn log_progress(table: &str, done: u64, total: u64, started: Instant) {
+    let rate = done as f64 / started.elapsed().as_secs_f64().max(f64::EPSILON);
+    let eta = Duration::from_secs_f64(total.saturating_sub(done) as f64 / rate.max(1.0));
+    eprintln!(
+        "seeding {table}: {done}/{total} rows ({rate:.0} rows/s, ETA {})",
+        humantime::format_duration(Duration::from_secs(eta.as_secs()))
+    );
+}
+
+/// Stream CSV records into `table` with multi-row INSERTs, returning the number loaded.
+async fn load_csv(conn: &mut Conn, table: &str, csv: &CsvOpts, path: &Path) -> Result<u64> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(csv.csv_has_header)
+        .delimiter(u8::try_from(csv.csv_delimiter).context("--csv-delimiter must be ASCII")?)
+        .from_path(path)
+        .with_context(|| format!("cannot open {}", path.display()))?;
+    let columns: Vec<String> = match &csv.csv_columns {
+        Some(columns) => columns.clone(),
+        None if csv.csv_has_header => reader.headers()?.iter().map(str::to_owned).collect(),
+        None => bail!("--csv-columns is required unless --csv-has-header is given"),
+    };
+
+    // (CSV index, column name, numeric type?) for every mapped column.
+    let mut targets = Vec::new();
+    for (idx, name) in columns.iter().enumerate().filter(|(_, name)| *name != "-") {
+        let data_type: Option<String> = conn
+            .exec_first(
+                "SELECT DATA_TYPE FROM information_schema.COLUMNS \
+                 WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? AND COLUMN_NAME = ?",
+                (table.trim_matches('`'), name),
+            )
+            .await?;
+        let Some(data_type) = data_type else {
+            bail!(
+                "CSV column {} maps to unknown column {name} of {table}",
+                idx + 1
+            );
+        };
+        let numeric = matches!(
+            data_type.as_str(),
+            "tinyint"
+                | "smallint"
+                | "mediumint"
+                | "int"
+                | "bigint"
+                | "decimal"
+                | "float"
+                | "double"
+        );
+        targets.push((idx, name.as_str(), numeric));
+    }
+    let names = targets
+        .iter()
+        .map(|(_, name, _)| quote_ident(name))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let limit = csv.csv_limit.unwrap_or(u64::MAX);
+    let started = Instant::now();
+    let mut last_progress = started;
+    let mut loaded = 0;
+    let mut batch = Vec::with_capacity(INSERT_BATCH_SIZE as usize);
+    for record in reader.records() {
+        if loaded + batch.len() as u64 >= limit {
+            break;
+        }
+        let record = record?;
+        let line = record.position().map_or(0, |p| p.line());
+        let mut tuple = Vec::with_capacity(targets.len());
+        for &(idx, name, numeric) in &targets {
+            let field = record
+                .get(idx)
+                .with_context(|| format!("line {line}: missing CSV column {}", idx + 1))?
+                .trim();
+            tuple.push(if field.is_empty() {
+                "NULL".to_owned()
+            } else if numeric {
+                if !is_numeric_literal(field) {
+                    bail!(
+                        "line {line}, column {} ({name}): '{field}' is not numeric",
+                        idx + 1
+                    );
+                }
+                field.to_owned()
+            } else {
+                quote_str(field)
+            });
+        }
+        batch.push(format!("({})", tuple.join(", ")));
+
+        if batch.len() as u64 == INSERT_BATCH_SIZE {
+            loaded += flush_csv_batch(conn, table, &names, &mut batch).await?;
+            if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                last_progress = Instant::now();
+                let rate = loaded as f64 / started.elapsed().as_secs_f64();
+                eprintln!("loading {table} from CSV: {loaded} rows ({rate:.0} rows/s)");
+            }
+        }
+    }
+    loaded += flush_csv_batch(conn, table, &names, &mut batch).await?;
+    eprintln!("loaded {loaded} rows into {table} from {}", path.display());
+    Ok(loaded)
+}
+
+/// Whether `field` can go into the INSERT unquoted as a number: anything `f64` parses except
+/// `inf`, `NaN` and the like, which SQL would read as column names.
+fn is_numeric_literal(field: &str) -> bool {
+    field.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+async fn flush_csv_batch(
+    conn: &mut Conn,
+    table: &str,
+    names: &str,
+    batch: &mut Vec<String>,
+) -> Result<u64> {
+    if batch.is_empty() {
+        return Ok(0);
+    }
+    let values = batch.join(", ");
+    conn.query_drop(format!("INSERT INTO {table} ({names}) VALUES {values}"))
+        .await?;
+    let n = batch.len() as u64;
+    batch.clear();
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_literals_exclude_non_finite_values() {
+        for field in ["0", "-12", "+7", "3.25", ".5", "1e6", "-2.5E-3"] {
+            assert!(is_numeric_literal(field), "{field}");
+        }
+        for field in [
+            "inf", "-inf", "infinity", "Infinity", "NaN", "nan", "1,5", "0x10", "abc",
+        ] {
+            assert!(!is_numeric_literal(field), "{field}");
+        }
+    }
+
+    #[test]
+    fn timestamps_spread_evenly_up_to_the_given_end() {
+        let row: RowFn = Arc::new(|i| format!("({}, 'a)')", i + 1));
+        let rows = spread_timestamps(row, 5, Duration::from_secs(100), 1_000);
+        let values: Vec<String> = (0..5).map(|i| rows(i)).collect();
+        assert_eq!(values[0], "(1, 'a)', FROM_UNIXTIME(900))");
+        assert_eq!(values[2], "(3, 'a)', FROM_UNIXTIME(950))");
+        assert_eq!(values[4], "(5, 'a)', FROM_UNIXTIME(1000))");
+        // A window reaching back before the epoch starts at it.
+        let row: RowFn = Arc::new(|i| format!("({i})"));
+        assert_eq!(
+            spread_timestamps(row, 2, Duration::from_secs(100), 10)(0),
+            "(0, FROM_UNIXTIME(0))"
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Server-side execution time from TiDB's statement summary (`--latency-source server`).
+//!
+//! TiDB does not report the execution time of individual statements back to the client, so
+//! the summary (`CLUSTER_STATEMENTS_SUMMARY`) is read right before the first iteration and
+//! again after the run. The difference is the server time spent on the benchmark's
+//! statements, per statement digest, which is compared with the latency the client observed.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+use mysql_async::prelude::*;
+use tokio::sync::OnceCell;
+
+use crate::{quote_str, DbOpts};
+
+/// Client latency per iteration above this multiple of the server time hints at a
+/// client-bound run.
+const CLIENT_BOUND_RATIO: f64 = 2.0;
+
+/// Differences below this are network round trips rather than a saturated client.
+const CLIENT_BOUND_MIN_GAP: Duration = Duration::from_millis(1);
+
+/// Where the latency summaries come from, as chosen by `--latency-source`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LatencySource {
+    /// Wall clock around each iteration, on the client.
+    Client,
+    /// Also read the server's execution time from the statement summary.
+    Server,
+}
+
+/// Totals of one statement digest in the summary.
+#[derive(Debug, Clone, Default)]
+struct Digest {
+    text: String,
+    execs: u64,
+    /// Nanoseconds.
+    total: u64,
+    /// Nanoseconds, over the whole summary window rather than just the run.
+    max: u64,
+}
+
+type Snapshot = BTreeMap<String, Digest>;
+
+/// Shared by all workers; the first iteration takes the starting snapshot.
+pub struct ServerTime {
+    db: DbOpts,
+    before: OnceCell<Option<Snapshot>>,
+    /// Server time spent during the run, or why it is unknown.
+    during: Mutex<Option<Result<Snapshot, String>>>,
+    client_nanos: AtomicU64,
+    iterations: AtomicU64,
+}
+
+impl ServerTime {
+    pub fn new(db: &DbOpts) -> Self {
+        Self {
+            db: db.clone(),
+            before: OnceCell::new(),
+            during: Mutex::default(),
+            client_nanos: AtomicU64::new(0),
+            iterations: AtomicU64::new(0),
+        }
+    }
+
+    /// Take the starting snapshot, unless an earlier iteration already did; the other
+    /// workers wait for it, so seeding during setup is not counted.
+    pub async fn start(&self) {
+        self.before
+            .get_or_init(|| async {
+                match self.snapshot().await {
+                    Ok(snapshot) => Some(snapshot),
+                    Err(e) => {
+                        eprintln!("warning: cannot read the statement summary: {e:#}");
+                        None
+                    }
+                }
+            })
+            .await;
+    }
+
+    /// Record the client-observed latency of an iteration.
+    pub fn record(&self, latency: Duration) {
+        self.client_nanos
+            .fetch_add(latency.as_nanos() as u64, Ordering::Relaxed);
+        self.iterations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Take the final snapshot. Call once, after the run.
+    pub async fn finish(&self) {
+        let Some(Some(before)) = self.before.get() else {
+            return;
+        };
+        let during = match self.snapshot().await {
+            Ok(after) => Ok(after
+                .into_iter()
+                .filter_map(|(digest, after)| {
+                    let start = before.get(&digest).cloned().unwrap_or_default();
+                    // A count that went down means the summary window rolled over mid-run.
+                    let execs = after.execs.checked_sub(start.execs)?;
+                    (execs > 0).then(|| {
+                        let diff = Digest {
+                            execs,
+                            total: after.total.saturating_sub(start.total),
+                            ..after
+                        };
+                        (digest, diff)
+                    })
+                })
+                .collect()),
+            Err(e) => Err(format!("{e:#}")),
+        };
+        *self.during.lock().unwrap() = Some(during);
+    }
+
+    /// Statements of the benchmark's schema on its tables, plus transaction control.
+    async fn snapshot(&self) -> Result<Snapshot> {
+        let mut conn = self.db.connect().await?;
+        let tables = format!("%{}.{}%", self.db.database, self.db.table);
+        let rows: Vec<(String, String, u64, u64, u64)> = conn
+            .query(format!(
+                "SELECT DIGEST, ANY_VALUE(DIGEST_TEXT), CAST(SUM(EXEC_COUNT) AS UNSIGNED), \
+                 CAST(SUM(SUM_LATENCY) AS UNSIGNED), CAST(MAX(MAX_LATENCY) AS UNSIGNED) \
+                 FROM INFORMATION_SCHEMA.CLUSTER_STATEMENTS_SUMMARY \
+                 WHERE SCHEMA_NAME = DATABASE() \
+                 AND (TABLE_NAMES LIKE {} OR DIGEST_TEXT IN ('begin', 'commit', 'rollback')) \
+                 GROUP BY DIGEST",
+                quote_str(&tables)
+            ))
+            .await?;
+        let _ = conn.disconnect().await;
+        Ok(rows
+            .into_iter()
+            .map(|(digest, text, execs, total, max)| {
+                (
+                    digest,
+                    Digest {
+                        text,
+                        execs,
+                        total,
+                        max,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Print the server time per statement next to the client latency, and warn when the
+    /// two diverge. Call once, after [`ServerTime::finish`].
+    pub fn print(&self) {
+        let during = match self.during.lock().unwrap().take() {
+            Some(Ok(during)) => during,
+            Some(Err(e)) => {
+                eprintln!("server execution time: cannot read the statement summary: {e}");
+                return;
+            }
+            None => {
+                eprintln!("server execution time: no statement summary to compare with");
+                return;
+            }
+        };
+        let iterations = self.iterations.load(Ordering::Relaxed);
+        if during.is_empty() || iterations == 0 {
+            eprintln!("server execution time: no benchmark statement in the statement summary");
+            return;
+        }
+        eprintln!("server execution time (statement summary, all TiDB instances):");
+        let mut digests: Vec<_> = during.into_values().collect();
+        digests.sort_by_key(|d| std::cmp::Reverse(d.total));
+        for digest in &digests {
+            let mut text = digest.text.clone();
+            if text.len() > 60 {
+                let cut = (0..=60)
+                    .rev()
+                    .find(|&i| text.is_char_boundary(i))
+                    .unwrap_or(0);
+                text.truncate(cut);
+                text.push('…');
+            }
+            eprintln!(
+                "  {:>9} execs  mean {:>10.2?}  max {:>10.2?}  {text}",
+                digest.execs,
+                Duration::from_nanos(digest.total / digest.execs),
+                Duration::from_nanos(digest.max)
+            );
+        }
+        let server =
+            Duration::from_nanos(digests.iter().map(|d| d.total).sum::<u64>() / iterations);
+        let client = Duration::from_nanos(self.client_nanos.load(Ordering::Relaxed) / iterations);
+        eprintln!("per iteration: client {client:.2?}, server {server:.2?}");
+        if client.as_secs_f64() > server.as_secs_f64() * CLIENT_BOUND_RATIO
+            && client - server > CLIENT_BOUND_MIN_GAP
+        {
+            eprintln!(
+                "warning: client-bound? iterations took {client:.2?} on the client but only \
+                 {server:.2?} on the server; the rest is network, driver and client scheduling \
+                 (a saturated client shows up here)"
+            );
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Worker connections: one per worker, or a pool shared by all workers (`--connections`).
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use mysql_async::prelude::*;
+use mysql_async::Conn;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::lifecycle::Lifecycle;
+use crate::{DbOpts, Role};
+
+/// Roll back whatever transaction `conn` may have left open, so its locks do not block the
+/// `DROP TABLE` of teardown. A no-op without one; failures (e.g. a dead connection, whose
+/// transaction is gone anyway) are only logged.
+pub async fn rollback_open(conn: &mut Conn) {
+    if let Err(e) = conn.query_drop("ROLLBACK").await {
+        eprintln!("warning: cannot roll back an open transaction during teardown: {e}");
+    }
+}
+
+/// Connections shared by all workers, each lent to one iteration at a time.
+///
+/// Filled by the workers during setup: each offers the connection it opened, and the first
+/// `--connections` ones are kept.
+pub struct ConnPool {
+    conns: Mutex<Vec<Conn>>,
+    /// One permit per connection currently in `conns`.
+    available: Semaphore,
+    size: Mutex<usize>,
+}
+
+impl Default for ConnPool {
+    fn default() -> Self {
+        Self {
+            conns: Mutex::default(),
+            available: Semaphore::new(0),
+            size: Mutex::default(),
+        }
+    }
+}
+
+impl ConnPool {
+    /// Keep `conn` if the pool holds fewer than `capacity` connections; otherwise hand it back.
+    fn adopt(&self, conn: Conn, capacity: usize) -> Option<Conn> {
+        let mut size = self.size.lock().unwrap();
+        if *size >= capacity {
+            return Some(conn);
+        }
+        *size += 1;
+        self.conns.lock().unwrap().push(conn);
+        self.available.add_permits(1);
+        None
+    }
+}
+
+/// What a worker holds between iterations: its own connection, or access to the pool.
+///
+/// A connection an iteration marked as failed (see [`Lease::fail`]) waits for
+/// [`Session::recover`] in `failed`; a pooled one is kept out of the pool until then.
+pub enum Session {
+    Owned {
+        conn: Conn,
+        failed: bool,
+    },
+    Pooled {
+        pool: Arc<ConnPool>,
+        failed: Option<Conn>,
+    },
+}
+
+impl Session {
+    /// A connection for `conn` unless the run uses a pool of `capacity` connections.
+    pub async fn new(conn: Conn, pool: &Arc<ConnPool>, capacity: Option<usize>) -> Self {
+        match capacity {
+            None => Session::Owned {
+                conn,
+                failed: false,
+            },
+            Some(capacity) => {
+                if let Some(surplus) = pool.adopt(conn, capacity) {
+                    // Best effort: a failed goodbye leaves nothing behind worth reporting.
+                    let _ = surplus.disconnect().await;
+                }
+                Session::Pooled {
+                    pool: pool.clone(),
+                    failed: None,
+                }
+            }
+        }
+    }
+
+    /// Mark the worker as finished (see [`Lifecycle::finish`]) after rolling back any
+    /// transaction its connection left open; returns `true` for the last worker.
+    ///
+    /// Pooled connections are shared, so the last worker rolls them all back once no worker
+    /// can be using one any more.
+    pub async fn finish(&mut self, lifecycle: &Lifecycle) -> bool {
+        match self {
+            Session::Owned { conn, .. } => {
+                rollback_open(conn).await;
+                lifecycle.finish()
+            }
+            Session::Pooled { pool, .. } => {
+                let last = lifecycle.finish();
+                if last {
+                    let mut conns = std::mem::take(&mut *pool.conns.lock().unwrap());
+                    for conn in &mut conns {
+                        rollback_open(conn).await;
+                    }
+                    pool.conns.lock().unwrap().append(&mut conns);
+                }
+                last
+            }
+        }
+    }
+
+    /// The connection to run on, waiting for a free one if pooled. Call it inside the timed
+    /// iteration, so the wait counts as latency.
+    pub async fn get(&mut self) -> Lease<'_> {
+        match self {
+            Session::Owned { conn, failed } => Lease::Owned { conn, failed },
+            Session::Pooled { pool, failed } => {
+                let permit = pool
+                    .available
+                    .acquire()
+                    .await
+                    .expect("the pool semaphore is never closed");
+                let conn = pool.conns.lock().unwrap().pop();
+                Lease::Pooled {
+                    conn,
+                    pool,
+                    failed,
+                    permit: Some(permit),
+                    broken: false,
+                }
+            }
+        }
+    }
+
+    /// Return the connection the last iteration marked as failed to a clean state (see
+    /// [`DbOpts::recover`]), and a pooled one to the pool. Call it after the timed
+    /// iteration, so rolling back and reconnecting do not count as latency.
+    pub async fn recover(&mut self, db: &DbOpts, worker_id: u32, role: Role) -> Result<()> {
+        match self {
+            Session::Owned { conn, failed } => {
+                if std::mem::take(failed) {
+                    db.recover(conn, worker_id, role).await?;
+                }
+            }
+            Session::Pooled { pool, failed } => {
+                if let Some(mut conn) = failed.take() {
+                    let recovered = db.recover(&mut conn, worker_id, role).await;
+                    pool.conns.lock().unwrap().push(conn);
+                    pool.available.add_permits(1);
+                    recovered?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A connection lent to one iteration; a pooled one goes back to the pool when dropped,
+/// unless the iteration marked it as failed.
+pub enum Lease<'a> {
+    Owned {
+        conn: &'a mut Conn,
+        failed: &'a mut bool,
+    },
+    Pooled {
+        conn: Option<Conn>,
+        pool: &'a ConnPool,
+        failed: &'a mut Option<Conn>,
+        // Released only after the connection is back in the pool.
+        permit: Option<SemaphorePermit<'a>>,
+        broken: bool,
+    },
+}
+
+impl Lease<'_> {
+    /// Mark the connection as left in an unknown state by a failed iteration, for
+    /// [`Session::recover`] to clean up once the iteration is over.
+    pub fn fail(&mut self) {
+        match self {
+            Lease::Owned { failed, .. } => **failed = true,
+            Lease::Pooled { broken, .. } => *broken = true,
+        }
+    }
+}
+
+impl Deref for Lease<'_> {
+    type Target = Conn;
+
+    fn deref(&self) -> &Conn {
+        match self {
+            Lease::Owned { conn, .. } => conn,
+            Lease::Pooled { conn, .. } => conn.as_ref().expect("a permit guarantees a connection"),
+        }
+    }
+}
+
+impl DerefMut for Lease<'_> {
+    fn deref_mut(&mut self) -> &mut Conn {
+        match self {
+            Lease::Owned { conn, .. } => conn,
+            Lease::Pooled { conn, .. } => conn.as_mut().expect("a permit guarantees a connection"),
+        }
+    }
+}
+
+impl Drop for Lease<'_> {
+    fn drop(&mut self) {
+        if let Lease::Pooled {
+            conn,
+            pool,
+            failed,
+            permit,
+            broken,
+        } = self
+        {
+            let Some(conn) = conn.take() else {
+                return;
+            };
+            if *broken {
+                // Its permit is given back once `Session::recover` returns it to the pool.
+                **failed = Some(conn);
+                if let Some(permit) = permit.take() {
+                    permit.forget();
+                }
+            } else {
+                pool.conns.lock().unwrap().push(conn);
+            }
+        }
+    }
+}
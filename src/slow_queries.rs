@@ -0,0 +1,312 @@
+//! Slow query log records of the run (`--collect-slow-queries`).
+//!
+//! The run's start and end are noted, and once it is over the records of TiDB's slow query
+//! log that fall into that window and belong to the benchmark's database are written to a
+//! file: the per-statement breakdown (coprocessor, lock wait, prewrite and commit time) that
+//! explains latency outliers. `INFORMATION_SCHEMA.CLUSTER_SLOW_QUERY` covers every TiDB
+//! instance; `SLOW_QUERY`, only the one connected to, is the fallback.
+
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use mysql_async::prelude::*;
+use mysql_async::{Conn, Row, Value};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::{quote_str, DbOpts};
+
+/// Most records collected; the earliest ones are kept.
+pub const MAX_RECORDS: usize = 10_000;
+
+/// Added to both ends of the window, for the clock skew between client and TiDB nodes.
+const WINDOW_SLACK: Duration = Duration::from_secs(1);
+
+/// Columns collected, besides `Instance` of the cluster table.
+const COLUMNS: [&str; 19] = [
+    "Time",
+    "Txn_start_ts",
+    "Conn_ID",
+    "Query_time",
+    "Parse_time",
+    "Compile_time",
+    "Cop_time",
+    "Process_time",
+    "Wait_time",
+    "Backoff_time",
+    "LockKeys_time",
+    "Prewrite_time",
+    "Commit_time",
+    "Total_keys",
+    "Process_keys",
+    "Succ",
+    "Plan_from_cache",
+    "Digest",
+    "Query",
+];
+
+/// What was collected, for the summary after the run.
+struct Collected {
+    table: &'static str,
+    records: usize,
+}
+
+/// An instance whose threshold was lowered, with the connection that lowered it: kept open
+/// for the run, so the restore reaches the same instance even behind a load balancer.
+struct Lowered {
+    endpoint: (String, u16),
+    conn: Conn,
+    previous: String,
+}
+
+/// One record as a JSON object, its members in the order of the columns.
+struct Record<'a> {
+    header: &'a [&'a str],
+    cells: Vec<Option<String>>,
+}
+
+impl Serialize for Record<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.header.len()))?;
+        for (name, cell) in self.header.iter().zip(&self.cells) {
+            map.serialize_entry(name, cell)?;
+        }
+        map.end()
+    }
+}
+
+/// Shared by the harness; not used by the workers.
+pub struct SlowQueries {
+    db: DbOpts,
+    path: PathBuf,
+    threshold: Option<Duration>,
+    started: Mutex<Option<SystemTime>>,
+    /// Instances whose `tidb_slow_log_threshold` is lowered until restored.
+    restore: Mutex<Vec<Lowered>>,
+    collected: Mutex<Option<Result<Collected, String>>>,
+}
+
+impl SlowQueries {
+    pub fn new(db: &DbOpts, path: PathBuf, threshold: Option<Duration>) -> Self {
+        Self {
+            db: db.clone(),
+            path,
+            threshold,
+            started: Mutex::default(),
+            restore: Mutex::default(),
+            collected: Mutex::default(),
+        }
+    }
+
+    /// Lower `tidb_slow_log_threshold` if asked to and note the start of the run. Call once,
+    /// before the run.
+    pub async fn start(&self) {
+        if let Some(threshold) = self.threshold {
+            for (host, port) in self.db.all_endpoints() {
+                match self.lower_threshold((host, port), threshold).await {
+                    Ok((conn, previous)) => {
+                        eprintln!(
+                            "{host}:{port}: tidb_slow_log_threshold lowered from {previous}ms \
+                             to {}ms",
+                            threshold.as_millis()
+                        );
+                        self.restore.lock().unwrap().push(Lowered {
+                            endpoint: (host.to_owned(), port),
+                            conn,
+                            previous,
+                        });
+                    }
+                    Err(e) => eprintln!(
+                        "warning: {host}:{port}: cannot lower tidb_slow_log_threshold: {e:#}"
+                    ),
+                }
+            }
+        }
+        *self.started.lock().unwrap() = Some(SystemTime::now());
+    }
+
+    /// Set the threshold of the TiDB instance behind `endpoint`, returning the connection
+    /// that set it and the previous threshold.
+    async fn lower_threshold(
+        &self,
+        endpoint: (&str, u16),
+        threshold: Duration,
+    ) -> Result<(Conn, String)> {
+        let mut conn = self.db.connect_to(endpoint).await?;
+        let previous: Option<String> = conn
+            .query_first("SELECT @@GLOBAL.tidb_slow_log_threshold")
+            .await?;
+        let previous = previous.context("reading tidb_slow_log_threshold returned no row")?;
+        conn.query_drop(format!(
+            "SET GLOBAL tidb_slow_log_threshold = {}",
+            threshold.as_millis()
+        ))
+        .await?;
+        Ok((conn, previous))
+    }
+
+    /// Restore the thresholds lowered by [`SlowQueries::start`], each on the connection that
+    /// lowered it. Only the first call restores; also called before exiting on a second
+    /// Ctrl-C.
+    pub async fn restore_threshold(&self) {
+        let restore = std::mem::take(&mut *self.restore.lock().unwrap());
+        for Lowered {
+            endpoint: (host, port),
+            mut conn,
+            previous,
+        } in restore
+        {
+            let restored = conn
+                .query_drop(format!("SET GLOBAL tidb_slow_log_threshold = {previous}"))
+                .await;
+            let _ = conn.disconnect().await;
+            if let Err(e) = restored {
+                eprintln!(
+                    "warning: {host}:{port}: cannot restore tidb_slow_log_threshold to \
+                     {previous}ms: {e}"
+                );
+            }
+        }
+    }
+
+    /// Restore the thresholds and write the records of the run to the file. Call once,
+    /// after the run, interrupted or not.
+    ///
+    /// Servers that do not let the records be read (a missing `PROCESS` privilege, a
+    /// server without the slow query tables) only leave a warning; failing to write the
+    /// file is an error.
+    pub async fn finish(&self) -> Result<()> {
+        let finished = SystemTime::now();
+        self.restore_threshold().await;
+        let Some(started) = *self.started.lock().unwrap() else {
+            return Ok(());
+        };
+        let (table, rows) = match self.query(started, finished).await {
+            Ok(found) => found,
+            Err(e) => {
+                *self.collected.lock().unwrap() = Some(Err(format!("{e:#}")));
+                return Ok(());
+            }
+        };
+        self.write(&rows, table == "CLUSTER_SLOW_QUERY")?;
+        *self.collected.lock().unwrap() = Some(Ok(Collected {
+            table,
+            records: rows.len(),
+        }));
+        Ok(())
+    }
+
+    /// Records between `started` and `finished` from the cluster table, or from the
+    /// instance's table if the cluster table cannot be read.
+    async fn query(
+        &self,
+        started: SystemTime,
+        finished: SystemTime,
+    ) -> Result<(&'static str, Vec<Row>)> {
+        let secs = |t: SystemTime| {
+            t.duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64()
+        };
+        let filter = format!(
+            "WHERE Time BETWEEN FROM_UNIXTIME({:.6}) AND FROM_UNIXTIME({:.6}) \
+             AND DB = {} AND Is_internal = 0 ORDER BY Time LIMIT {MAX_RECORDS}",
+            secs(started) - WINDOW_SLACK.as_secs_f64(),
+            secs(finished) + WINDOW_SLACK.as_secs_f64(),
+            quote_str(&self.db.database)
+        );
+        let mut conn = self.db.connect().await?;
+        let columns = COLUMNS.join(", ");
+        let cluster = conn
+            .query(format!(
+                "SELECT Instance, {columns} FROM INFORMATION_SCHEMA.CLUSTER_SLOW_QUERY {filter}"
+            ))
+            .await;
+        let found = match cluster {
+            Ok(rows) => Ok(("CLUSTER_SLOW_QUERY", rows)),
+            Err(e) => {
+                eprintln!(
+                    "warning: cannot read CLUSTER_SLOW_QUERY, falling back to SLOW_QUERY: {e}"
+                );
+                conn.query(format!(
+                    "SELECT {columns} FROM INFORMATION_SCHEMA.SLOW_QUERY {filter}"
+                ))
+                .await
+                .map(|rows| ("SLOW_QUERY", rows))
+                .context("cannot read the slow query log")
+            }
+        };
+        let _ = conn.disconnect().await;
+        found
+    }
+
+    /// Write `rows` as CSV if the file name ends in `.csv`, as a JSON array of objects
+    /// otherwise.
+    fn write(&self, rows: &[Row], with_instance: bool) -> Result<()> {
+        let mut header = Vec::new();
+        if with_instance {
+            header.push("Instance");
+        }
+        header.extend(COLUMNS);
+        let cells = |row: &Row| -> Vec<Option<String>> {
+            (0..row.len())
+                .map(|i| match row.as_ref(i) {
+                    Some(Value::Bytes(bytes)) => Some(String::from_utf8_lossy(bytes).into_owned()),
+                    Some(Value::NULL) | None => None,
+                    Some(value) => Some(value.as_sql(true)),
+                })
+                .collect()
+        };
+        let csv = self
+            .path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+        let write = || -> Result<()> {
+            if csv {
+                let mut out = csv::Writer::from_path(&self.path)?;
+                out.write_record(&header)?;
+                for row in rows {
+                    out.write_record(cells(row).iter().map(|c| c.as_deref().unwrap_or("")))?;
+                }
+                out.flush()?;
+            } else {
+                let records: Vec<_> = rows
+                    .iter()
+                    .map(|row| Record {
+                        header: &header,
+                        cells: cells(row),
+                    })
+                    .collect();
+                let mut out = std::io::BufWriter::new(std::fs::File::create(&self.path)?);
+                serde_json::to_writer(&mut out, &records)?;
+                out.write_all(b"\n")?;
+                out.flush()?;
+            }
+            Ok(())
+        };
+        write().with_context(|| format!("cannot write slow queries to {}", self.path.display()))
+    }
+
+    /// Print what was collected. Call once, after [`SlowQueries::finish`].
+    pub fn print(&self) {
+        match &*self.collected.lock().unwrap() {
+            Some(Ok(collected)) => {
+                let limited = if collected.records == MAX_RECORDS {
+                    " (limit reached; the earliest are kept)"
+                } else {
+                    ""
+                };
+                eprintln!(
+                    "slow queries: {} records from {} written to {}{limited}",
+                    collected.records,
+                    collected.table,
+                    self.path.display()
+                );
+            }
+            Some(Err(e)) => eprintln!("warning: slow queries not collected: {e}"),
+            None => {}
+        }
+    }
+}
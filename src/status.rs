@@ -0,0 +1,46 @@
+//! Status codes reported in `IterReport::status`.
+//!
+//! Codes are stable across releases so that status breakdowns stay comparable.
+//!
+//! Failed iterations do not use these constants: server errors carry the MySQL/TiDB error
+//! code (e.g. `1213`, `9007`) as a server-error status, a lost connection is client error
+//! `2013` and any other client-side failure is client error `-1` (see [`crate::errors`]).
+
+use rlt::{Status, StatusKind};
+
+/// Plain successful iteration.
+pub const OK: i64 = 0;
+
+/// Transaction rolled back to a savepoint before committing the rest.
+pub const ROLLED_BACK: i64 = 1;
+
+/// Transaction committed, but its locking read had to wait for another transaction.
+pub const LOCK_WAITED: i64 = 2;
+
+/// Successful iteration that ran while workers were still ramping up.
+pub const RAMP_UP: i64 = 100;
+
+/// Client-detected error: a row read back in the same transaction did not match what was written.
+pub const READ_MISMATCH: i64 = 1000;
+
+/// Client-detected error: a query over a known key range returned a different number of rows,
+/// or (with `--expect-full` or `--verify-writes`) an INSERT affected a different number of rows than it carried.
+pub const ROW_COUNT_MISMATCH: i64 = 1001;
+
+/// Client-detected error (with `--verify-writes`): an INSERT into a table with a generated
+/// key affected rows but reported no `last_insert_id`.
+pub const MISSING_INSERT_ID: i64 = 1002;
+
+/// Client-detected error: a write reported an affected-rows count its statement cannot
+/// produce, e.g. a single-row INSERT that affected no row (see [`crate::affected`]).
+pub const AFFECTED_ROWS_UNEXPECTED: i64 = 1003;
+
+/// Name of the kind of `status`, as written to the `--results-json` and `--latency-log` files.
+pub fn kind_name(status: &Status) -> &'static str {
+    match status.kind() {
+        StatusKind::Success => "success",
+        StatusKind::ClientError => "client_error",
+        StatusKind::ServerError => "server_error",
+        StatusKind::Error => "error",
+    }
+}
@@ -0,0 +1,307 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{ensure, Result};
+use async_trait::async_trait;
+use clap::Parser;
+use mysql_async::prelude::*;
+use mysql_async::Conn;
+use rand::Rng;
+use rlt::{BenchSuite, IterInfo, IterReport, Status};
+use tokio::time::Instant;
+
+use crate::affected::{UnexpectedWrites, WriteKind};
+use crate::distributions::Uniform;
+use crate::errors::lock_conflict_code;
+use crate::harness::{Harness, Outcome, PhaseClock, Phases, RunOpts};
+use crate::lifecycle::Lifecycle;
+use crate::plan::PlanStatement;
+use crate::seed::{prepare_table, SeedOpts, SeedSpec};
+use crate::session::Session;
+use crate::suites::{BenchOpts, Benchmark};
+use crate::{payload, status, DbOpts, Role};
+
+const VALUES: Uniform = Uniform::new(1000);
+
+/// TiDB pessimistic range-lock benchmark.
+///
+/// Rows are seeded at even ids only. Each iteration runs `BEGIN PESSIMISTIC`, locks a random
+/// id range with `SELECT ... FOR UPDATE`, inserts (and deletes again) a row in one of the gaps
+/// and commits. Ranges of concurrent workers overlap, so the run shows how often they block
+/// (status code 2) or conflict (server-error status with the MySQL error code).
+#[derive(Parser, Clone)]
+pub struct GapLockOpts {
+    #[command(flatten)]
+    pub db: DbOpts,
+
+    /// Number of preloaded rows (at ids 0, 2, 4, ...).
+    #[clap(long, default_value_t = 10000, value_parser = clap::value_parser!(u32).range(1..))]
+    pub rows: u32,
+
+    /// Width of the locked id range; at least 2 and at most twice `--rows`.
+    #[clap(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(2..))]
+    pub range_width: u32,
+
+    /// Locking reads slower than this are counted as having waited for a lock.
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "5ms")]
+    pub lock_wait_threshold: Duration,
+
+    #[command(flatten)]
+    pub seed: SeedOpts,
+
+    #[command(flatten)]
+    pub run: RunOpts,
+}
+
+impl BenchOpts for GapLockOpts {
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn db_mut(&mut self) -> &mut DbOpts {
+        &mut self.db
+    }
+
+    fn run(&self) -> &RunOpts {
+        &self.run
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = self.db.settings();
+        settings.push(("range width", self.range_width.to_string()));
+        settings.extend(self.run.settings());
+        settings
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.range_width as u64 <= self.rows as u64 * 2,
+            "--range-width {} exceeds the seeded id space of --rows {} (ids 0..{})",
+            self.range_width,
+            self.rows,
+            self.rows as u64 * 2
+        );
+        Ok(())
+    }
+}
+
+/// `bench-gaplock`: pessimistic locking reads over id ranges, with inserts into their gaps.
+#[derive(Clone)]
+pub struct GapLockBench {
+    db: DbOpts,
+    rows: u32,
+    range_width: u32,
+    lock_wait_threshold: Duration,
+    seed: SeedOpts,
+    harness: Harness,
+    lifecycle: Arc<Lifecycle>,
+    unexpected: Arc<UnexpectedWrites>,
+}
+
+impl Benchmark for GapLockBench {
+    type Opts = GapLockOpts;
+    const NAME: &'static str = "bench-gaplock";
+    const PRIVILEGES: &'static [&'static str] = &["DELETE", "INSERT", "SELECT"];
+
+    fn new(opts: &GapLockOpts, workers: u32) -> Self {
+        Self {
+            db: opts.db.clone(),
+            rows: opts.rows,
+            range_width: opts.range_width,
+            lock_wait_threshold: opts.lock_wait_threshold,
+            seed: opts.seed.clone(),
+            harness: Harness::new(&opts.run, &opts.db, workers),
+            lifecycle: Arc::new(Lifecycle::new(workers)),
+            unexpected: Arc::default(),
+        }
+    }
+
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn harness(&self) -> &Harness {
+        &self.harness
+    }
+
+    fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    fn print_summaries(&self) {
+        self.unexpected.print();
+    }
+}
+
+impl GapLockBench {
+    /// The locking read of `lo..=hi`, and the INSERT and DELETE of the row at `gap`.
+    fn statements(&self, lo: u64, hi: u64, gap: u64) -> [String; 3] {
+        let table = self.db.quoted_table();
+        [
+            format!("SELECT id FROM {table} WHERE id BETWEEN {lo} AND {hi} FOR UPDATE"),
+            format!("INSERT INTO {table} (id, value) VALUES ({gap}, 0)"),
+            format!("DELETE FROM {table} WHERE id = {gap}"),
+        ]
+    }
+
+    /// The statements of an iteration, on a range past the seeded ids that no worker locks.
+    fn plan_statements(&self) -> Vec<PlanStatement> {
+        let lo = self.rows as u64 * 2;
+        let [lock, insert, delete] = self.statements(lo, lo + self.range_width as u64 - 1, lo | 1);
+        vec![
+            PlanStatement::text("locking read", lock).write(),
+            PlanStatement::text("gap insert", insert).write(),
+            PlanStatement::text("gap delete", delete).write(),
+        ]
+    }
+
+    /// Lock `lo..=hi`, fill the gap at `gap` and empty it again; returns rows locked, whether
+    /// the locking read had to wait, whether the INSERT and DELETE each affected their row
+    /// and the transaction's phases.
+    async fn lock_and_fill(
+        &self,
+        conn: &mut Conn,
+        lo: u64,
+        hi: u64,
+        gap: u64,
+    ) -> Result<(u64, bool, bool, Phases)> {
+        let [lock, insert, delete] = self.statements(lo, hi, gap);
+        let mut clock = PhaseClock::start();
+        conn.query_drop("BEGIN PESSIMISTIC").await?;
+        clock.begun();
+        let t = Instant::now();
+        let locked: Vec<i64> = conn.query(lock).await?;
+        let waited = t.elapsed() > self.lock_wait_threshold;
+        conn.query_drop(insert).await?;
+        let inserted = WriteKind::Insert.interpret(1, conn.affected_rows());
+        conn.query_drop(delete).await?;
+        let deleted = WriteKind::Delete.interpret(1, conn.affected_rows());
+        clock.executed();
+        conn.query_drop("COMMIT").await?;
+        let expected = inserted.full && deleted.full;
+        Ok((locked.len() as u64, waited, expected, clock.committed()))
+    }
+}
+
+#[async_trait]
+impl BenchSuite for GapLockBench {
+    type WorkerState = Session;
+
+    async fn setup(&mut self, worker_id: u32) -> Result<Self::WorkerState> {
+        let mut conn = self.db.connect_worker(worker_id, Role::Write).await?;
+
+        self.lifecycle
+            .setup_once(self.db.undo_on_error(async {
+                let spec = SeedSpec {
+                    table: &self.db.table,
+                    schema: "id BIGINT PRIMARY KEY, value INT",
+                    columns: "id, value",
+                    rows: self.rows as u64,
+                    row: Arc::new(|i| format!("({}, {})", i * 2, VALUES.value_at(i))),
+                    ids: 0..=(self.rows as i64 - 1) * 2,
+                    generator: "gaplock-v1",
+                    csv: None,
+                    created_at: false,
+                };
+                prepare_table(&mut conn, &self.db, &self.seed, spec).await?;
+                self.harness.plan_statements(|| self.plan_statements());
+                Ok(())
+            }))
+            .await?;
+        self.db.ping(&mut conn, worker_id, Role::Write).await?;
+        Ok(self.db.session(conn).await)
+    }
+
+    async fn bench(&mut self, session: &mut Session, info: &IterInfo) -> Result<IterReport> {
+        let max_id = self.rows as u64 * 2 - 1;
+        let width = self.range_width as u64;
+        let (lo, hi, gap) = {
+            let mut rng = rand::thread_rng();
+            let lo = rng.gen_range(0..=max_id + 1 - width);
+            let hi = lo + width - 1;
+            // Odd ids are never seeded, so this always lands in a gap.
+            let (first_gap, last_gap) = (lo | 1, (hi - 1) | 1);
+            (
+                lo,
+                hi,
+                first_gap + 2 * rng.gen_range(0..=(last_gap - first_gap) / 2),
+            )
+        };
+
+        let report = self
+            .harness
+            .iter(info, async {
+                let mut lease = session.get().await;
+                let conn = &mut *lease;
+                match self.lock_and_fill(conn, lo, hi, gap).await {
+                    Ok((locked, waited, expected, phases)) => {
+                        if !self.unexpected.check(expected) {
+                            return Ok(Outcome::failure(Status::client_error(
+                                status::AFFECTED_ROWS_UNEXPECTED,
+                            )));
+                        }
+                        let code = if waited {
+                            status::LOCK_WAITED
+                        } else {
+                            status::OK
+                        };
+                        Ok(Outcome {
+                            status: Status::success(code),
+                            ..Outcome::success(locked, locked * payload::BIGINT)
+                                .with_phases(Some(phases))
+                        })
+                    }
+                    Err(e) => {
+                        lease.fail();
+                        match lock_conflict_code(&e) {
+                            Some(code) => Ok(Outcome::failure(Status::server_error(code as i64))),
+                            None => Err(e),
+                        }
+                    }
+                }
+            })
+            .await;
+        // After the timed iteration, so rolling back and reconnecting are not latency.
+        let recovered = session.recover(&self.db, info.worker_id, Role::Write).await;
+        let report = report?;
+        recovered?;
+        Ok(report)
+    }
+
+    async fn teardown(self, mut session: Session, _info: IterInfo) -> Result<()> {
+        if session.finish(&self.lifecycle).await {
+            self.harness.capture_final_plans().await;
+            let mut conn = session.get().await;
+            self.db.drop_table(&mut conn, &self.db.table).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(args: &[&str]) -> Result<GapLockOpts> {
+        let opts =
+            GapLockOpts::try_parse_from(std::iter::once("test").chain(args.iter().copied()))?;
+        opts.validate()?;
+        Ok(opts)
+    }
+
+    #[test]
+    fn zero_rows_and_narrow_ranges_are_rejected() {
+        assert!(opts(&["--rows", "0"]).is_err());
+        assert!(opts(&["--range-width", "0"]).is_err());
+        assert!(opts(&["--range-width", "1"]).is_err());
+    }
+
+    #[test]
+    fn range_width_is_bounded_by_the_seeded_ids() {
+        assert!(opts(&["--rows", "5", "--range-width", "10"]).is_ok());
+        let err = opts(&["--rows", "5", "--range-width", "11"]).err().unwrap();
+        assert!(err.to_string().contains("--range-width 11"), "{err}");
+        // Twice `--rows` does not fit `u32`; the check must not overflow.
+        assert!(opts(&["--rows", "4294967295", "--range-width", "4294967295"]).is_ok());
+    }
+}
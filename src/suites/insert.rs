@@ -0,0 +1,1399 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::{ensure, Result};
+use async_trait::async_trait;
+use clap::Parser;
+use mysql_async::prelude::*;
+use mysql_async::{Conn, Row, TxOpts, Value};
+use rlt::{BenchSuite, IterInfo, IterReport, Status};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::affected::{Affected, UnexpectedWrites, WriteKind};
+use crate::distributions::{ValueOpts, Values};
+use crate::errors::{is_transient, server_code};
+use crate::harness::{Harness, Outcome, PhaseClock, Phases, RowCounts, RunOpts};
+use crate::lifecycle::Lifecycle;
+use crate::plan::{PlanStatement, SACRIFICIAL_WORKER};
+use crate::session::rollback_open;
+use crate::suites::{BenchOpts, Benchmark};
+use crate::{payload, quote_ident, status, worker_key, DbOpts, Protocol, Role, TxMode, TABLE_TAG};
+
+const MAX_PIPELINE_DEPTH: u32 = 64;
+/// MySQL `ER_DUP_ENTRY`.
+const DUPLICATE_KEY: u16 = 1062;
+
+/// TiDB's default `max_allowed_packet`, if the server does not report one.
+const DEFAULT_MAX_PACKET: usize = 64 << 20;
+
+/// Generated `data` values are this prefix followed by the row's worker key.
+const DATA_PREFIX: &str = "bench_data_";
+
+/// Suffix of the table next to `--table` that records, one row per run, the first worker id
+/// above the key ranges the run used. A later `--no-create` run starts above its indexed
+/// maximum.
+const KEY_RANGES_SUFFIX: &str = "key_ranges";
+
+const KEY_RANGES_SCHEMA: &str = "next_worker INT UNSIGNED PRIMARY KEY";
+
+/// TiDB INSERT benchmark.
+#[derive(Parser, Clone)]
+pub struct InsertOpts {
+    #[command(flatten)]
+    pub db: DbOpts,
+
+    /// Number of rows to insert per batch.
+    #[clap(long, short = 'b', default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub batch_size: u32,
+
+    /// Number of transactions kept in flight per worker, each on its own connection.
+    #[clap(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..=MAX_PIPELINE_DEPTH as i64)
+    )]
+    pub pipeline_depth: u32,
+
+    /// Group the batches of every N iterations into one explicit transaction, committed by
+    /// the N-th, regardless of `--tx-mode`; 1 commits every batch on its own.
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub commit_every: Option<u32>,
+
+    /// After the run, print the average client-side prep time per batch next to the
+    /// server round-trip time.
+    #[clap(long)]
+    pub prep_breakdown: bool,
+
+    /// Fail iterations whose INSERTs affected fewer (or more) rows than the batch carried.
+    #[clap(long)]
+    pub expect_full: bool,
+
+    /// Check what every INSERT reports back: the affected rows must match the rows it
+    /// carried, duplicates included, and a generated key must come with a `last_insert_id`.
+    ///
+    /// Implies `--expect-full`. Mismatches fail the iteration with client error 1001 (rows)
+    /// or 1002 (id).
+    #[clap(long)]
+    pub verify_writes: bool,
+
+    /// What to do with rows whose key already exists, e.g. when writing client-generated
+    /// keys into an existing table with `--no-create --key-layout scattered`.
+    #[clap(long, value_enum, default_value = "fail")]
+    pub on_duplicate: OnDuplicate,
+
+    /// With `--no-create`, create the `<table>_key_ranges` table that records the worker key
+    /// ranges of earlier runs if it is missing, instead of failing.
+    #[clap(long, requires = "no_create")]
+    pub create_key_ranges: bool,
+
+    /// How values are sent: a prepared statement with bound values, or SQL text.
+    #[clap(long, value_enum, default_value = "binary")]
+    pub protocol: Protocol,
+
+    /// How the primary key is assigned.
+    ///
+    /// The default `auto-increment` key sends every insert to the same TiDB region. With
+    /// `--no-create` only `scattered` matters: it inserts explicit ids.
+    #[clap(long, value_enum, default_value = "auto-increment")]
+    pub key_layout: KeyLayout,
+
+    /// After the run, print how the table's regions and writes are spread over TiKV stores.
+    #[clap(long)]
+    pub show_regions: bool,
+
+    /// Create a secondary index on `value`, adding index maintenance to every insert.
+    #[clap(long, conflicts_with = "no_create")]
+    pub index_value: bool,
+
+    #[command(flatten)]
+    pub values: ValueOpts,
+
+    #[command(flatten)]
+    pub run: RunOpts,
+}
+
+impl BenchOpts for InsertOpts {
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn db_mut(&mut self) -> &mut DbOpts {
+        &mut self.db
+    }
+
+    fn run(&self) -> &RunOpts {
+        &self.run
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = self.db.settings();
+        settings.push(("protocol", format!("{:?}", self.protocol).to_lowercase()));
+        if let Some(n) = self.commit_every {
+            settings.push((
+                "commit every",
+                format!("{n} batches (explicit transaction)"),
+            ));
+        }
+        settings.push(("key layout", format!("{:?}", self.key_layout)));
+        settings.push(("on duplicate", format!("{:?}", self.on_duplicate)));
+        settings.push((
+            "value index",
+            if self.index_value { "yes" } else { "no" }.into(),
+        ));
+        settings.extend(self.values.settings());
+        settings.extend(self.run.settings());
+        settings
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.db.connections.is_none(),
+            "bench-insert keeps its own connections per pipeline slot and does not support \
+             --connections; use --pipeline-depth instead"
+        );
+        ensure!(
+            self.commit_every.is_none() || self.pipeline_depth == 1,
+            "--commit-every keeps one transaction open per worker and cannot be combined with \
+             --pipeline-depth"
+        );
+        // A pipelined batch is timed from its own submission, not from the schedule slot of
+        // the iteration that submitted it, so queueing behind the schedule would go unseen.
+        ensure!(
+            self.run.target_rate.is_none() || self.pipeline_depth == 1,
+            "--pipeline-depth times each batch from its submission and cannot be combined with \
+             --target-rate, which measures latency from the scheduled start"
+        );
+        Ok(())
+    }
+}
+
+/// `bench-insert`: batched INSERTs, optionally pipelined over several connections per
+/// worker.
+#[derive(Clone)]
+pub struct InsertBench {
+    db: DbOpts,
+    batch_size: u32,
+    pipeline_depth: u32,
+    commit_every: Option<u32>,
+    protocol: Protocol,
+    key_layout: KeyLayout,
+    index_value: bool,
+    show_regions: bool,
+    expect_full: bool,
+    verify_writes: bool,
+    /// Iterations failed by `--verify-writes`.
+    write_mismatches: Arc<AtomicU64>,
+    on_duplicate: OnDuplicate,
+    create_key_ranges: bool,
+    prep_breakdown: bool,
+    values: Values,
+    /// Added to every worker id, so keys and values generated in this run stay clear of
+    /// those earlier runs left in a `--no-create` table. Set during setup.
+    worker_offset: Arc<OnceLock<u32>>,
+    workers: u32,
+    /// Duplicate keys met: failed batches, skipped rows or updated rows.
+    duplicates: Arc<AtomicU64>,
+    row_counts: Arc<RowCounts>,
+    unexpected: Arc<UnexpectedWrites>,
+    /// Region summary taken by the last worker before the table is dropped.
+    regions: Arc<Mutex<Option<String>>>,
+    breakdown: Arc<Breakdown>,
+    groups: Arc<CommitGroups>,
+    harness: Harness,
+    lifecycle: Arc<Lifecycle>,
+}
+
+/// What became of the `--commit-every` transactions, summed over all workers.
+#[derive(Default)]
+struct CommitGroups {
+    committed: AtomicU64,
+    /// Transactions rolled back after an error, and the rows their earlier iterations had
+    /// already reported as inserted.
+    rolled_back: AtomicU64,
+    lost_rows: AtomicU64,
+    /// Transactions still open at the end of the run, committed at teardown, and the
+    /// batches they held.
+    partial: AtomicU64,
+    partial_batches: AtomicU64,
+}
+
+impl CommitGroups {
+    fn print(&self, every: u32) {
+        eprintln!(
+            "transactions of {every} batches: {} committed",
+            self.committed.load(Ordering::Relaxed)
+        );
+        let rolled_back = self.rolled_back.load(Ordering::Relaxed);
+        if rolled_back > 0 {
+            eprintln!(
+                "  {rolled_back} rolled back after an error, losing {} rows already reported \
+                 as inserted",
+                self.lost_rows.load(Ordering::Relaxed)
+            );
+        }
+        let partial = self.partial.load(Ordering::Relaxed);
+        if partial > 0 {
+            eprintln!(
+                "  {partial} partial ({} batches in total) committed at the end of the run",
+                self.partial_batches.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+/// Where the time of each batch goes, summed over all workers.
+#[derive(Default)]
+struct Breakdown {
+    batches: AtomicU64,
+    /// Batches that had to be split to fit the max packet size.
+    split_batches: AtomicU64,
+    statements: AtomicU64,
+    prep_nanos: AtomicU64,
+    server_nanos: AtomicU64,
+}
+
+impl Breakdown {
+    fn record_prep(&self, batches: &[Batch], prep: Duration) {
+        let statements = batches.iter().map(|b| b.statements.len() as u64).sum();
+        let split = batches.iter().filter(|b| b.statements.len() > 1).count() as u64;
+        self.batches
+            .fetch_add(batches.len() as u64, Ordering::Relaxed);
+        self.statements.fetch_add(statements, Ordering::Relaxed);
+        self.split_batches.fetch_add(split, Ordering::Relaxed);
+        self.prep_nanos
+            .fetch_add(prep.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn record_server(&self, elapsed: Duration) {
+        self.server_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn print(&self) {
+        let batches = self.batches.load(Ordering::Relaxed).max(1);
+        let avg = |nanos: &AtomicU64| Duration::from_nanos(nanos.load(Ordering::Relaxed) / batches);
+        eprintln!("per batch");
+        eprintln!(
+            "  client prep (not in latency)  {:?}",
+            avg(&self.prep_nanos)
+        );
+        eprintln!(
+            "  server round trip             {:?}",
+            avg(&self.server_nanos)
+        );
+    }
+
+    /// Say how many batches were split to stay under the max packet size, if any.
+    fn print_splits(&self, max_packet: &str) {
+        let split = self.split_batches.load(Ordering::Relaxed);
+        if split > 0 {
+            eprintln!(
+                "{split} of {} batches split into sub-batches to fit max packet size {max_packet} \
+                 ({} statements in total)",
+                self.batches.load(Ordering::Relaxed),
+                self.statements.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+impl Benchmark for InsertBench {
+    type Opts = InsertOpts;
+    const NAME: &'static str = "bench-insert";
+    const PRIVILEGES: &'static [&'static str] = &["INSERT", "SELECT", "UPDATE"];
+
+    /// Warns when the key layout is going to funnel all writers into one region.
+    fn new(opts: &InsertOpts, workers: u32) -> Self {
+        let writers = workers * opts.pipeline_depth;
+        if opts.key_layout == KeyLayout::AutoIncrement && !opts.db.no_create && writers > 1 {
+            eprintln!(
+                "warning: {writers} concurrent writers share an AUTO_INCREMENT primary key, so on \
+                 TiDB every insert lands in the same region and throughput is capped by one TiKV \
+                 node; use --key-layout auto-random, scattered or shard-row-id to spread the writes"
+            );
+        }
+        Self {
+            db: opts.db.clone(),
+            batch_size: opts.batch_size,
+            pipeline_depth: opts.pipeline_depth,
+            commit_every: opts.commit_every,
+            protocol: opts.protocol,
+            key_layout: opts.key_layout,
+            index_value: opts.index_value,
+            show_regions: opts.show_regions,
+            expect_full: opts.expect_full,
+            verify_writes: opts.verify_writes,
+            write_mismatches: Arc::default(),
+            on_duplicate: opts.on_duplicate,
+            create_key_ranges: opts.create_key_ranges,
+            prep_breakdown: opts.prep_breakdown,
+            values: opts.values.values(),
+            worker_offset: Arc::default(),
+            workers,
+            duplicates: Arc::default(),
+            row_counts: Arc::default(),
+            unexpected: Arc::default(),
+            regions: Arc::default(),
+            breakdown: Arc::default(),
+            groups: Arc::default(),
+            harness: Harness::new(&opts.run, &opts.db, workers),
+            lifecycle: Arc::new(Lifecycle::new(workers)),
+        }
+    }
+
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn harness(&self) -> &Harness {
+        &self.harness
+    }
+
+    fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    fn tables(&self) -> Vec<String> {
+        vec![self.db.table.clone(), self.db.aux_table(KEY_RANGES_SUFFIX)]
+    }
+
+    fn print_summaries(&self) {
+        self.row_counts.print();
+        self.unexpected.print();
+        let duplicates = self.duplicates.load(Ordering::Relaxed);
+        if duplicates > 0 {
+            let what = match self.on_duplicate {
+                OnDuplicate::Fail => "batches failed on a duplicate key",
+                OnDuplicate::Skip => "duplicate rows skipped",
+                OnDuplicate::Update => "or more duplicate rows updated or left as they were",
+            };
+            eprintln!("{duplicates} {what}");
+        }
+        if let Some(regions) = self.regions.lock().unwrap().take() {
+            eprintln!("{regions}");
+        }
+        let max_packet = self
+            .db
+            .max_packet
+            .map_or("(server default)".to_owned(), |n| n.to_string());
+        self.breakdown.print_splits(&max_packet);
+        let mismatches = self.write_mismatches.load(Ordering::Relaxed);
+        if mismatches > 0 {
+            eprintln!("warning: {mismatches} batches failed --verify-writes");
+        }
+        if let Some(every) = self.commit_every {
+            self.groups.print(every);
+        }
+        if self.prep_breakdown {
+            self.breakdown.print();
+        }
+    }
+}
+
+impl InsertBench {
+    /// Build the queries for the worker's next `count` batches before any timing starts.
+    fn prepare(&self, worker: &InsertWorker, count: usize) -> Result<Vec<Batch>> {
+        let t = Instant::now();
+        let batches = (worker.seq..worker.seq + count as u64)
+            .map(|seq| self.build_batch(worker.worker_id, seq, worker.max_packet))
+            .collect::<Result<Vec<_>>>()?;
+        self.breakdown.record_prep(&batches, t.elapsed());
+        Ok(batches)
+    }
+
+    /// Build batch `seq` of a worker as multi-row INSERTs, starting a new statement whenever
+    /// the next row would push the current one past `max_packet` bytes. Fails once the batch
+    /// would run past the worker's key range.
+    fn build_batch(&self, worker_id: u32, seq: u64, max_packet: usize) -> Result<Batch> {
+        let table = self.db.quoted_table();
+        let head = format!(
+            "{} INTO {table} ({}) VALUES ",
+            self.on_duplicate.verb(),
+            self.key_layout.columns()
+        );
+        let tail = self.on_duplicate.suffix();
+        let offset = *self
+            .worker_offset
+            .get()
+            .expect("worker offset is set during setup");
+        let worker_id = offset.saturating_add(worker_id);
+        let first = seq.saturating_mul(self.batch_size as u64);
+        let counter = worker_key(worker_id, first)?;
+        worker_key(worker_id, first + self.batch_size as u64 - 1)?;
+        let mut batch = Batch {
+            statements: Vec::new(),
+            bytes: 0,
+        };
+        // One extra byte for the command in front of the statement.
+        let empty_len = head.len() + tail.len() + 1;
+        let mut rows = Vec::new();
+        let mut len = empty_len;
+        for i in 0..self.batch_size {
+            let c = counter + i as u64;
+            let data = self.values.data_at(DATA_PREFIX, c);
+            batch.bytes += payload::string(&data) + payload::INT;
+            let mut row = vec![Value::from(data), Value::from(self.values.value_at(c))];
+            if self.key_layout == KeyLayout::Scattered {
+                batch.bytes += payload::BIGINT;
+                row.insert(0, Value::from(scattered_key(c)));
+            }
+            let row_len = self.protocol.row_len(&row);
+            let full =
+                len + row_len > max_packet || rows.len() == self.protocol.max_rows(row.len());
+            if !rows.is_empty() && full {
+                let rows = std::mem::take(&mut rows);
+                batch
+                    .statements
+                    .push(self.protocol.insert(&head, rows, tail));
+                len = empty_len;
+            }
+            len += row_len;
+            rows.push(row);
+        }
+        batch
+            .statements
+            .push(self.protocol.insert(&head, rows, tail));
+        Ok(batch)
+    }
+
+    /// The first INSERT of a batch, on keys no worker writes.
+    fn plan_statements(&self) -> Vec<PlanStatement> {
+        let offset = *self
+            .worker_offset
+            .get()
+            .expect("worker offset is set during setup");
+        let max_packet = self.db.max_packet.unwrap_or(DEFAULT_MAX_PACKET);
+        // `build_batch` adds the offset back.
+        let Ok(batch) = self.build_batch(SACRIFICIAL_WORKER.saturating_sub(offset), 0, max_packet)
+        else {
+            return Vec::new();
+        };
+        let Some(insert) = batch.statements.into_iter().next() else {
+            return Vec::new();
+        };
+        vec![PlanStatement {
+            label: "insert batch",
+            sql: insert.sql,
+            params: insert.params,
+            write: true,
+        }]
+    }
+
+    /// Run a batch in the worker's `--commit-every` transaction, beginning one if none is
+    /// open and committing it with the `every`-th batch, so that iteration's latency includes
+    /// the commit.
+    async fn insert_grouped(
+        &self,
+        worker: &mut InsertWorker,
+        batch: Batch,
+        info: &IterInfo,
+        every: u32,
+    ) -> Result<IterReport> {
+        let conn = &mut worker.idle[0];
+        let open_batches = &mut worker.open_batches;
+        let open_rows = &mut worker.open_rows;
+        let mut failed = false;
+        let report = self
+            .harness
+            .iter(info, async {
+                let t = Instant::now();
+                let result = async {
+                    if *open_batches == 0 {
+                        conn.query_drop("BEGIN").await?;
+                    }
+                    let mut written = Written::default();
+                    for stmt in batch.statements {
+                        stmt.run(&mut *conn, &mut written).await?;
+                    }
+                    if *open_batches + 1 == every {
+                        conn.query_drop("COMMIT").await?;
+                    }
+                    anyhow::Ok(written)
+                }
+                .await;
+                let written = match result {
+                    Ok(written) => written,
+                    Err(e) => {
+                        if *open_batches > 0 {
+                            self.groups.rolled_back.fetch_add(1, Ordering::Relaxed);
+                            self.groups
+                                .lost_rows
+                                .fetch_add(*open_rows, Ordering::Relaxed);
+                        }
+                        *open_batches = 0;
+                        *open_rows = 0;
+                        failed = true;
+                        return self.duplicate_failure(e);
+                    }
+                };
+                *open_batches += 1;
+                *open_rows += written.affected;
+                if *open_batches == every {
+                    self.groups.committed.fetch_add(1, Ordering::Relaxed);
+                    *open_batches = 0;
+                    *open_rows = 0;
+                }
+                self.breakdown.record_server(t.elapsed());
+                Ok(self.outcome(written, batch.bytes))
+            })
+            .await;
+        if failed {
+            self.db
+                .recover(&mut worker.idle[0], info.worker_id, Role::Write)
+                .await?;
+        }
+        report
+    }
+
+    /// Commit a `--commit-every` transaction the run left open; its batches were reported
+    /// when they were inserted.
+    async fn commit_partial(&self, worker: &mut InsertWorker) {
+        if worker.open_batches == 0 {
+            return;
+        }
+        match worker.idle[0].query_drop("COMMIT").await {
+            Ok(()) => {
+                self.groups.partial.fetch_add(1, Ordering::Relaxed);
+                self.groups
+                    .partial_batches
+                    .fetch_add(worker.open_batches as u64, Ordering::Relaxed);
+            }
+            Err(e) => {
+                eprintln!("warning: cannot commit the open --commit-every transaction: {e}");
+                self.groups.rolled_back.fetch_add(1, Ordering::Relaxed);
+                self.groups
+                    .lost_rows
+                    .fetch_add(worker.open_rows, Ordering::Relaxed);
+            }
+        }
+        worker.open_batches = 0;
+        worker.open_rows = 0;
+    }
+
+    /// Report a batch by the rows it actually inserted.
+    fn outcome(&self, written: Written, bytes: u64) -> Outcome {
+        let rows = self.batch_size as u64;
+        let affected = self.on_duplicate.kind().interpret(rows, written.affected);
+        if !self.unexpected.check(affected.plausible) {
+            return Outcome::failure(Status::client_error(status::AFFECTED_ROWS_UNEXPECTED));
+        }
+        if self.verify_writes {
+            if let Some(code) = self.verify(&affected, written.without_id) {
+                self.write_mismatches.fetch_add(1, Ordering::Relaxed);
+                return Outcome::failure(Status::client_error(code));
+            }
+        }
+        self.duplicates
+            .fetch_add(affected.duplicates, Ordering::Relaxed);
+        // Duplicates explain any difference in the other strategies.
+        if self.on_duplicate == OnDuplicate::Fail {
+            let full = self.row_counts.check(rows, written.affected);
+            if (self.expect_full || self.verify_writes) && !full {
+                return Outcome::failure(Status::client_error(status::ROW_COUNT_MISMATCH));
+            }
+        }
+        Outcome::success(affected.written, bytes)
+    }
+
+    /// The `--verify-writes` status code of a batch that did not write what it carried, if
+    /// any.
+    fn verify(&self, affected: &Affected, without_id: u64) -> Option<i64> {
+        // Fail is also checked against the row counts, so it is reported with them, and any
+        // plausible count is a valid mix of inserts and updates.
+        if self.on_duplicate == OnDuplicate::Skip && !affected.full {
+            return Some(status::ROW_COUNT_MISMATCH);
+        }
+        let generated = self.key_layout != KeyLayout::Scattered && !self.db.no_create;
+        (generated && without_id > 0).then_some(status::MISSING_INSERT_ID)
+    }
+
+    /// Report a batch that hit a duplicate key under `--on-duplicate fail` as a failed
+    /// iteration instead of aborting the run; any other error is passed on.
+    fn duplicate_failure(&self, err: anyhow::Error) -> Result<Outcome> {
+        match server_code(&err) {
+            Some(DUPLICATE_KEY) if self.on_duplicate == OnDuplicate::Fail => {
+                self.duplicates.fetch_add(1, Ordering::Relaxed);
+                Ok(Outcome::failure(Status::server_error(DUPLICATE_KEY as i64)))
+            }
+            _ => Err(err),
+        }
+    }
+}
+
+/// What an INSERT does with rows whose key already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OnDuplicate {
+    /// Fail the batch with error 1062 and go on with the next one.
+    Fail,
+    /// `INSERT IGNORE`: drop the duplicate rows and insert the rest.
+    Skip,
+    /// `ON DUPLICATE KEY UPDATE`: overwrite the existing rows.
+    Update,
+}
+
+impl OnDuplicate {
+    /// How the affected rows of the statement are read.
+    fn kind(self) -> WriteKind {
+        match self {
+            OnDuplicate::Fail => WriteKind::Insert,
+            OnDuplicate::Skip => WriteKind::InsertIgnore,
+            OnDuplicate::Update => WriteKind::Upsert,
+        }
+    }
+
+    fn verb(self) -> &'static str {
+        match self {
+            OnDuplicate::Skip => "INSERT IGNORE",
+            _ => "INSERT",
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            OnDuplicate::Update => {
+                " ON DUPLICATE KEY UPDATE data = VALUES(data), value = VALUES(value)"
+            }
+            _ => "",
+        }
+    }
+}
+
+/// How the primary key of the benchmark table is assigned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeyLayout {
+    /// `AUTO_INCREMENT` primary key; on TiDB every insert lands in the last region.
+    AutoIncrement,
+    /// `AUTO_RANDOM` primary key, scattered by TiDB.
+    AutoRandom,
+    /// Client-generated keys spread over the whole key space.
+    Scattered,
+    /// Non-clustered `AUTO_INCREMENT` key with `SHARD_ROW_ID_BITS`, scattering the row ids.
+    ShardRowId,
+}
+
+impl KeyLayout {
+    fn id_column(self) -> &'static str {
+        match self {
+            KeyLayout::AutoIncrement => "id BIGINT PRIMARY KEY AUTO_INCREMENT",
+            KeyLayout::AutoRandom => "id BIGINT PRIMARY KEY AUTO_RANDOM",
+            KeyLayout::Scattered => "id BIGINT PRIMARY KEY",
+            KeyLayout::ShardRowId => "id BIGINT AUTO_INCREMENT",
+        }
+    }
+
+    /// Primary key declared after the columns, if not inline in [`KeyLayout::id_column`].
+    fn primary_key(self) -> Option<&'static str> {
+        match self {
+            KeyLayout::ShardRowId => Some("PRIMARY KEY (id) NONCLUSTERED"),
+            _ => None,
+        }
+    }
+
+    fn table_options(self) -> &'static str {
+        match self {
+            KeyLayout::ShardRowId => "SHARD_ROW_ID_BITS = 4 PRE_SPLIT_REGIONS = 4",
+            _ => "",
+        }
+    }
+
+    /// Columns filled by the benchmark's INSERTs.
+    fn columns(self) -> &'static str {
+        match self {
+            KeyLayout::Scattered => "id, data, value",
+            _ => "data, value",
+        }
+    }
+}
+
+/// Attempts at reserving worker key ranges when concurrent runs conflict.
+const RESERVE_ATTEMPTS: u32 = 5;
+
+/// Check that the `ranges` table (unquoted) of a `--no-create` `table` exists, creating it
+/// if `create` is set.
+async fn require_key_ranges(
+    conn: &mut Conn,
+    table: &str,
+    ranges: &str,
+    create: bool,
+) -> Result<()> {
+    let exists: Option<u8> = conn
+        .exec_first(
+            "SELECT 1 FROM information_schema.TABLES \
+             WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ?",
+            (ranges,),
+        )
+        .await?;
+    if exists.is_some() {
+        return Ok(());
+    }
+    let quoted = quote_ident(ranges);
+    let create_sql = format!("CREATE TABLE {quoted} ({KEY_RANGES_SCHEMA}) COMMENT = '{TABLE_TAG}'");
+    ensure!(
+        create,
+        "{quoted} does not exist; runs record the worker key ranges they used on {} there so \
+         values never repeat, and --no-create does not create it: run `{create_sql}` or pass \
+         --create-key-ranges",
+        quote_ident(table)
+    );
+    conn.query_drop(create_sql).await?;
+    Ok(())
+}
+
+/// Reserve the key ranges of `workers` workers above those recorded in `ranges`
+/// (unquoted) and return the first worker id, so a run against an existing table does not
+/// regenerate the values of earlier ones.
+///
+/// The highest recorded row is locked, read and moved up in one transaction: a run starting
+/// at the same time waits for the lock, or fails to commit under optimistic transactions
+/// and tries again, and then reads the moved row.
+async fn reserve_workers(conn: &mut Conn, ranges: &str, workers: u32) -> Result<u32> {
+    let quoted = quote_ident(ranges);
+    // The range before any run, so there is always a row to lock.
+    conn.query_drop(format!("INSERT IGNORE INTO {quoted} VALUES (0)"))
+        .await?;
+    let mut attempt = 1;
+    loop {
+        let reserved = async {
+            let mut tx = conn.start_transaction(TxOpts::default()).await?;
+            let offset: u32 = tx
+                .query_first(format!(
+                    "SELECT next_worker FROM {quoted} ORDER BY next_worker DESC LIMIT 1 FOR UPDATE"
+                ))
+                .await?
+                .unwrap_or(0);
+            tx.exec_drop(
+                format!("UPDATE {quoted} SET next_worker = ? WHERE next_worker = ?"),
+                (offset + workers, offset),
+            )
+            .await?;
+            tx.commit().await?;
+            anyhow::Ok(offset)
+        };
+        match reserved.await {
+            Err(e)
+                if attempt < RESERVE_ATTEMPTS
+                    && (is_transient(&e) || server_code(&e) == Some(DUPLICATE_KEY)) =>
+            {
+                attempt += 1;
+            }
+            reserved => return reserved,
+        }
+    }
+}
+
+/// Spread sequential worker key `c` over the positive `BIGINT` range by reversing its bits.
+fn scattered_key(c: u64) -> i64 {
+    (c.reverse_bits() >> 1) as i64
+}
+
+/// How [`Protocol`]s shape a multi-row INSERT.
+trait InsertProtocol {
+    /// Bytes `row` adds to an INSERT packet.
+    fn row_len(self, row: &[Value]) -> usize;
+
+    /// Most rows of `columns` values a single INSERT may carry.
+    fn max_rows(self, columns: usize) -> usize;
+
+    /// A multi-row INSERT of `rows`, given the statement text up to `VALUES ` and the text
+    /// following the values.
+    fn insert(self, head: &str, rows: Vec<Vec<Value>>, tail: &str) -> Insert;
+}
+
+impl InsertProtocol for Protocol {
+    fn row_len(self, row: &[Value]) -> usize {
+        match self {
+            // `(a, b), `
+            Protocol::Text => row.iter().map(|v| v.as_sql(false).len() + 2).sum::<usize>() + 2,
+            // Each value plus its two type bytes and null-bitmap bit.
+            Protocol::Binary => row
+                .iter()
+                .map(|v| match v {
+                    Value::Bytes(bytes) => bytes.len() + 1 + 3,
+                    _ => 8 + 3,
+                })
+                .sum(),
+        }
+    }
+
+    fn max_rows(self, columns: usize) -> usize {
+        match self {
+            Protocol::Text => usize::MAX,
+            // A prepared statement takes at most 65535 placeholders.
+            Protocol::Binary => u16::MAX as usize / columns,
+        }
+    }
+
+    fn insert(self, head: &str, rows: Vec<Vec<Value>>, tail: &str) -> Insert {
+        // Built in one buffer sized up front: with large batches, per-row strings and joins
+        // cost client CPU that competes with the workers being measured.
+        let mut sql = String::new();
+        match self {
+            Protocol::Text => {
+                // Estimated without formatting the values twice: quoted strings, and numbers
+                // of up to 20 digits, each followed by `, `.
+                let values = rows
+                    .iter()
+                    .flatten()
+                    .map(|v| match v {
+                        Value::Bytes(bytes) => bytes.len() + 4,
+                        _ => 22,
+                    })
+                    .sum::<usize>();
+                sql.reserve(head.len() + values + rows.len() * 2 + tail.len());
+                sql.push_str(head);
+                for (i, row) in rows.iter().enumerate() {
+                    sql.push_str(if i == 0 { "(" } else { ", (" });
+                    for (j, value) in row.iter().enumerate() {
+                        if j > 0 {
+                            sql.push_str(", ");
+                        }
+                        sql.push_str(&value.as_sql(false));
+                    }
+                    sql.push(')');
+                }
+                sql.push_str(tail);
+                Insert { sql, params: None }
+            }
+            Protocol::Binary => {
+                let columns = rows.first().map_or(0, Vec::len);
+                // `(?, ?), ` per row.
+                let row_len = columns * 3 + 2;
+                sql.reserve_exact(head.len() + rows.len() * row_len + tail.len());
+                sql.push_str(head);
+                for i in 0..rows.len() {
+                    sql.push_str(if i == 0 { "(" } else { ", (" });
+                    for j in 0..columns {
+                        sql.push_str(if j == 0 { "?" } else { ", ?" });
+                    }
+                    sql.push(')');
+                }
+                sql.push_str(tail);
+                Insert {
+                    sql,
+                    params: Some(rows.into_iter().flatten().collect()),
+                }
+            }
+        }
+    }
+}
+
+/// One INSERT statement of a batch.
+struct Insert {
+    sql: String,
+    /// Values bound to the placeholders of `sql`, if it is a prepared statement.
+    params: Option<Vec<Value>>,
+}
+
+impl Insert {
+    /// Execute the statement, adding what it reported to `written`.
+    async fn run<Q: Queryable>(self, q: &mut Q, written: &mut Written) -> Result<()> {
+        let (affected, id) = match self.params {
+            Some(params) => {
+                let result = q.exec_iter(self.sql, params).await?;
+                let reported = (result.affected_rows(), result.last_insert_id());
+                result.drop_result().await?;
+                reported
+            }
+            None => {
+                let result = q.query_iter(self.sql).await?;
+                let reported = (result.affected_rows(), result.last_insert_id());
+                result.drop_result().await?;
+                reported
+            }
+        };
+        written.affected += affected;
+        if affected > 0 && id.is_none() {
+            written.without_id += 1;
+        }
+        Ok(())
+    }
+}
+
+/// What the INSERTs of a batch reported back.
+#[derive(Debug, Default, Clone, Copy)]
+struct Written {
+    affected: u64,
+    /// Statements that inserted rows but reported no generated id (`last_insert_id`).
+    without_id: u64,
+}
+
+/// One batch of rows, built ahead of time.
+struct Batch {
+    /// Usually a single multi-row INSERT; more when the batch exceeds the max packet size.
+    statements: Vec<Insert>,
+    /// Payload bytes of the inserted values.
+    bytes: u64,
+}
+
+/// A batch that went through: what its INSERTs reported and the phases of its transaction,
+/// unless it ran in auto-commit mode.
+struct Applied {
+    written: Written,
+    phases: Option<Phases>,
+}
+
+/// A submitted batch, resolving to its connection, submit-to-commit latency and result.
+type InFlight = JoinHandle<(Conn, Result<(Duration, Applied)>)>;
+
+/// Per-worker connections and the transactions currently in flight on them.
+pub struct InsertWorker {
+    idle: Vec<Conn>,
+    /// Payload bytes and completion of each batch in flight, oldest first.
+    in_flight: VecDeque<(u64, InFlight)>,
+    worker_id: u32,
+    /// Effective `max_allowed_packet` of this worker's connections.
+    max_packet: usize,
+    /// Number of batches submitted by this worker so far.
+    seq: u64,
+    /// Batches in the open `--commit-every` transaction, and the rows they inserted.
+    open_batches: u32,
+    open_rows: u64,
+}
+
+impl InsertWorker {
+    /// Start a batch on an idle connection, timing it from submission to commit.
+    fn submit(&mut self, conn: Conn, tx_mode: TxMode, batch: Batch) {
+        let handle = tokio::spawn(async move {
+            let mut conn = conn;
+            let t = Instant::now();
+            let result = insert_batch(&mut conn, &tx_mode, batch.statements).await;
+            (conn, result.map(|applied| (t.elapsed(), applied)))
+        });
+        self.in_flight.push_back((batch.bytes, handle));
+        self.seq += 1;
+    }
+
+    /// Wait for every in-flight batch, returning its connection to the idle set.
+    async fn drain(&mut self) -> Result<()> {
+        while let Some((_, handle)) = self.in_flight.pop_front() {
+            let (conn, result) = handle.await?;
+            self.idle.push(conn);
+            result?;
+        }
+        Ok(())
+    }
+}
+
+/// Run the statements of a batch.
+async fn insert_batch(
+    conn: &mut Conn,
+    tx_mode: &TxMode,
+    statements: Vec<Insert>,
+) -> Result<Applied> {
+    let mut written = Written::default();
+    let phases = match tx_mode {
+        TxMode::AutoCommit => {
+            for stmt in statements {
+                stmt.run(conn, &mut written).await?;
+            }
+            None
+        }
+        TxMode::Optimistic | TxMode::Pessimistic => {
+            let mut clock = PhaseClock::start();
+            let mut tx = conn.start_transaction(TxOpts::default()).await?;
+            clock.begun();
+            for stmt in statements {
+                stmt.run(&mut tx, &mut written).await?;
+            }
+            clock.executed();
+            tx.commit().await?;
+            Some(clock.committed())
+        }
+    };
+    Ok(Applied { written, phases })
+}
+
+#[async_trait]
+impl BenchSuite for InsertBench {
+    type WorkerState = InsertWorker;
+
+    async fn setup(&mut self, worker_id: u32) -> Result<Self::WorkerState> {
+        let mut idle = Vec::with_capacity(self.pipeline_depth as usize);
+        for _ in 0..self.pipeline_depth {
+            idle.push(self.db.connect_worker(worker_id, Role::Write).await?);
+        }
+        let conn = &mut idle[0];
+
+        let mut schema = format!(
+            "{},
+             data VARCHAR(255),
+             value INT{}",
+            self.key_layout.id_column(),
+            self.db.timestamp_column()
+        );
+        if let Some(key) = self.key_layout.primary_key() {
+            schema.push_str(&format!(", {key}"));
+        }
+        if self.index_value {
+            schema.push_str(", KEY idx_value (value)");
+        }
+        let options = self.key_layout.table_options();
+        self.lifecycle
+            .setup_once(self.db.undo_on_error(async {
+                self.db
+                    .create_table_with(conn, &self.db.table, &schema, options)
+                    .await?;
+                let ranges = self.db.aux_table(KEY_RANGES_SUFFIX);
+                if self.db.no_create {
+                    let (table, create) = (&self.db.table, self.create_key_ranges);
+                    require_key_ranges(conn, table, &ranges, create).await?;
+                } else {
+                    self.db
+                        .create_table(conn, &ranges, KEY_RANGES_SCHEMA)
+                        .await?;
+                }
+                let offset = reserve_workers(conn, &ranges, self.workers).await?;
+                if offset > 0 {
+                    eprintln!(
+                        "{} holds rows of earlier runs; worker key ranges start at worker \
+                         {offset} (key {})",
+                        quote_ident(&self.db.table),
+                        worker_key(offset, 0)?
+                    );
+                }
+                let _ = self.worker_offset.set(offset);
+                self.harness.plan_statements(|| self.plan_statements());
+                Ok(())
+            }))
+            .await?;
+        let max_packet = match self.db.max_packet {
+            Some(max_packet) => max_packet,
+            None => conn
+                .query_first("SELECT @@max_allowed_packet")
+                .await?
+                .unwrap_or(DEFAULT_MAX_PACKET),
+        };
+        for conn in &mut idle {
+            self.db.ping(conn, worker_id, Role::Write).await?;
+        }
+        Ok(InsertWorker {
+            idle,
+            in_flight: VecDeque::new(),
+            worker_id,
+            max_packet,
+            seq: 0,
+            open_batches: 0,
+            open_rows: 0,
+        })
+    }
+
+    async fn bench(&mut self, worker: &mut InsertWorker, info: &IterInfo) -> Result<IterReport> {
+        // SQL text is built before the harness starts timing, so it never counts as latency.
+        let queries = self.prepare(worker, worker.idle.len())?;
+
+        if self.pipeline_depth == 1 {
+            let batch = queries.into_iter().next().expect("one idle connection");
+            worker.seq += 1;
+            if let Some(every) = self.commit_every {
+                return self.insert_grouped(worker, batch, info, every).await;
+            }
+            let conn = &mut worker.idle[0];
+            let mut failed = false;
+            let report = self
+                .harness
+                .iter(info, async {
+                    let t = Instant::now();
+                    let result = insert_batch(conn, &self.db.tx_mode, batch.statements).await;
+                    failed = result.is_err();
+                    let applied = match result {
+                        Ok(applied) => applied,
+                        Err(e) => return self.duplicate_failure(e),
+                    };
+                    self.breakdown.record_server(t.elapsed());
+                    Ok(self
+                        .outcome(applied.written, batch.bytes)
+                        .with_phases(applied.phases))
+                })
+                .await;
+            // After the timed iteration, so rolling back and reconnecting are not latency.
+            if failed {
+                self.db
+                    .recover(&mut worker.idle[0], info.worker_id, Role::Write)
+                    .await?;
+            }
+            return report;
+        }
+
+        // Keep the pipeline full, then report the oldest batch with its own latency.
+        let mut failed = None;
+        let report = self
+            .harness
+            .iter(info, async {
+                for batch in queries {
+                    let conn = worker.idle.pop().expect("a batch per idle connection");
+                    worker.submit(conn, self.db.tx_mode.clone(), batch);
+                }
+                let (bytes, handle) = worker.in_flight.pop_front().expect("pipeline is not empty");
+                let (conn, result) = handle.await?;
+                match result {
+                    Ok(_) => worker.idle.push(conn),
+                    Err(_) => failed = Some(conn),
+                }
+                let (elapsed, applied) = match result {
+                    Ok(done) => done,
+                    Err(e) => return self.duplicate_failure(e),
+                };
+                self.breakdown.record_server(elapsed);
+                Ok(self
+                    .outcome(applied.written, bytes)
+                    .with_elapsed(elapsed)
+                    .with_phases(applied.phases))
+            })
+            .await;
+        if let Some(mut conn) = failed {
+            let recovered = self
+                .db
+                .recover(&mut conn, info.worker_id, Role::Write)
+                .await;
+            worker.idle.push(conn);
+            recovered?;
+        }
+        report
+    }
+
+    async fn teardown(self, mut worker: InsertWorker, _info: IterInfo) -> Result<()> {
+        // Batches still in flight past the end of the run are committed but not reported.
+        let drained = worker.drain().await;
+        self.commit_partial(&mut worker).await;
+        for conn in &mut worker.idle {
+            rollback_open(conn).await;
+        }
+        if !self.lifecycle.finish() {
+            return drained;
+        }
+        drained?;
+        self.harness.capture_final_plans().await;
+        let conn = &mut worker.idle[0];
+        if self.show_regions {
+            let regions = match region_summary(conn, &self.db.quoted_table()).await {
+                Ok(summary) => summary,
+                Err(e) => format!("cannot show table regions: {e:#}"),
+            };
+            *self.regions.lock().unwrap() = Some(regions);
+        }
+        self.db.drop_table(conn, &self.db.table).await?;
+        self.db
+            .drop_table(conn, &self.db.aux_table(KEY_RANGES_SUFFIX))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Summarize `SHOW TABLE ... REGIONS`: regions and written bytes per leader store, and the
+/// share of writes taken by the busiest region.
+async fn region_summary(conn: &mut Conn, table: &str) -> Result<String> {
+    let rows: Vec<Row> = conn.query(format!("SHOW TABLE {table} REGIONS")).await?;
+    let mut stores: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+    let mut busiest = 0;
+    for row in &rows {
+        let store: u64 = row.get("LEADER_STORE_ID").unwrap_or_default();
+        let written: u64 = row.get("WRITTEN_BYTES").unwrap_or_default();
+        let entry = stores.entry(store).or_default();
+        entry.0 += 1;
+        entry.1 += written;
+        busiest = busiest.max(written);
+    }
+    let total: u64 = stores.values().map(|(_, written)| written).sum();
+    let mut out = format!("{} regions of {table} by leader store:", rows.len());
+    for (store, (regions, written)) in &stores {
+        let _ = write!(
+            out,
+            "\n  store {store}: {regions} regions, {written} bytes written"
+        );
+    }
+    if total > 0 {
+        let share = busiest as f64 / total as f64 * 100.0;
+        let _ = write!(out, "\n  busiest region took {share:.1}% of written bytes");
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::WORKER_SEQ_BITS;
+
+    fn bench(args: &[&str]) -> InsertBench {
+        let opts = InsertOpts::parse_from(std::iter::once("test").chain(args.iter().copied()));
+        let bench = InsertBench::new(&opts, 2);
+        bench.worker_offset.set(0).unwrap();
+        bench
+    }
+
+    /// `data` values of the worker's first `batches` batches.
+    fn data(bench: &InsertBench, worker_id: u32, batches: u64) -> BTreeSet<String> {
+        (0..batches)
+            .flat_map(|seq| {
+                bench
+                    .build_batch(worker_id, seq, DEFAULT_MAX_PACKET)
+                    .unwrap()
+                    .statements
+            })
+            .flat_map(|insert| insert.params.unwrap())
+            .filter_map(|value| match value {
+                Value::Bytes(bytes) => Some(String::from_utf8(bytes).unwrap()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn zero_batch_sizes_and_tiny_packets_are_rejected() {
+        let parse = |args: &[&str]| {
+            InsertOpts::try_parse_from(std::iter::once("test").chain(args.iter().copied()))
+        };
+        assert!(parse(&["--batch-size", "0"]).is_err());
+        assert!(parse(&["--pipeline-depth", "0"]).is_err());
+        assert!(parse(&["--max-packet", "1023"]).is_err());
+        assert!(parse(&["--max-packet", "1024"]).is_ok());
+    }
+
+    #[test]
+    fn batches_of_two_workers_are_disjoint() {
+        let bench = bench(&["--batch-size", "50"]);
+        let first = data(&bench, 0, 4);
+        let second = data(&bench, 1, 4);
+        assert_eq!(first.len(), 200);
+        assert_eq!(second.len(), 200);
+        assert!(first.is_disjoint(&second));
+    }
+
+    #[test]
+    fn batches_past_the_worker_range_fail() {
+        let bench = bench(&["--batch-size", "100"]);
+        let last = ((1 << WORKER_SEQ_BITS) - 1) / 100;
+        assert!(bench.build_batch(0, last - 1, DEFAULT_MAX_PACKET).is_ok());
+        assert!(bench.build_batch(0, last, DEFAULT_MAX_PACKET).is_err());
+    }
+
+    /// A worker without connections, enough to prepare its batches.
+    fn worker(max_packet: usize) -> InsertWorker {
+        InsertWorker {
+            idle: Vec::new(),
+            in_flight: VecDeque::new(),
+            worker_id: 0,
+            max_packet,
+            seq: 0,
+            open_batches: 0,
+            open_rows: 0,
+        }
+    }
+
+    #[test]
+    fn batches_are_complete_before_the_timed_iteration() {
+        // What `bench` hands to the timed future: statements with every value in place,
+        // built and counted as client prep beforehand.
+        let binary = bench(&["--batch-size", "100"]);
+        let batches = binary.prepare(&worker(DEFAULT_MAX_PACKET), 2).unwrap();
+        assert_eq!(batches.len(), 2);
+        for batch in &batches {
+            let [insert] = batch.statements.as_slice() else {
+                panic!("one INSERT per batch");
+            };
+            let params = insert.params.as_ref().unwrap();
+            assert_eq!(params.len(), 200);
+            assert_eq!(insert.sql.matches('?').count(), params.len());
+            assert!(batch.bytes > 0);
+        }
+        assert_eq!(binary.breakdown.batches.load(Ordering::Relaxed), 2);
+
+        let text = bench(&["--batch-size", "100", "--protocol", "text"]);
+        let batches = text.prepare(&worker(1024), 1).unwrap();
+        let statements = &batches[0].statements;
+        assert!(statements.len() > 1, "a 1 KiB packet splits the batch");
+        for insert in statements {
+            assert!(insert.params.is_none());
+            assert!(!insert.sql.contains('?'));
+        }
+        let rows: usize = statements
+            .iter()
+            .map(|insert| insert.sql.matches("), (").count() + 1)
+            .sum();
+        assert_eq!(rows, 100);
+        assert_eq!(text.breakdown.split_batches.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn batch_bytes_count_the_inserted_values() {
+        // `bench_data_0` to `bench_data_2`: 12 bytes of data and a 4-byte value per row.
+        let batch = bench(&["--batch-size", "3"])
+            .build_batch(0, 0, DEFAULT_MAX_PACKET)
+            .unwrap();
+        assert_eq!(batch.bytes, 3 * (12 + 4));
+        // Plus the 8-byte key the client generates.
+        let batch = bench(&["--batch-size", "3", "--key-layout", "scattered"])
+            .build_batch(0, 0, DEFAULT_MAX_PACKET)
+            .unwrap();
+        assert_eq!(batch.bytes, 3 * (8 + 12 + 4));
+    }
+
+    const HEAD: &str = "INSERT INTO t (id, data) VALUES ";
+
+    /// `n` rows of an id and a string that needs escaping.
+    fn rows(n: i64) -> Vec<Vec<Value>> {
+        (0..n)
+            .map(|i| {
+                vec![
+                    Value::Int(i),
+                    Value::Bytes(format!("it's row {i}").into_bytes()),
+                ]
+            })
+            .collect()
+    }
+
+    /// The text INSERT as built before [`InsertProtocol::insert`] used a single buffer:
+    /// a string per row, joined.
+    fn joined_insert(head: &str, rows: &[Vec<Value>], tail: &str) -> String {
+        let values = rows
+            .iter()
+            .map(|row| {
+                let row = row.iter().map(|v| v.as_sql(false)).collect::<Vec<_>>();
+                format!("({})", row.join(", "))
+            })
+            .collect::<Vec<_>>();
+        format!("{head}{}{tail}", values.join(", "))
+    }
+
+    #[test]
+    fn single_buffer_builds_the_joined_statement() {
+        let rows = rows(3);
+        let text = Protocol::Text.insert(HEAD, rows.clone(), " ON DUPLICATE KEY UPDATE id = id");
+        assert_eq!(
+            text.sql,
+            joined_insert(HEAD, &rows, " ON DUPLICATE KEY UPDATE id = id")
+        );
+        assert!(text.sql.contains(r"'it\'s row 2'"));
+        assert!(text.params.is_none());
+
+        let binary = Protocol::Binary.insert(HEAD, rows, "");
+        assert_eq!(binary.sql, format!("{HEAD}(?, ?), (?, ?), (?, ?)"));
+        assert_eq!(binary.params.unwrap().len(), 6);
+    }
+
+    /// Compares the two text builders; timing only means something in a release build:
+    /// `cargo test --release -- --ignored --nocapture builder_throughput`.
+    #[test]
+    #[ignore = "timing comparison"]
+    fn builder_throughput() {
+        for (n, rounds) in [(100, 2000), (10_000, 20)] {
+            let rows = rows(n);
+            // Each round builds from its own copy of the rows, made before the clock starts.
+            let time = |build: &dyn Fn(Vec<Vec<Value>>) -> usize| {
+                let batches: Vec<_> = (0..rounds).map(|_| rows.clone()).collect();
+                let start = std::time::Instant::now();
+                let bytes: usize = batches.into_iter().map(build).sum();
+                assert!(bytes > 0);
+                start.elapsed() / rounds
+            };
+            let joined = time(&|rows| joined_insert(HEAD, &rows, "").len());
+            let single = time(&|rows| Protocol::Text.insert(HEAD, rows, "").sql.len());
+            eprintln!("{n} rows: joined {joined:.2?}, single buffer {single:.2?} per batch");
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! The benchmarks as [`rlt::BenchSuite`]s, for the `bench-*` binaries and for embedding in
+//! another harness.
+//!
+//! Each suite is built from its options (`clap` arguments, also constructible with
+//! `parse_from`) and the number of workers it will run with. Every suite implements
+//! [`Benchmark`], through which [`run_main`](crate::run_main) drives it; the binaries do
+//! nothing else.
+
+mod gaplock;
+mod insert;
+mod params;
+mod ryw;
+mod savepoint;
+mod select;
+mod subquery;
+
+pub use gaplock::{GapLockBench, GapLockOpts};
+pub use insert::{InsertBench, InsertOpts, InsertWorker, KeyLayout, OnDuplicate};
+pub use params::{ParamsBench, ParamsOpts};
+pub use ryw::{RywBench, RywOpts};
+pub use savepoint::{SavepointBench, SavepointOpts};
+pub use select::{SelectBench, SelectOpts};
+pub use subquery::{SubqueryBench, SubqueryForm, SubqueryOpts};
+
+use anyhow::Result;
+use rlt::BenchSuite;
+
+use crate::harness::{Harness, RunOpts};
+use crate::lifecycle::Lifecycle;
+use crate::DbOpts;
+
+/// The command-line options of a suite.
+pub trait BenchOpts: clap::Parser + clap::Args + Send {
+    fn db(&self) -> &DbOpts;
+
+    fn db_mut(&mut self) -> &mut DbOpts;
+
+    fn run(&self) -> &RunOpts;
+
+    /// Settings shown in the run header.
+    fn settings(&self) -> Vec<(&'static str, String)>;
+
+    /// Reject option combinations the benchmark cannot run.
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A benchmark suite together with what it takes to run it from the command line.
+pub trait Benchmark: BenchSuite + Clone + Send + Sync + 'static {
+    type Opts: BenchOpts;
+
+    /// Name of the binary, in the run header and the configuration fingerprint.
+    const NAME: &'static str;
+
+    /// Privileges the iterations need on the database, checked by
+    /// [`preflight`](crate::preflight::preflight).
+    const PRIVILEGES: &'static [&'static str];
+
+    /// Build the suite for a run with `workers` workers.
+    fn new(opts: &Self::Opts, workers: u32) -> Self;
+
+    fn db(&self) -> &DbOpts;
+
+    /// Drives the run; see [`Harness::run`].
+    fn harness(&self) -> &Harness;
+
+    fn lifecycle(&self) -> &Lifecycle;
+
+    /// Tables the run creates, for cleanup after an interrupted run.
+    fn tables(&self) -> Vec<String> {
+        vec![self.db().table.clone()]
+    }
+
+    /// Print the suite's own summaries; see [`report`](Benchmark::report).
+    fn print_summaries(&self) {}
+
+    /// Print what the run collected beyond rlt's own report.
+    fn report(&self) -> Result<()> {
+        self.lifecycle().print_setup();
+        self.harness().report()?;
+        self.db().print_reconnects();
+        self.print_summaries();
+        Ok(())
+    }
+}
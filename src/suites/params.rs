@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::Parser;
+use mysql_async::prelude::*;
+use mysql_async::Value;
+use rand::Rng;
+use rlt::{BenchSuite, IterInfo, IterReport};
+
+use crate::distributions::Uniform;
+use crate::harness::{Harness, Outcome, RunOpts};
+use crate::lifecycle::Lifecycle;
+use crate::plan::PlanStatement;
+use crate::seed::{prepare_table, SeedOpts, SeedSpec};
+use crate::session::Session;
+use crate::suites::{BenchOpts, Benchmark};
+use crate::{payload, DbOpts, Protocol, Role};
+
+const VALUES: Uniform = Uniform::new(1000);
+
+/// Most placeholders a prepared statement may carry.
+const MAX_PARAMS: u32 = u16::MAX as u32;
+
+/// TiDB wide prepared-statement benchmark.
+///
+/// Each iteration executes `SELECT id, value FROM t WHERE id IN (?, ?, ...)` with `--params`
+/// placeholders bound to random ids. The statement is prepared once per connection, so the
+/// run measures parameter marshalling and the server's handling of a wide parameter list.
+#[derive(Parser, Clone)]
+pub struct ParamsOpts {
+    #[command(flatten)]
+    pub db: DbOpts,
+
+    /// Number of placeholders in the prepared statement.
+    #[clap(
+        long,
+        default_value_t = 1000,
+        value_parser = clap::value_parser!(u32).range(1..=MAX_PARAMS as i64)
+    )]
+    pub params: u32,
+
+    /// Number of preloaded rows the bound ids are drawn from.
+    #[clap(long, default_value_t = 100000, value_parser = clap::value_parser!(u32).range(1..))]
+    pub rows: u32,
+
+    #[command(flatten)]
+    pub seed: SeedOpts,
+
+    #[command(flatten)]
+    pub run: RunOpts,
+}
+
+impl BenchOpts for ParamsOpts {
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn db_mut(&mut self) -> &mut DbOpts {
+        &mut self.db
+    }
+
+    fn run(&self) -> &RunOpts {
+        &self.run
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = self.db.settings();
+        settings.push(("params", self.params.to_string()));
+        settings.extend(self.run.settings());
+        settings
+    }
+}
+
+/// `bench-params`: a prepared `SELECT ... IN (...)` with many placeholders.
+#[derive(Clone)]
+pub struct ParamsBench {
+    db: DbOpts,
+    params: u32,
+    rows: u32,
+    /// Statement text, identical for every iteration so the driver prepares it only once.
+    query: String,
+    seed: SeedOpts,
+    harness: Harness,
+    lifecycle: Arc<Lifecycle>,
+}
+
+impl Benchmark for ParamsBench {
+    type Opts = ParamsOpts;
+    const NAME: &'static str = "bench-params";
+    const PRIVILEGES: &'static [&'static str] = &["SELECT"];
+
+    fn new(opts: &ParamsOpts, workers: u32) -> Self {
+        let placeholders = vec!["?"; opts.params as usize].join(", ");
+        Self {
+            db: opts.db.clone(),
+            params: opts.params,
+            rows: opts.rows,
+            query: format!(
+                "SELECT id, value FROM {} WHERE id IN ({placeholders})",
+                opts.db.quoted_table()
+            ),
+            seed: opts.seed.clone(),
+            harness: Harness::new(&opts.run, &opts.db, workers),
+            lifecycle: Arc::new(Lifecycle::new(workers)),
+        }
+    }
+
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn harness(&self) -> &Harness {
+        &self.harness
+    }
+
+    fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+}
+
+impl ParamsBench {
+    /// Random ids for the placeholders of the next query.
+    fn next_ids(&self) -> Vec<u64> {
+        let mut rng = rand::thread_rng();
+        (0..self.params)
+            .map(|_| rng.gen_range(1..=self.rows as u64))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl BenchSuite for ParamsBench {
+    type WorkerState = Session;
+
+    async fn setup(&mut self, worker_id: u32) -> Result<Self::WorkerState> {
+        let mut conn = self.db.connect_worker(worker_id, Role::Read).await?;
+
+        self.lifecycle
+            .setup_once(self.db.undo_on_error(async {
+                let spec = SeedSpec {
+                    table: &self.db.table,
+                    schema: "id BIGINT PRIMARY KEY, value INT",
+                    columns: "id, value",
+                    rows: self.rows as u64,
+                    row: Arc::new(|i| format!("({}, {})", i + 1, VALUES.value_at(i))),
+                    ids: 1..=self.rows as i64,
+                    generator: "params-v1",
+                    csv: None,
+                    created_at: false,
+                };
+                prepare_table(&mut conn, &self.db, &self.seed, spec).await?;
+                self.harness.plan_statements(|| {
+                    let ids = self.next_ids().into_iter().map(Value::from).collect();
+                    vec![PlanStatement::new(
+                        "IN-list lookup",
+                        Protocol::Binary,
+                        &self.query,
+                        ids,
+                    )]
+                });
+                Ok(())
+            }))
+            .await?;
+        self.db.ping(&mut conn, worker_id, Role::Read).await?;
+        Ok(self.db.session(conn).await)
+    }
+
+    async fn bench(&mut self, session: &mut Session, info: &IterInfo) -> Result<IterReport> {
+        let ids = self.next_ids();
+
+        let report = self
+            .harness
+            .iter(info, async {
+                let mut lease = session.get().await;
+                let conn = &mut *lease;
+                let result = conn.exec::<(i64, i32), _, _>(&self.query, ids).await;
+                if result.is_err() {
+                    lease.fail();
+                }
+                let found = result?.len() as u64;
+                let bytes =
+                    self.params as u64 * payload::BIGINT + found * (payload::BIGINT + payload::INT);
+                Ok(Outcome::success(self.params as u64, bytes))
+            })
+            .await;
+        // After the timed iteration, so rolling back and reconnecting are not latency.
+        let recovered = session.recover(&self.db, info.worker_id, Role::Read).await;
+        let report = report?;
+        recovered?;
+        Ok(report)
+    }
+
+    async fn teardown(self, mut session: Session, _info: IterInfo) -> Result<()> {
+        if session.finish(&self.lifecycle).await {
+            self.harness.capture_final_plans().await;
+            let mut conn = session.get().await;
+            self.db.drop_table(&mut conn, &self.db.table).await?;
+        }
+        Ok(())
+    }
+}
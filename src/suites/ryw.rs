@@ -0,0 +1,234 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::Parser;
+use mysql_async::prelude::*;
+use mysql_async::{Conn, TxOpts};
+use rand::Rng;
+use rlt::{BenchSuite, IterInfo, IterReport, Status};
+
+use crate::affected::{UnexpectedWrites, WriteKind};
+use crate::harness::{Harness, Outcome, PhaseClock, Phases, RunOpts};
+use crate::lifecycle::Lifecycle;
+use crate::plan::{PlanStatement, SACRIFICIAL_WORKER};
+use crate::session::Session;
+use crate::suites::{BenchOpts, Benchmark};
+use crate::{payload, status, worker_key, DbOpts, Protocol, Role, TxMode};
+
+/// TiDB read-your-writes consistency check.
+///
+/// Each iteration inserts a row and immediately reads it back by id, inside one transaction
+/// (or one session in auto-commit mode). A read that does not return what was written is
+/// reported as a client error with status code 1000.
+#[derive(Parser, Clone)]
+pub struct RywOpts {
+    #[command(flatten)]
+    pub db: DbOpts,
+
+    #[command(flatten)]
+    pub run: RunOpts,
+}
+
+impl BenchOpts for RywOpts {
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn db_mut(&mut self) -> &mut DbOpts {
+        &mut self.db
+    }
+
+    fn run(&self) -> &RunOpts {
+        &self.run
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = self.db.settings();
+        settings.extend(self.run.settings());
+        settings
+    }
+}
+
+/// `bench-ryw`: rows written and read back on the same session, checked for read-your-writes.
+#[derive(Clone)]
+pub struct RywBench {
+    db: DbOpts,
+    harness: Harness,
+    lifecycle: Arc<Lifecycle>,
+    unexpected: Arc<UnexpectedWrites>,
+}
+
+impl Benchmark for RywBench {
+    type Opts = RywOpts;
+    const NAME: &'static str = "bench-ryw";
+    const PRIVILEGES: &'static [&'static str] = &["INSERT", "SELECT"];
+
+    fn new(opts: &RywOpts, workers: u32) -> Self {
+        Self {
+            db: opts.db.clone(),
+            harness: Harness::new(&opts.run, &opts.db, workers),
+            lifecycle: Arc::new(Lifecycle::new(workers)),
+            unexpected: Arc::default(),
+        }
+    }
+
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn harness(&self) -> &Harness {
+        &self.harness
+    }
+
+    fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    fn print_summaries(&self) {
+        self.unexpected.print();
+    }
+}
+
+impl RywBench {
+    /// The statements of an iteration, on a key no worker writes.
+    fn plan_statements(&self) -> Vec<PlanStatement> {
+        let table = self.db.quoted_table();
+        let id = worker_key(SACRIFICIAL_WORKER, 0).expect("the sacrificial key range exists");
+        let row = vec![id.into(), format!("ryw_data_{id}").into(), 0.into()];
+        vec![
+            PlanStatement::new("insert", Protocol::Binary, &insert_sql(&table), row).write(),
+            PlanStatement::new(
+                "read back",
+                Protocol::Binary,
+                &read_sql(&table),
+                vec![id.into()],
+            ),
+        ]
+    }
+}
+
+/// The INSERT of an iteration, binding id, data and value.
+fn insert_sql(table: &str) -> String {
+    format!("INSERT INTO {table} (id, data, value) VALUES (?, ?, ?)")
+}
+
+/// The read-back of an iteration, binding the id.
+fn read_sql(table: &str) -> String {
+    format!("SELECT data, value FROM {table} WHERE id = ?")
+}
+
+/// Write a row and read it back on the same session, returning the rows the write affected
+/// and the bytes read if the row matches.
+async fn write_then_read<Q: Queryable>(
+    q: &mut Q,
+    table: &str,
+    id: u64,
+    data: &str,
+    value: i32,
+) -> Result<(u64, Option<u64>)> {
+    let result = q.exec_iter(insert_sql(table), (id, data, value)).await?;
+    let affected = result.affected_rows();
+    result.drop_result().await?;
+    let row: Option<(String, i32)> = q.exec_first(read_sql(table), (id,)).await?;
+    let read = match row {
+        Some((d, v)) if d == data && v == value => Some(payload::string(&d) + payload::INT),
+        _ => None,
+    };
+    Ok((affected, read))
+}
+
+/// [`write_then_read`] in its own transaction, also returning the transaction's phases.
+async fn write_then_commit(
+    conn: &mut Conn,
+    table: &str,
+    id: u64,
+    data: &str,
+    value: i32,
+) -> Result<((u64, Option<u64>), Phases)> {
+    let mut clock = PhaseClock::start();
+    let mut tx = conn.start_transaction(TxOpts::default()).await?;
+    clock.begun();
+    let read = write_then_read(&mut tx, table, id, data, value).await?;
+    clock.executed();
+    tx.commit().await?;
+    Ok((read, clock.committed()))
+}
+
+#[async_trait]
+impl BenchSuite for RywBench {
+    type WorkerState = Session;
+
+    async fn setup(&mut self, worker_id: u32) -> Result<Self::WorkerState> {
+        let mut conn = self.db.connect_worker(worker_id, Role::Write).await?;
+
+        let schema = "id BIGINT PRIMARY KEY,
+                      data VARCHAR(255),
+                      value INT";
+        self.lifecycle
+            .setup_once(self.db.undo_on_error(async {
+                self.db
+                    .create_table(&mut conn, &self.db.table, schema)
+                    .await?;
+                self.harness.plan_statements(|| self.plan_statements());
+                Ok(())
+            }))
+            .await?;
+        self.db.ping(&mut conn, worker_id, Role::Write).await?;
+        Ok(self.db.session(conn).await)
+    }
+
+    async fn bench(&mut self, session: &mut Session, info: &IterInfo) -> Result<IterReport> {
+        let table = self.db.quoted_table();
+        let id = worker_key(info.worker_id, info.worker_seq)?;
+        let data = format!("ryw_data_{id}");
+        let value = rand::thread_rng().gen::<i32>();
+
+        let report = self
+            .harness
+            .iter(info, async {
+                let mut lease = session.get().await;
+                let conn = &mut *lease;
+                let read = match self.db.tx_mode {
+                    TxMode::AutoCommit => write_then_read(conn, &table, id, &data, value)
+                        .await
+                        .map(|read| (read, None)),
+                    TxMode::Optimistic | TxMode::Pessimistic => {
+                        write_then_commit(conn, &table, id, &data, value)
+                            .await
+                            .map(|(read, phases)| (read, Some(phases)))
+                    }
+                };
+                if read.is_err() {
+                    lease.fail();
+                }
+                let ((affected, read), phases) = read?;
+
+                let affected = WriteKind::Insert.interpret(1, affected);
+                if !self.unexpected.check(affected.full) {
+                    return Ok(Outcome::failure(Status::client_error(
+                        status::AFFECTED_ROWS_UNEXPECTED,
+                    )));
+                }
+                Ok(match read {
+                    Some(bytes) => Outcome::success(affected.written, bytes).with_phases(phases),
+                    None => Outcome::failure(Status::client_error(status::READ_MISMATCH)),
+                })
+            })
+            .await;
+        // After the timed iteration, so rolling back and reconnecting are not latency.
+        let recovered = session.recover(&self.db, info.worker_id, Role::Write).await;
+        let report = report?;
+        recovered?;
+        Ok(report)
+    }
+
+    async fn teardown(self, mut session: Session, _info: IterInfo) -> Result<()> {
+        if session.finish(&self.lifecycle).await {
+            self.harness.capture_final_plans().await;
+            let mut conn = session.get().await;
+            self.db.drop_table(&mut conn, &self.db.table).await?;
+        }
+        Ok(())
+    }
+}
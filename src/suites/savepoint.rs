@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::Parser;
+use mysql_async::prelude::*;
+use mysql_async::{Conn, TxOpts};
+use rand::Rng;
+use rlt::{BenchSuite, IterInfo, IterReport, Status};
+
+use crate::affected::{UnexpectedWrites, WriteKind};
+use crate::harness::{Harness, Outcome, PhaseClock, Phases, RunOpts};
+use crate::lifecycle::Lifecycle;
+use crate::plan::{PlanStatement, SACRIFICIAL_WORKER};
+use crate::session::Session;
+use crate::suites::{BenchOpts, Benchmark};
+use crate::{parse_probability, release_savepoint, rollback_to_savepoint, savepoint, DbOpts, Role};
+use crate::{payload, status};
+
+const SAVEPOINT: &str = "bench_sp";
+
+/// TiDB SAVEPOINT / ROLLBACK TO SAVEPOINT benchmark.
+///
+/// Every iteration runs in an explicit transaction, including in auto-commit mode.
+#[derive(Parser, Clone)]
+pub struct SavepointOpts {
+    #[command(flatten)]
+    pub db: DbOpts,
+
+    /// Number of single-row INSERTs issued before the savepoint (always committed).
+    #[clap(long, default_value_t = 1)]
+    pub writes: u32,
+
+    /// Number of single-row INSERTs issued after the savepoint.
+    #[clap(long, default_value_t = 5)]
+    pub savepoint_writes: u32,
+
+    /// Fraction of iterations that roll back to the savepoint before committing.
+    #[clap(long, default_value_t = 0.5, value_parser = parse_probability)]
+    pub rollback_ratio: f64,
+
+    #[command(flatten)]
+    pub run: RunOpts,
+}
+
+impl BenchOpts for SavepointOpts {
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn db_mut(&mut self) -> &mut DbOpts {
+        &mut self.db
+    }
+
+    fn run(&self) -> &RunOpts {
+        &self.run
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = self.db.settings();
+        settings.push(("rollback ratio", self.rollback_ratio.to_string()));
+        settings.extend(self.run.settings());
+        settings
+    }
+}
+
+/// `bench-savepoint`: transactions that write, set a savepoint and roll back to it or release it.
+#[derive(Clone)]
+pub struct SavepointBench {
+    db: DbOpts,
+    writes: u32,
+    savepoint_writes: u32,
+    rollback_ratio: f64,
+    harness: Harness,
+    lifecycle: Arc<Lifecycle>,
+    unexpected: Arc<UnexpectedWrites>,
+}
+
+impl Benchmark for SavepointBench {
+    type Opts = SavepointOpts;
+    const NAME: &'static str = "bench-savepoint";
+    const PRIVILEGES: &'static [&'static str] = &["INSERT"];
+
+    fn new(opts: &SavepointOpts, workers: u32) -> Self {
+        Self {
+            db: opts.db.clone(),
+            writes: opts.writes,
+            savepoint_writes: opts.savepoint_writes,
+            rollback_ratio: opts.rollback_ratio,
+            harness: Harness::new(&opts.run, &opts.db, workers),
+            lifecycle: Arc::new(Lifecycle::new(workers)),
+            unexpected: Arc::default(),
+        }
+    }
+
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn harness(&self) -> &Harness {
+        &self.harness
+    }
+
+    fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    fn print_summaries(&self) {
+        self.unexpected.print();
+    }
+}
+
+impl SavepointBench {
+    /// Single-row INSERTs of iteration `s` of worker `w` and the payload bytes they write.
+    fn build_inserts(&self, w: u32, s: u64, count: u32, offset: u32) -> (Vec<String>, u64) {
+        let table = self.db.quoted_table();
+        let mut bytes = 0;
+        let stmts = (offset..offset + count)
+            .map(|i| {
+                let data = format!("sp_data_{w}_{s}_{i}");
+                bytes += payload::string(&data) + payload::INT;
+                format!(
+                    "INSERT INTO {table} (data, value) VALUES ('{data}', {})",
+                    i % 1000
+                )
+            })
+            .collect();
+        (stmts, bytes)
+    }
+}
+
+/// Run `before`, set the savepoint, run `after` and commit, rolling `after` back first if
+/// `rollback` is set; returns the transaction's phases and the rows affected before and
+/// after the savepoint.
+async fn run_transaction(
+    conn: &mut Conn,
+    before: &[String],
+    after: &[String],
+    rollback: bool,
+) -> Result<(Phases, [u64; 2])> {
+    let mut clock = PhaseClock::start();
+    let mut affected = [0; 2];
+    let mut tx = conn.start_transaction(TxOpts::default()).await?;
+    clock.begun();
+    for stmt in before {
+        tx.query_drop(stmt).await?;
+        affected[0] += tx.affected_rows();
+    }
+    savepoint(&mut tx, SAVEPOINT).await?;
+    for stmt in after {
+        tx.query_drop(stmt).await?;
+        affected[1] += tx.affected_rows();
+    }
+    if rollback {
+        rollback_to_savepoint(&mut tx, SAVEPOINT).await?;
+    } else {
+        release_savepoint(&mut tx, SAVEPOINT).await?;
+    }
+    clock.executed();
+    tx.commit().await?;
+    Ok((clock.committed(), affected))
+}
+
+#[async_trait]
+impl BenchSuite for SavepointBench {
+    type WorkerState = Session;
+
+    async fn setup(&mut self, worker_id: u32) -> Result<Self::WorkerState> {
+        let mut conn = self.db.connect_worker(worker_id, Role::Write).await?;
+
+        let schema = format!(
+            "id BIGINT PRIMARY KEY AUTO_INCREMENT,
+             data VARCHAR(255),
+             value INT{}",
+            self.db.timestamp_column()
+        );
+        self.lifecycle
+            .setup_once(self.db.undo_on_error(async {
+                self.db
+                    .create_table(&mut conn, &self.db.table, &schema)
+                    .await?;
+                self.harness.plan_statements(|| {
+                    let (inserts, _) = self.build_inserts(SACRIFICIAL_WORKER, 0, 1, 0);
+                    let insert = inserts.into_iter().next().unwrap_or_default();
+                    vec![PlanStatement::text("insert", insert).write()]
+                });
+                Ok(())
+            }))
+            .await?;
+        self.db.ping(&mut conn, worker_id, Role::Write).await?;
+        Ok(self.db.session(conn).await)
+    }
+
+    async fn bench(&mut self, session: &mut Session, info: &IterInfo) -> Result<IterReport> {
+        let (w, s) = (info.worker_id, info.worker_seq);
+        let (before, before_bytes) = self.build_inserts(w, s, self.writes, 0);
+        let (after, after_bytes) = self.build_inserts(w, s, self.savepoint_writes, self.writes);
+        let rollback = rand::thread_rng().gen_bool(self.rollback_ratio);
+
+        let report = self
+            .harness
+            .iter(info, async {
+                let mut lease = session.get().await;
+                let conn = &mut *lease;
+                let result = run_transaction(conn, &before, &after, rollback).await;
+                if result.is_err() {
+                    lease.fail();
+                }
+                let (phases, [affected_before, affected_after]) = result?;
+
+                // Every single-row INSERT must have written its row, rolled back or not.
+                let statements = (before.len() + after.len()) as u64;
+                let affected =
+                    WriteKind::Insert.interpret(statements, affected_before + affected_after);
+                if !self.unexpected.check(affected.full) {
+                    return Ok(Outcome::failure(Status::client_error(
+                        status::AFFECTED_ROWS_UNEXPECTED,
+                    )));
+                }
+                let (code, committed, bytes) = if rollback {
+                    (status::ROLLED_BACK, affected_before, before_bytes)
+                } else {
+                    (status::OK, affected.written, before_bytes + after_bytes)
+                };
+                Ok(Outcome {
+                    status: Status::success(code),
+                    ..Outcome::success(committed, bytes).with_phases(Some(phases))
+                })
+            })
+            .await;
+        // After the timed iteration, so rolling back and reconnecting are not latency.
+        let recovered = session.recover(&self.db, info.worker_id, Role::Write).await;
+        let report = report?;
+        recovered?;
+        Ok(report)
+    }
+
+    async fn teardown(self, mut session: Session, _info: IterInfo) -> Result<()> {
+        if session.finish(&self.lifecycle).await {
+            self.harness.capture_final_plans().await;
+            let mut conn = session.get().await;
+            self.db.drop_table(&mut conn, &self.db.table).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,490 @@
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{ensure, Result};
+use async_trait::async_trait;
+use clap::Parser;
+use mysql_async::{Conn, TxOpts, Value};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rlt::{BenchSuite, IterInfo, IterReport, Status};
+
+use crate::distributions::{parse_theta, Picker, Spread, ValueOpts, Values};
+use crate::harness::{Harness, Outcome, PhaseClock, Phases, RowCounts, RunOpts};
+use crate::lifecycle::Lifecycle;
+use crate::plan::PlanStatement;
+use crate::seed::{prepare_table, table_stats, timestamp_bounds, CsvOpts, SeedOpts, SeedSpec};
+use crate::session::Session;
+use crate::suites::{BenchOpts, Benchmark};
+use crate::{
+    parse_fraction, payload, stable_hash, status, DbOpts, Protocol, ReadTxnEnd, Role, TxMode,
+};
+
+const TEST_DATA_MULTIPLIER: u64 = 2;
+
+type Rows = Vec<(i64, String)>;
+
+/// Logical bytes of a result: the `id` and `data` of every row.
+fn result_bytes(rows: &Rows) -> u64 {
+    rows.iter()
+        .map(|(_, data)| payload::BIGINT + payload::string(data))
+        .sum()
+}
+
+/// The range start generator of worker `worker_id` in a run seeded with `seed`, one stream
+/// per worker and seed.
+fn worker_rng(seed: u64, worker_id: u32) -> StdRng {
+    StdRng::seed_from_u64(stable_hash(format!("{seed}/{worker_id}").as_bytes()))
+}
+
+/// TiDB SELECT benchmark.
+#[derive(Parser, Clone)]
+pub struct SelectOpts {
+    #[command(flatten)]
+    pub db: DbOpts,
+
+    /// Number of rows to select per query, as the width of a `WHERE id BETWEEN` range.
+    #[clap(long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+    pub select_count: u32,
+
+    /// Rows to preload, independent of `--select-count`; twice `--select-count` if omitted.
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub table_size: Option<u64>,
+
+    /// Fraction of rows to match with a `WHERE value < threshold` filter instead of id ranges.
+    #[clap(long, value_parser = parse_fraction)]
+    pub selectivity: Option<f64>,
+
+    /// How id range scans pick their start, as an offset from the smallest id.
+    #[clap(long, value_enum, default_value = "uniform")]
+    pub offset_spread: Spread,
+
+    /// Skew of `--offset-spread zipfian`; higher values concentrate reads on the first rows.
+    #[clap(long, default_value_t = 0.99, value_parser = parse_theta)]
+    pub zipf_theta: f64,
+
+    /// Seed of the random range starts. Each worker draws from its own generator derived
+    /// from it, so a run with the same seed and concurrency reads the same ranges; random
+    /// if omitted.
+    #[clap(long, value_name = "N", conflicts_with = "selectivity")]
+    pub random_seed: Option<u64>,
+
+    /// Read the same leading id range every iteration, so the coprocessor cache and one
+    /// region serve most of the run; for comparison with the spread-out default.
+    #[clap(
+        long,
+        conflicts_with_all = ["selectivity", "offset_spread", "zipf_theta", "random_seed"]
+    )]
+    pub fixed_range: bool,
+
+    /// How the range or threshold reaches the server: bound to a prepared statement, or
+    /// spliced into the SQL text.
+    #[clap(long, value_enum, default_value = "binary")]
+    pub protocol: Protocol,
+
+    /// How the read's transaction ends outside auto-commit mode; `rollback` spares it the
+    /// commit round trip.
+    #[clap(long, value_enum, default_value = "rollback")]
+    pub read_txn_end: ReadTxnEnd,
+
+    #[command(flatten)]
+    pub values: ValueOpts,
+
+    #[command(flatten)]
+    pub seed: SeedOpts,
+
+    #[command(flatten)]
+    pub csv: CsvOpts,
+
+    #[command(flatten)]
+    pub run: RunOpts,
+}
+
+/// Ids present in the table, from which range scans pick their start.
+struct KeySpace {
+    starts: Picker,
+    min_id: i64,
+    rows: u64,
+    /// Every id in `min_id..=max_id` exists, so a range returns exactly its width in rows.
+    contiguous: bool,
+}
+
+impl SelectOpts {
+    /// Rows generated during setup: `--table-size`, or a multiple of `--select-count`.
+    pub fn table_size(&self) -> u64 {
+        self.table_size
+            .unwrap_or(self.select_count as u64 * TEST_DATA_MULTIPLIER)
+    }
+}
+
+impl BenchOpts for SelectOpts {
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn db_mut(&mut self) -> &mut DbOpts {
+        &mut self.db
+    }
+
+    fn run(&self) -> &RunOpts {
+        &self.run
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = self.db.settings();
+        settings.push(("table size", format!("{} rows", self.table_size())));
+        let pattern = match (self.selectivity, self.offset_spread) {
+            (Some(selectivity), _) => format!("value filter, selectivity {selectivity}"),
+            (None, _) if self.fixed_range => "fixed range (first ids only)".into(),
+            (None, Spread::Uniform) => "random ranges, uniform".into(),
+            (None, Spread::Zipfian) => format!("random ranges, zipfian θ={}", self.zipf_theta),
+        };
+        settings.push(("access pattern", pattern));
+        if let Some(seed) = self.random_seed {
+            settings.push(("random seed", seed.to_string()));
+        }
+        settings.extend(self.values.settings());
+        settings.push(("protocol", format!("{:?}", self.protocol).to_lowercase()));
+        if let Some(window) = self.seed.timestamp_spread {
+            let window = humantime::format_duration(window);
+            let end = match self.seed.timestamp_end {
+                Some(end) => format!("ending {}", humantime::format_rfc3339_seconds(end)),
+                None => "ending at seeding".into(),
+            };
+            settings.push((
+                "created_at",
+                format!("spread over {window} {end} (UTC seconds)"),
+            ));
+        }
+        if !matches!(self.db.tx_mode, TxMode::AutoCommit) {
+            let end = format!("{:?}", self.read_txn_end).to_lowercase();
+            settings.push(("read txn end", end));
+        }
+        settings.extend(self.run.settings());
+        settings
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.seed.timestamp_spread.is_none() || self.db.timestamps,
+            "--timestamp-spread fills the created_at column, which --timestamps false leaves out"
+        );
+        Ok(())
+    }
+}
+
+/// `bench-select`: range scans or filtered reads over a preloaded table.
+#[derive(Clone)]
+pub struct SelectBench {
+    db: DbOpts,
+    select_count: u32,
+    offset_spread: Spread,
+    zipf_theta: f64,
+    fixed_range: bool,
+    protocol: Protocol,
+    read_txn_end: ReadTxnEnd,
+    values: Values,
+    /// `value < threshold` bound matching `--selectivity` of the generated rows.
+    threshold: Option<u32>,
+    /// Seeded-table generator id, covering the `--value-*` options.
+    generator: String,
+    /// Statement text with placeholders, identical for every iteration so the driver
+    /// prepares it only once per connection.
+    query: String,
+    total_rows: u64,
+    /// Id range actually present after setup, which differs from `1..=total_rows` for CSV data.
+    keys: Arc<OnceLock<KeySpace>>,
+    /// Seed of the workers' range start generators.
+    random_seed: u64,
+    /// This worker's generator, derived from `random_seed` in `setup`.
+    rng: StdRng,
+    seed: SeedOpts,
+    csv: CsvOpts,
+    row_counts: Arc<RowCounts>,
+    harness: Harness,
+    lifecycle: Arc<Lifecycle>,
+}
+
+impl Benchmark for SelectBench {
+    type Opts = SelectOpts;
+    const NAME: &'static str = "bench-select";
+    const PRIVILEGES: &'static [&'static str] = &["SELECT"];
+
+    fn new(opts: &SelectOpts, workers: u32) -> Self {
+        let table = opts.db.quoted_table();
+        let filter = match opts.selectivity {
+            Some(_) => "value < ?",
+            None => "id BETWEEN ? AND ?",
+        };
+        let values = opts.values.values();
+        let random_seed = opts.random_seed.unwrap_or_else(rand::random);
+        Self {
+            db: opts.db.clone(),
+            select_count: opts.select_count,
+            offset_spread: opts.offset_spread,
+            zipf_theta: opts.zipf_theta,
+            fixed_range: opts.fixed_range,
+            protocol: opts.protocol,
+            read_txn_end: opts.read_txn_end,
+            values: values.clone(),
+            threshold: opts.selectivity.map(|s| values.threshold(s)),
+            generator: format!("select-v1{}", opts.values.generator_suffix()),
+            query: format!("SELECT id, data FROM {table} WHERE {filter}"),
+            total_rows: opts.table_size(),
+            keys: Arc::new(OnceLock::new()),
+            random_seed,
+            rng: StdRng::seed_from_u64(random_seed),
+            seed: opts.seed.clone(),
+            csv: opts.csv.clone(),
+            harness: Harness::new(&opts.run, &opts.db, workers),
+            row_counts: Arc::default(),
+            lifecycle: Arc::new(Lifecycle::new(workers)),
+        }
+    }
+
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn harness(&self) -> &Harness {
+        &self.harness
+    }
+
+    fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    fn print_summaries(&self) {
+        self.row_counts.print();
+    }
+}
+
+impl SelectBench {
+    /// Create the table and preload test rows with explicit ids `1..=total_rows`.
+    async fn prepare_table(&self, conn: &mut Conn) -> Result<()> {
+        let schema = format!(
+            "id BIGINT PRIMARY KEY AUTO_INCREMENT,
+             data VARCHAR(255),
+             value INT,
+             KEY idx_value (value){}",
+            self.db.timestamp_column()
+        );
+        let spec = SeedSpec {
+            table: &self.db.table,
+            schema: &schema,
+            columns: "id, data, value",
+            rows: self.total_rows,
+            row: Arc::new({
+                let values = self.values.clone();
+                move |i| {
+                    let data = values.data_at("test_data_", i);
+                    format!("({}, '{data}', {})", i + 1, values.value_at(i))
+                }
+            }),
+            ids: 1..=self.total_rows as i64,
+            generator: &self.generator,
+            csv: Some(&self.csv),
+            created_at: self.db.timestamps,
+        };
+        prepare_table(conn, &self.db, &self.seed, spec).await?;
+        let stats = table_stats(conn, &self.db.quoted_table()).await?;
+        eprintln!(
+            "{} holds {} rows (ids {}..={})",
+            self.db.quoted_table(),
+            stats.rows,
+            stats.min_id,
+            stats.max_id
+        );
+        if self.db.timestamps {
+            if let Some((min, max)) = timestamp_bounds(conn, &self.db.quoted_table()).await? {
+                let utc = |secs: i64| {
+                    let at = UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64);
+                    humantime::format_rfc3339_seconds(at).to_string()
+                };
+                eprintln!(
+                    "{} created_at spans {} ..= {} (whole seconds)",
+                    self.db.quoted_table(),
+                    utc(min),
+                    utc(max)
+                );
+            }
+        }
+        let span = (stats.max_id - stats.min_id + 1).max(0) as u64;
+        let max_start = span.saturating_sub(self.select_count as u64);
+        let _ = self.keys.set(KeySpace {
+            starts: Picker::new(self.offset_spread, max_start + 1, self.zipf_theta),
+            min_id: stats.min_id,
+            rows: stats.rows,
+            contiguous: stats.rows == span,
+        });
+        self.harness.plan_statements(|| {
+            let label = match self.threshold {
+                Some(_) => "value filter",
+                None => "range scan",
+            };
+            // The first range: any other has the same plan.
+            let (params, _) = self.next_params(0);
+            vec![PlanStatement::new(
+                label,
+                self.protocol,
+                &self.query,
+                params,
+            )]
+        });
+        Ok(())
+    }
+
+    /// Where the next range scan starts, as an offset from the smallest id.
+    fn next_offset(&mut self) -> u64 {
+        if self.fixed_range || self.threshold.is_some() {
+            return 0;
+        }
+        let keys = self.keys.get().expect("key space is set during setup");
+        keys.starts.pick(&mut self.rng)
+    }
+
+    /// The parameters of the next query, a range starting at `offset` unless it filters by
+    /// value, and the number of rows it must return, when that is known.
+    fn next_params(&self, offset: u64) -> (Vec<Value>, Option<u64>) {
+        match self.threshold {
+            Some(threshold) => (vec![threshold.into()], None),
+            None => {
+                let keys = self.keys.get().expect("key space is set during setup");
+                let lo = keys.min_id + offset as i64;
+                let hi = lo + self.select_count as i64 - 1;
+                let expected = keys
+                    .contiguous
+                    .then(|| keys.rows.min(self.select_count as u64));
+                (vec![lo.into(), hi.into()], expected)
+            }
+        }
+    }
+
+    /// Run the query in its own transaction, unless in auto-commit mode; returns the rows and
+    /// the transaction's phases.
+    async fn fetch(&self, conn: &mut Conn, params: Vec<Value>) -> Result<(Rows, Option<Phases>)> {
+        Ok(match self.db.tx_mode {
+            TxMode::AutoCommit => (self.protocol.query(conn, &self.query, params).await?, None),
+            TxMode::Optimistic | TxMode::Pessimistic => {
+                let mut clock = PhaseClock::start();
+                let mut tx = conn.start_transaction(TxOpts::default()).await?;
+                clock.begun();
+                let rows = self.protocol.query(&mut tx, &self.query, params).await?;
+                clock.executed();
+                self.read_txn_end.finish(tx, false).await?;
+                (rows, Some(clock.committed()))
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl BenchSuite for SelectBench {
+    type WorkerState = Session;
+
+    async fn setup(&mut self, worker_id: u32) -> Result<Self::WorkerState> {
+        let mut conn = self.db.connect_worker(worker_id, Role::Read).await?;
+
+        self.lifecycle
+            .setup_once(self.db.undo_on_error(self.prepare_table(&mut conn)))
+            .await?;
+        self.db.ping(&mut conn, worker_id, Role::Read).await?;
+        self.rng = worker_rng(self.random_seed, worker_id);
+        Ok(self.db.session(conn).await)
+    }
+
+    async fn bench(&mut self, session: &mut Session, info: &IterInfo) -> Result<IterReport> {
+        let offset = self.next_offset();
+        let (params, expected) = self.next_params(offset);
+        let report = self
+            .harness
+            .iter(info, async {
+                let mut lease = session.get().await;
+                let conn = &mut *lease;
+                let result = self.fetch(conn, params).await;
+                if result.is_err() {
+                    lease.fail();
+                }
+                let (result, phases) = result?;
+
+                let bytes = result_bytes(&result);
+                let rows = result.len() as u64;
+                if expected.is_some_and(|expected| !self.row_counts.check(expected, rows)) {
+                    return Ok(Outcome::failure(Status::client_error(
+                        status::ROW_COUNT_MISMATCH,
+                    )));
+                }
+                Ok(Outcome::success(rows, bytes).with_phases(phases))
+            })
+            .await;
+        // After the timed iteration, so rolling back and reconnecting are not latency.
+        let recovered = session.recover(&self.db, info.worker_id, Role::Read).await;
+        let report = report?;
+        recovered?;
+        Ok(report)
+    }
+
+    async fn teardown(self, mut session: Session, _info: IterInfo) -> Result<()> {
+        if session.finish(&self.lifecycle).await {
+            self.harness.capture_final_plans().await;
+            let mut conn = session.get().await;
+            self.db.drop_table(&mut conn, &self.db.table).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(args: &[&str]) -> SelectOpts {
+        SelectOpts::parse_from(std::iter::once("test").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn seed_count_is_a_multiple_of_select_count_unless_given() {
+        assert_eq!(opts(&[]).table_size(), 2000);
+        assert_eq!(opts(&["--select-count", "7"]).table_size(), 14);
+        assert_eq!(
+            opts(&["--select-count", "7", "--table-size", "5"]).table_size(),
+            5
+        );
+    }
+
+    #[test]
+    fn timestamp_spread_needs_the_created_at_column() {
+        assert!(opts(&["--timestamp-spread", "1d"]).validate().is_ok());
+        let without = opts(&["--timestamp-spread", "1d", "--timestamps", "false"]);
+        assert!(without.validate().is_err());
+        let end = ["--timestamp-end", "2024-06-01T00:00:00Z"];
+        assert!(SelectOpts::try_parse_from(std::iter::once("test").chain(end)).is_err());
+    }
+
+    #[test]
+    fn result_bytes_count_the_id_and_data_of_each_row() {
+        assert_eq!(result_bytes(&Vec::new()), 0);
+        let rows = vec![(1, "bench_data_1".to_owned()), (2, "caf\u{e9}".to_owned())];
+        assert_eq!(result_bytes(&rows), 8 + 12 + 8 + 5);
+    }
+
+    #[test]
+    fn seed_count_does_not_overflow_u32() {
+        let opts = opts(&["--select-count", "4294967295"]);
+        assert_eq!(opts.table_size(), 2 * u64::from(u32::MAX));
+    }
+
+    #[test]
+    fn worker_streams_repeat_per_seed_and_differ_between_workers() {
+        let picker = Picker::new(Spread::Uniform, 1_000_000, 0.99);
+        let starts = |seed, worker_id| {
+            let mut rng = worker_rng(seed, worker_id);
+            (0..8).map(|_| picker.pick(&mut rng)).collect::<Vec<_>>()
+        };
+        assert_eq!(starts(7, 0), starts(7, 0));
+        assert_ne!(starts(7, 0), starts(7, 1));
+        assert_ne!(starts(7, 0), starts(8, 0));
+        // Not shifted copies of each other, as adding the worker id to the seed would give.
+        assert_ne!(starts(7, 1), starts(8, 0));
+    }
+}
@@ -0,0 +1,296 @@
+use std::sync::Arc;
+
+use anyhow::{ensure, Result};
+use async_trait::async_trait;
+use clap::Parser;
+use mysql_async::prelude::*;
+use mysql_async::{Conn, TxOpts};
+use rand::Rng;
+use rlt::{BenchSuite, IterInfo, IterReport};
+
+use crate::distributions::Uniform;
+use crate::harness::{Harness, Outcome, PhaseClock, Phases, RunOpts};
+use crate::lifecycle::Lifecycle;
+use crate::plan::PlanStatement;
+use crate::seed::{prepare_table, SeedOpts, SeedSpec};
+use crate::session::Session;
+use crate::suites::{BenchOpts, Benchmark};
+use crate::{payload, DbOpts, Role, TxMode};
+
+const VALUES: Uniform = Uniform::new(1000);
+
+/// How the subquery is presented to the optimizer.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum SubqueryForm {
+    /// Plain correlated subquery; TiDB is free to decorrelate it into a join.
+    Decorrelate,
+    /// Same query with a `NO_DECORRELATE()` hint, forcing per-row evaluation.
+    Correlated,
+}
+
+/// TiDB correlated subquery benchmark.
+#[derive(Parser, Clone)]
+pub struct SubqueryOpts {
+    #[command(flatten)]
+    pub db: DbOpts,
+
+    /// Number of rows preloaded into each of the two tables.
+    #[clap(long, default_value_t = 10000, value_parser = clap::value_parser!(u32).range(1..))]
+    pub rows: u32,
+
+    /// Number of distinct correlation keys.
+    #[clap(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub keys: u32,
+
+    /// Number of correlation keys covered by each query; at most `--keys`.
+    #[clap(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    pub keys_per_query: u32,
+
+    /// Subquery form.
+    #[clap(long, value_enum, default_value = "decorrelate")]
+    pub form: SubqueryForm,
+
+    #[command(flatten)]
+    pub seed: SeedOpts,
+
+    #[command(flatten)]
+    pub run: RunOpts,
+}
+
+impl BenchOpts for SubqueryOpts {
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn db_mut(&mut self) -> &mut DbOpts {
+        &mut self.db
+    }
+
+    fn run(&self) -> &RunOpts {
+        &self.run
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = self.db.settings();
+        settings.extend(self.run.settings());
+        settings
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.keys_per_query <= self.keys,
+            "--keys-per-query {} exceeds --keys {}",
+            self.keys_per_query,
+            self.keys
+        );
+        Ok(())
+    }
+}
+
+/// `bench-subquery`: correlated subqueries over two preloaded tables.
+#[derive(Clone)]
+pub struct SubqueryBench {
+    db: DbOpts,
+    rows: u32,
+    keys: u32,
+    keys_per_query: u32,
+    form: SubqueryForm,
+    seed: SeedOpts,
+    harness: Harness,
+    lifecycle: Arc<Lifecycle>,
+}
+
+impl Benchmark for SubqueryBench {
+    type Opts = SubqueryOpts;
+    const NAME: &'static str = "bench-subquery";
+    const PRIVILEGES: &'static [&'static str] = &["SELECT"];
+
+    fn new(opts: &SubqueryOpts, workers: u32) -> Self {
+        Self {
+            db: opts.db.clone(),
+            rows: opts.rows,
+            keys: opts.keys,
+            keys_per_query: opts.keys_per_query,
+            form: opts.form,
+            seed: opts.seed.clone(),
+            harness: Harness::new(&opts.run, &opts.db, workers),
+            lifecycle: Arc::new(Lifecycle::new(workers)),
+        }
+    }
+
+    fn db(&self) -> &DbOpts {
+        &self.db
+    }
+
+    fn harness(&self) -> &Harness {
+        &self.harness
+    }
+
+    fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    fn tables(&self) -> Vec<String> {
+        vec![self.db.table.clone(), self.db.aux_table("inner")]
+    }
+}
+
+impl SubqueryBench {
+    fn inner_table(&self) -> String {
+        self.db.quoted_aux_table("inner")
+    }
+
+    /// Create `table` and preload it, spreading rows evenly over the key space.
+    async fn prepare_table(&self, conn: &mut Conn, table: &str, salt: u64) -> Result<()> {
+        let keys = self.keys as u64;
+        let spec = SeedSpec {
+            table,
+            schema: "id BIGINT PRIMARY KEY AUTO_INCREMENT,
+                     k INT NOT NULL,
+                     value INT NOT NULL,
+                     KEY idx_k (k)",
+            columns: "id, k, value",
+            rows: self.rows as u64,
+            row: Arc::new(move |i| {
+                format!("({}, {}, {})", i + 1, i % keys, VALUES.value_at(i + salt))
+            }),
+            ids: 1..=self.rows as i64,
+            generator: &format!("subquery-v1 keys={keys} salt={salt}"),
+            csv: None,
+            created_at: false,
+        };
+        prepare_table(conn, &self.db, &self.seed, spec).await
+    }
+
+    /// Random range of `--keys-per-query` keys for the next query.
+    fn next_keys(&self) -> (u32, u32) {
+        let lo = rand::thread_rng().gen_range(0..=self.keys - self.keys_per_query);
+        (lo, lo + self.keys_per_query - 1)
+    }
+
+    fn build_query(&self, lo: u32, hi: u32) -> String {
+        let outer = self.db.quoted_table();
+        let inner = self.inner_table();
+        let hint = match self.form {
+            SubqueryForm::Decorrelate => "",
+            SubqueryForm::Correlated => "/*+ NO_DECORRELATE() */ ",
+        };
+        format!(
+            "SELECT t1.id FROM {outer} t1 \
+             WHERE t1.k BETWEEN {lo} AND {hi} \
+             AND t1.value > (SELECT {hint}AVG(t2.value) FROM {inner} t2 WHERE t2.k = t1.k)"
+        )
+    }
+}
+
+/// Run `query` in its own transaction, unless in auto-commit mode; returns the ids and the
+/// transaction's phases.
+async fn fetch_ids(
+    conn: &mut Conn,
+    tx_mode: &TxMode,
+    query: &str,
+) -> Result<(Vec<i64>, Option<Phases>)> {
+    Ok(match tx_mode {
+        TxMode::AutoCommit => (conn.query(query).await?, None),
+        TxMode::Optimistic | TxMode::Pessimistic => {
+            let mut clock = PhaseClock::start();
+            let mut tx = conn.start_transaction(TxOpts::default()).await?;
+            clock.begun();
+            let rows = tx.query(query).await?;
+            clock.executed();
+            tx.commit().await?;
+            (rows, Some(clock.committed()))
+        }
+    })
+}
+
+#[async_trait]
+impl BenchSuite for SubqueryBench {
+    type WorkerState = Session;
+
+    async fn setup(&mut self, worker_id: u32) -> Result<Self::WorkerState> {
+        let mut conn = self.db.connect_worker(worker_id, Role::Read).await?;
+
+        self.lifecycle
+            .setup_once(self.db.undo_on_error(async {
+                self.prepare_table(&mut conn, &self.db.table, 0).await?;
+                self.prepare_table(&mut conn, &self.db.aux_table("inner"), 1)
+                    .await?;
+                self.harness.plan_statements(|| {
+                    let (lo, hi) = self.next_keys();
+                    vec![PlanStatement::text("subquery", self.build_query(lo, hi))]
+                });
+                Ok(())
+            }))
+            .await?;
+        self.db.ping(&mut conn, worker_id, Role::Read).await?;
+        Ok(self.db.session(conn).await)
+    }
+
+    async fn bench(&mut self, session: &mut Session, info: &IterInfo) -> Result<IterReport> {
+        let (lo, hi) = self.next_keys();
+        let query = self.build_query(lo, hi);
+
+        let report = self
+            .harness
+            .iter(info, async {
+                let mut lease = session.get().await;
+                let conn = &mut *lease;
+                let ids = fetch_ids(conn, &self.db.tx_mode, &query).await;
+                if ids.is_err() {
+                    lease.fail();
+                }
+                let (ids, phases) = ids?;
+
+                Ok(
+                    Outcome::success(ids.len() as u64, ids.len() as u64 * payload::BIGINT)
+                        .with_phases(phases),
+                )
+            })
+            .await;
+        // After the timed iteration, so rolling back and reconnecting are not latency.
+        let recovered = session.recover(&self.db, info.worker_id, Role::Read).await;
+        let report = report?;
+        recovered?;
+        Ok(report)
+    }
+
+    async fn teardown(self, mut session: Session, _info: IterInfo) -> Result<()> {
+        if session.finish(&self.lifecycle).await {
+            self.harness.capture_final_plans().await;
+            let mut conn = session.get().await;
+            for table in [self.db.table.clone(), self.db.aux_table("inner")] {
+                self.db.drop_table(&mut conn, &table).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(args: &[&str]) -> Result<SubqueryOpts> {
+        let opts =
+            SubqueryOpts::try_parse_from(std::iter::once("test").chain(args.iter().copied()))?;
+        opts.validate()?;
+        Ok(opts)
+    }
+
+    #[test]
+    fn zero_counts_are_rejected() {
+        assert!(opts(&["--rows", "0"]).is_err());
+        assert!(opts(&["--keys", "0"]).is_err());
+        assert!(opts(&["--keys-per-query", "0"]).is_err());
+    }
+
+    #[test]
+    fn keys_per_query_is_bounded_by_keys() {
+        assert!(opts(&["--keys", "10", "--keys-per-query", "10"]).is_ok());
+        let err = opts(&["--keys", "10", "--keys-per-query", "11"])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("exceeds --keys 10"), "{err}");
+    }
+}
@@ -0,0 +1,137 @@
+//! Plans of slow iterations (`--trace-slow`).
+//!
+//! When an iteration takes longer than the threshold, a separate connection looks up the
+//! slowest benchmark statement that finished during it in TiDB's slow query log
+//! (`INFORMATION_SCHEMA.CLUSTER_SLOW_QUERY`) and decodes its plan with `TIDB_DECODE_PLAN`.
+//! Only one lookup runs at a time, off the workers' connections, so fast iterations pay
+//! nothing and slow ones are not slowed down further.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use mysql_async::prelude::*;
+use tokio::task::JoinHandle;
+
+use crate::{quote_str, DbOpts};
+
+/// Plans kept for printing after the run; later slow iterations are only counted.
+const MAX_TRACES: usize = 20;
+
+/// Added to the look-back window, for the clock skew between client and TiDB nodes.
+const WINDOW_SLACK: Duration = Duration::from_secs(1);
+
+/// One slow iteration and the plan of its slowest statement.
+struct Trace {
+    latency: Duration,
+    /// Statement, server time and decoded plan, or why they are unknown.
+    plan: Result<(String, f64, String), String>,
+}
+
+/// Shared by all workers.
+pub struct SlowTrace {
+    db: DbOpts,
+    threshold: Duration,
+    slow: AtomicU64,
+    /// The lookup in flight, if any.
+    pending: Mutex<Option<JoinHandle<()>>>,
+    traces: Mutex<Vec<Trace>>,
+}
+
+impl SlowTrace {
+    pub fn new(db: &DbOpts, threshold: Duration) -> Self {
+        Self {
+            db: db.clone(),
+            threshold,
+            slow: AtomicU64::new(0),
+            pending: Mutex::default(),
+            traces: Mutex::default(),
+        }
+    }
+
+    /// Record the latency of an iteration that just completed, and look up its plan if it
+    /// was slow, no lookup is in flight and fewer than [`MAX_TRACES`] were taken.
+    pub fn observe(self: &Arc<Self>, latency: Duration) {
+        if latency <= self.threshold {
+            return;
+        }
+        self.slow.fetch_add(1, Ordering::Relaxed);
+        let mut pending = self.pending.lock().unwrap();
+        if pending.as_ref().is_some_and(|task| !task.is_finished())
+            || self.traces.lock().unwrap().len() >= MAX_TRACES
+        {
+            return;
+        }
+        let trace = self.clone();
+        *pending = Some(tokio::spawn(async move {
+            let plan = trace.lookup(latency).await.map_err(|e| format!("{e:#}"));
+            match &plan {
+                Ok((query, secs, _)) => eprintln!(
+                    "slow iteration ({latency:.2?}): slowest statement took {secs:.3}s: {query}"
+                ),
+                Err(e) => eprintln!("warning: slow iteration ({latency:.2?}): no plan: {e}"),
+            }
+            trace.traces.lock().unwrap().push(Trace { latency, plan });
+        }));
+    }
+
+    /// The slowest statement on the benchmark's database that finished within `latency`
+    /// (plus slack) before now, with its decoded plan.
+    async fn lookup(&self, latency: Duration) -> Result<(String, f64, String)> {
+        let mut conn = self.db.connect().await?;
+        let window = (latency + WINDOW_SLACK).as_micros();
+        let row: Option<(String, f64, Option<String>)> = conn
+            .query_first(format!(
+                "SELECT Query, Query_time, TIDB_DECODE_PLAN(Plan) \
+                 FROM INFORMATION_SCHEMA.CLUSTER_SLOW_QUERY \
+                 WHERE Time >= NOW(6) - INTERVAL {window} MICROSECOND \
+                 AND DB = {} AND Is_internal = 0 \
+                 ORDER BY Query_time DESC LIMIT 1",
+                quote_str(&self.db.database)
+            ))
+            .await?;
+        let _ = conn.disconnect().await;
+        let (query, secs, plan) = row.ok_or_else(|| {
+            anyhow::anyhow!(
+                "no statement in the slow query log; is tidb_slow_log_threshold above {}?",
+                humantime::format_duration(self.threshold)
+            )
+        })?;
+        Ok((query, secs, plan.unwrap_or_default()))
+    }
+
+    /// Wait for the lookup in flight, if any. Call once, after the run.
+    pub async fn finish(&self) {
+        let pending = self.pending.lock().unwrap().take();
+        if let Some(task) = pending {
+            let _ = task.await;
+        }
+    }
+
+    /// Print the slow iterations and the plans taken. Call once, after
+    /// [`SlowTrace::finish`].
+    pub fn print(&self) {
+        let slow = self.slow.load(Ordering::Relaxed);
+        let traces = self.traces.lock().unwrap();
+        eprintln!(
+            "slow iterations (over {}): {slow}, {} traced",
+            humantime::format_duration(self.threshold),
+            traces.len()
+        );
+        for trace in traces.iter() {
+            match &trace.plan {
+                Ok((query, secs, plan)) => {
+                    eprintln!(
+                        "  iteration {:.2?}, slowest statement {secs:.3}s: {query}",
+                        trace.latency
+                    );
+                    for line in plan.lines() {
+                        eprintln!("    {line}");
+                    }
+                }
+                Err(e) => eprintln!("  iteration {:.2?}: {e}", trace.latency),
+            }
+        }
+    }
+}
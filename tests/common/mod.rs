@@ -0,0 +1,110 @@
+//! Shared by the integration tests. They run against the server of `TIDB_TEST_URL`, e.g.
+//! `mysql://root@127.0.0.1:4000/test`, and are skipped when it is not set.
+
+#![allow(dead_code)]
+
+use std::process::{Child, Command, ExitStatus};
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use mysql_async::prelude::*;
+use mysql_async::{Conn, Opts};
+use tidb_bench::DbOpts;
+
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    db: DbOpts,
+}
+
+/// The connection arguments of `TIDB_TEST_URL`, e.g. `--host` and `--user`, or `None` (and a
+/// note on stderr) if it is not set.
+pub fn server() -> Option<Vec<String>> {
+    let Ok(url) = std::env::var("TIDB_TEST_URL") else {
+        eprintln!("TIDB_TEST_URL is not set; skipping");
+        return None;
+    };
+    let opts = Opts::from_url(&url).expect("TIDB_TEST_URL is a mysql:// URL");
+    let mut args = vec![
+        "--host".to_owned(),
+        opts.ip_or_hostname().to_owned(),
+        "--port".to_owned(),
+        opts.tcp_port().to_string(),
+    ];
+    if let Some(user) = opts.user() {
+        args.extend(["--user".to_owned(), user.to_owned()]);
+    }
+    if let Some(pass) = opts.pass() {
+        args.extend(["--password".to_owned(), pass.to_owned()]);
+    }
+    if let Some(db) = opts.db_name() {
+        args.extend(["--database".to_owned(), db.to_owned()]);
+    }
+    Some(args)
+}
+
+/// Options of the server's connection arguments followed by `args`.
+pub fn db_opts(server: &[String], args: &[&str]) -> DbOpts {
+    let args = server
+        .iter()
+        .map(String::as_str)
+        .chain(args.iter().copied());
+    Cli::parse_from(std::iter::once("test").chain(args)).db
+}
+
+/// Whether `table` (unquoted) exists in the connection's database.
+pub async fn table_exists(conn: &mut Conn, table: &str) -> bool {
+    let found: Option<u8> = conn
+        .exec_first(
+            "SELECT 1 FROM information_schema.TABLES \
+             WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ?",
+            (table,),
+        )
+        .await
+        .unwrap();
+    found.is_some()
+}
+
+/// Start the benchmark binary at `bench_exe` (`env!("CARGO_BIN_EXE_bench-insert")`) with the
+/// server's connection arguments and `args`, its report discarded.
+pub fn spawn_bench(bench_exe: &str, server: &[String], args: &[&str]) -> Child {
+    Command::new(bench_exe)
+        .args(server)
+        .args(args)
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .expect("the benchmark binary starts")
+}
+
+/// Wait until `table` (unquoted) holds rows, i.e. a benchmark writing it is under way.
+pub async fn wait_for_rows(conn: &mut Conn, table: &str, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    let count = format!("SELECT COUNT(*) FROM {}", tidb_bench::quote_ident(table));
+    loop {
+        if let Ok(Some(rows)) = conn.query_first::<u64, _>(&count).await {
+            if rows > 0 {
+                return;
+            }
+        }
+        assert!(
+            Instant::now() < deadline,
+            "{table} got no rows within {timeout:?}"
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Wait up to `timeout` for `child` to exit, killing it if it does not.
+pub fn wait_for(child: &mut Child, timeout: Duration) -> ExitStatus {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status;
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            panic!("the benchmark did not exit within {timeout:?}");
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
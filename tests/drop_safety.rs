@@ -0,0 +1,112 @@
+//! Tables the benchmarks did not create are only dropped with `--force-drop`.
+
+mod common;
+
+use mysql_async::prelude::*;
+use mysql_async::Conn;
+use tidb_bench::{quote_ident, TABLE_TAG};
+
+const SCHEMA: &str = "id BIGINT PRIMARY KEY";
+
+/// Replace `table` with an untagged table holding a row, as if it held real data.
+async fn foreign_table(conn: &mut Conn, table: &str) {
+    let quoted = quote_ident(table);
+    conn.query_drop(format!("DROP TABLE IF EXISTS {quoted}"))
+        .await
+        .unwrap();
+    conn.query_drop(format!("CREATE TABLE {quoted} (id BIGINT PRIMARY KEY)"))
+        .await
+        .unwrap();
+    conn.query_drop(format!("INSERT INTO {quoted} VALUES (1)"))
+        .await
+        .unwrap();
+}
+
+async fn rows(conn: &mut Conn, table: &str) -> u64 {
+    let count: Option<u64> = conn
+        .query_first(format!("SELECT COUNT(*) FROM {}", quote_ident(table)))
+        .await
+        .unwrap();
+    count.unwrap()
+}
+
+async fn comment(conn: &mut Conn, table: &str) -> String {
+    let comment: Option<String> = conn
+        .exec_first(
+            "SELECT TABLE_COMMENT FROM information_schema.TABLES \
+             WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ?",
+            (table,),
+        )
+        .await
+        .unwrap();
+    comment.unwrap()
+}
+
+#[tokio::test]
+async fn untagged_table_with_rows_is_refused() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let table = "it_drop_refused";
+    let db = common::db_opts(&server, &["--table", table]);
+    let mut conn = db.connect().await.unwrap();
+    foreign_table(&mut conn, table).await;
+
+    let e = db.create_table(&mut conn, table, SCHEMA).await.unwrap_err();
+    assert!(format!("{e:#}").contains("refusing to drop"), "{e:#}");
+    assert_eq!(rows(&mut conn, table).await, 1);
+
+    // Teardown leaves alone a table this run did not create.
+    db.drop_table(&mut conn, table).await.unwrap();
+    assert_eq!(rows(&mut conn, table).await, 1);
+
+    conn.query_drop(format!("DROP TABLE {}", quote_ident(table)))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn force_drop_replaces_the_table() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let table = "it_drop_forced";
+    let db = common::db_opts(&server, &["--table", table, "--force-drop"]);
+    let mut conn = db.connect().await.unwrap();
+    foreign_table(&mut conn, table).await;
+
+    db.create_table(&mut conn, table, SCHEMA).await.unwrap();
+    assert_eq!(rows(&mut conn, table).await, 0);
+    assert!(comment(&mut conn, table).await.starts_with(TABLE_TAG));
+
+    db.drop_table(&mut conn, table).await.unwrap();
+    assert!(!common::table_exists(&mut conn, table).await);
+}
+
+#[tokio::test]
+async fn tagged_and_empty_tables_are_replaced_without_force() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let table = "it_drop_tagged";
+    let db = common::db_opts(&server, &["--table", table]);
+    let mut conn = db.connect().await.unwrap();
+
+    // Left behind by an earlier run, e.g. with --keep-table.
+    db.create_table(&mut conn, table, SCHEMA).await.unwrap();
+    conn.query_drop(format!("INSERT INTO {} VALUES (1)", quote_ident(table)))
+        .await
+        .unwrap();
+    db.create_table(&mut conn, table, SCHEMA).await.unwrap();
+    assert_eq!(rows(&mut conn, table).await, 0);
+
+    foreign_table(&mut conn, table).await;
+    conn.query_drop(format!("DELETE FROM {}", quote_ident(table)))
+        .await
+        .unwrap();
+    db.create_table(&mut conn, table, SCHEMA).await.unwrap();
+    assert!(comment(&mut conn, table).await.starts_with(TABLE_TAG));
+
+    db.drop_table(&mut conn, table).await.unwrap();
+    assert!(!common::table_exists(&mut conn, table).await);
+}
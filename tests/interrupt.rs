@@ -0,0 +1,75 @@
+//! Ctrl-C handling of a benchmark run, driven by sending SIGINT to a child process.
+
+#![cfg(unix)]
+
+mod common;
+
+use std::process::{Child, Command};
+use std::time::Duration;
+
+use mysql_async::prelude::*;
+use tidb_bench::interrupt::EXIT_INTERRUPTED;
+
+const BENCH: &str = env!("CARGO_BIN_EXE_bench-insert");
+
+fn interrupt(child: &Child) {
+    let sent = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(sent.success());
+}
+
+#[tokio::test]
+async fn ctrl_c_drops_the_tables_and_exits_130() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let table = "it_interrupt";
+    let db = common::db_opts(&server, &["--table", table]);
+    let mut conn = db.connect().await.unwrap();
+    let mut child = common::spawn_bench(
+        BENCH,
+        &server,
+        &["--table", table, "-c", "2", "-d", "5m", "-q"],
+    );
+    common::wait_for_rows(&mut conn, table, Duration::from_secs(60)).await;
+
+    interrupt(&child);
+    let status = common::wait_for(&mut child, Duration::from_secs(30));
+    assert_eq!(status.code(), Some(EXIT_INTERRUPTED));
+    assert!(!common::table_exists(&mut conn, table).await);
+    assert!(!common::table_exists(&mut conn, &db.aux_table("key_ranges")).await);
+}
+
+#[tokio::test]
+async fn second_ctrl_c_exits_at_once() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let table = "it_interrupt_twice";
+    let db = common::db_opts(&server, &["--table", table]);
+    let mut conn = db.connect().await.unwrap();
+    let mut child = common::spawn_bench(
+        BENCH,
+        &server,
+        &["--table", table, "-c", "2", "-d", "5m", "-q"],
+    );
+    common::wait_for_rows(&mut conn, table, Duration::from_secs(60)).await;
+
+    interrupt(&child);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    interrupt(&child);
+    let status = common::wait_for(&mut child, Duration::from_secs(10));
+    assert_eq!(status.code(), Some(EXIT_INTERRUPTED));
+
+    // Exiting without cleanup leaves the tables behind.
+    for table in [table.to_owned(), db.aux_table("key_ranges")] {
+        conn.query_drop(format!(
+            "DROP TABLE IF EXISTS {}",
+            tidb_bench::quote_ident(&table)
+        ))
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+//! Consecutive `bench-insert --no-create` runs against one table never repeat a value.
+
+mod common;
+
+use std::process::{Command, Stdio};
+
+use mysql_async::prelude::*;
+
+const BENCH: &str = env!("CARGO_BIN_EXE_bench-insert");
+
+/// Insert 20 batches of 10 rows into `table` with two workers and `args`.
+fn run(server: &[String], table: &str, args: &[&str]) -> std::process::Output {
+    Command::new(BENCH)
+        .args(server)
+        .args([
+            "--table",
+            table,
+            "--batch-size",
+            "10",
+            "-c",
+            "2",
+            "-n",
+            "20",
+            "-q",
+        ])
+        .args(args)
+        .stdout(Stdio::null())
+        .output()
+        .expect("the benchmark binary starts")
+}
+
+#[tokio::test]
+async fn consecutive_runs_insert_no_duplicate_values() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let table = "it_key_ranges";
+    let ranges = format!("{table}_key_ranges");
+    let db = common::db_opts(&server, &[]);
+    let mut conn = db.connect().await.unwrap();
+    for t in [table, ranges.as_str()] {
+        conn.query_drop(format!("DROP TABLE IF EXISTS {t}"))
+            .await
+            .unwrap();
+    }
+
+    // The first run creates the tables and keeps them for the `--no-create` ones.
+    let first = run(
+        &server,
+        table,
+        &["--key-layout", "scattered", "--keep-table"],
+    );
+    assert!(first.status.success());
+    conn.query_drop(format!("DROP TABLE {ranges}"))
+        .await
+        .unwrap();
+
+    let no_create = ["--key-layout", "scattered", "--no-create"];
+    let missing = run(&server, table, &no_create);
+    assert!(!missing.status.success());
+    let stderr = String::from_utf8_lossy(&missing.stderr);
+    assert!(stderr.contains("pass --create-key-ranges"), "{stderr}");
+    assert!(!common::table_exists(&mut conn, &ranges).await);
+
+    let created = run(
+        &server,
+        table,
+        &[&no_create[..], &["--create-key-ranges"]].concat(),
+    );
+    assert!(created.status.success());
+    let next = run(&server, table, &no_create);
+    assert!(next.status.success());
+
+    let (rows, ids, data): (u64, u64, u64) = conn
+        .query_first(format!(
+            "SELECT COUNT(*), COUNT(DISTINCT id), COUNT(DISTINCT data) FROM {table}"
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(rows, 3 * 20 * 10);
+    assert_eq!(ids, rows);
+    assert_eq!(data, rows);
+
+    for t in [table, ranges.as_str()] {
+        conn.query_drop(format!("DROP TABLE {t}")).await.unwrap();
+    }
+}
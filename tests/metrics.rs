@@ -0,0 +1,78 @@
+//! The `--metrics-listen` endpoint of a running benchmark, scraped like Prometheus would.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+const BENCH: &str = env!("CARGO_BIN_EXE_bench-insert");
+
+/// The body of a `GET /metrics` to `addr`, if it answers.
+fn scrape(addr: &str) -> Option<String> {
+    let mut stream = TcpStream::connect(addr).ok()?;
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+    let (_, body) = response.split_once("\r\n\r\n")?;
+    Some(body.to_owned())
+}
+
+#[test]
+fn endpoint_serves_the_metrics_during_the_run() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    // A port that was free a moment ago.
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+    let mut child = common::spawn_bench(
+        BENCH,
+        &server,
+        &[
+            "--table",
+            "it_metrics",
+            "--metrics-listen",
+            &addr,
+            "-c",
+            "2",
+            "-d",
+            "10s",
+            "-q",
+        ],
+    );
+
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let body = loop {
+        match scrape(&addr) {
+            Some(body) if body.contains("tidb_bench_iterations_total{") => break body,
+            _ if Instant::now() > deadline => {
+                let _ = child.kill();
+                panic!("no iterations were scraped from {addr}");
+            }
+            _ => std::thread::sleep(Duration::from_millis(200)),
+        }
+    };
+    for expected in [
+        "tidb_bench_iterations_total{bench=\"bench-insert\",worker=\"",
+        "status=\"success\"",
+        "tidb_bench_items_total{bench=\"bench-insert\"",
+        "tidb_bench_bytes_total{bench=\"bench-insert\"",
+        "tidb_bench_iteration_latency_seconds_bucket{bench=\"bench-insert\"",
+        "le=\"+Inf\"",
+        "tidb_bench_iteration_latency_seconds_count{",
+        "tidb_bench_active_workers{bench=\"bench-insert\"}",
+    ] {
+        assert!(body.contains(expected), "missing {expected} in:\n{body}");
+    }
+
+    let status = common::wait_for(&mut child, Duration::from_secs(60));
+    assert!(status.success());
+    assert!(TcpStream::connect(&addr).is_err());
+}
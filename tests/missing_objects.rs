@@ -0,0 +1,63 @@
+//! A missing database or `--no-create` table fails the run with a hint naming it.
+
+mod common;
+
+use std::process::Command;
+
+const BENCH: &str = env!("CARGO_BIN_EXE_bench-select");
+
+/// Run `bench-select` for one iteration with `args`, expecting it to fail, and return its
+/// stderr.
+fn failed_run(args: &[&str]) -> String {
+    let output = Command::new(BENCH)
+        .args(args)
+        .args(["-c", "1", "-n", "1", "-q"])
+        .output()
+        .expect("the benchmark binary starts");
+    assert!(!output.status.success());
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn missing_database_fails_with_a_hint() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    // The server's own --database, if any, gives way to the missing one.
+    let mut args: Vec<&str> = Vec::new();
+    let mut server = server.iter().map(String::as_str);
+    while let Some(arg) = server.next() {
+        if arg == "--database" {
+            server.next();
+        } else {
+            args.push(arg);
+        }
+    }
+    args.extend(["--database", "it_no_such_database"]);
+
+    let stderr = failed_run(&args);
+    assert!(
+        stderr.contains("database `it_no_such_database` does not exist; create it first"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("1049"), "{stderr}");
+}
+
+#[test]
+fn missing_table_fails_with_a_hint_under_no_create() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let mut args: Vec<&str> = server.iter().map(String::as_str).collect();
+    args.extend(["--table", "it_no_such_table", "--no-create"]);
+
+    let stderr = failed_run(&args);
+    assert!(
+        stderr.contains(".`it_no_such_table` does not exist"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("--no-create expects it to be provisioned already"),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,72 @@
+//! `bench-select --random-seed` repeats the ranges of a run, and another seed reads others.
+
+mod common;
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use mysql_async::prelude::*;
+
+const TABLE: &str = "it_random_seed";
+
+/// Run 50 range scans of 10 ids with one worker and `seed`, as SQL text that the slow query
+/// log written to `log` records with its bounds, and return their `BETWEEN` clauses.
+fn ranges(server: &[String], seed: &str, log: &Path) -> BTreeSet<String> {
+    let status = Command::new(env!("CARGO_BIN_EXE_bench-select"))
+        .args(server)
+        .args([
+            "--table",
+            TABLE,
+            "--table-size",
+            "100000",
+            "--select-count",
+            "10",
+        ])
+        .args(["--protocol", "text", "--random-seed", seed, "--keep-table"])
+        .args(["--slow-log-threshold", "0s", "--collect-slow-queries"])
+        .arg(log)
+        .args(["-c", "1", "-n", "50", "-q"])
+        .stdout(Stdio::null())
+        .status()
+        .expect("the benchmark binary starts");
+    assert!(status.success());
+    let records: Vec<serde_json::Value> =
+        serde_json::from_reader(std::fs::File::open(log).unwrap()).unwrap();
+    let ranges: BTreeSet<String> = records
+        .iter()
+        .filter_map(|record| record["Query"].as_str())
+        .filter(|query| query.starts_with("SELECT id, data FROM"))
+        .filter_map(|query| {
+            query
+                .split_once("BETWEEN")
+                .map(|(_, range)| range.trim().into())
+        })
+        .collect();
+    assert!(!ranges.is_empty(), "no range scans in the slow query log");
+    ranges
+}
+
+#[tokio::test]
+async fn seeds_choose_the_ranges() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let dir = std::env::temp_dir();
+    let log = |name: &str| dir.join(format!("tidb-bench-random-seed-{name}.json"));
+
+    let first = ranges(&server, "1", &log("first"));
+    let again = ranges(&server, "1", &log("again"));
+    let other = ranges(&server, "2", &log("other"));
+    assert_eq!(first, again);
+    assert_ne!(first, other);
+
+    let db = common::db_opts(&server, &[]);
+    let mut conn = db.connect().await.unwrap();
+    conn.query_drop(format!("DROP TABLE {TABLE}"))
+        .await
+        .unwrap();
+    for name in ["first", "again", "other"] {
+        let _ = std::fs::remove_file(log(name));
+    }
+}
@@ -0,0 +1,74 @@
+//! Session settings are applied when a connection opens, never inside the timed iterations.
+
+mod common;
+
+use std::time::Duration;
+
+use mysql_async::prelude::*;
+use mysql_async::Conn;
+
+const BENCH: &str = env!("CARGO_BIN_EXE_bench-select");
+
+/// `SET` statements the server has executed, from the statement summary.
+async fn set_statements(conn: &mut Conn) -> u64 {
+    let count: Option<u64> = conn
+        .query_first(
+            "SELECT CAST(COALESCE(SUM(EXEC_COUNT), 0) AS UNSIGNED) \
+             FROM information_schema.STATEMENTS_SUMMARY WHERE STMT_TYPE = 'Set'",
+        )
+        .await
+        .unwrap();
+    count.unwrap()
+}
+
+/// `SET` statements a `bench-select` run of `iterations` iterations sends.
+async fn sets_of_run(conn: &mut Conn, server: &[String], iterations: u32) -> u64 {
+    let before = set_statements(conn).await;
+    let iterations = iterations.to_string();
+    let mut child = common::spawn_bench(
+        BENCH,
+        server,
+        &[
+            "--table",
+            "it_session_init",
+            "--table-size",
+            "100",
+            "--select-count",
+            "10",
+            "--tx-mode",
+            "pessimistic",
+            "--lock-wait-timeout",
+            "7",
+            "--set",
+            "tidb_mem_quota_query=1073741824",
+            "-c",
+            "1",
+            "-n",
+            &iterations,
+            "-q",
+        ],
+    );
+    assert!(common::wait_for(&mut child, Duration::from_secs(120)).success());
+    set_statements(conn).await - before
+}
+
+#[tokio::test]
+async fn iterations_send_no_set_statements() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let db = common::db_opts(&server, &[]);
+    let mut conn = db.connect().await.unwrap();
+
+    let short = sets_of_run(&mut conn, &server, 10).await;
+    if short == 0 {
+        eprintln!("the statement summary does not record SET statements; skipping");
+        return;
+    }
+    // Setup sends the same statements however long the run is; any more came from the loop.
+    let long = sets_of_run(&mut conn, &server, 510).await;
+    assert_eq!(
+        long, short,
+        "SET statements grew with the number of iterations"
+    );
+}
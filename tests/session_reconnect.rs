@@ -0,0 +1,158 @@
+//! Session settings survive a worker connection being killed between iterations, and a
+//! transaction an iteration failed in does not leak into the next one.
+
+mod common;
+
+use mysql_async::prelude::*;
+use mysql_async::Conn;
+use tidb_bench::Role;
+
+/// Kill connection `id` from another connection, as a server restart or an operator would.
+async fn kill(killer: &mut Conn, id: u64) {
+    // TiDB without global kill only takes its own form of the statement.
+    if killer.query_drop(format!("KILL {id}")).await.is_err() {
+        killer.query_drop(format!("KILL TIDB {id}")).await.unwrap();
+    }
+}
+
+/// Run iterations on a worker session, with `extra` arguments, killing its connection
+/// between two of them, and check that the next iteration runs on a fresh connection that is still
+/// pessimistic.
+async fn killed_connection_is_replaced(extra: &[&str]) {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let mut args = vec!["--tx-mode", "pessimistic", "--lock-wait-timeout", "7"];
+    args.extend(extra);
+    let db = common::db_opts(&server, &args);
+    let mut killer = db.connect().await.unwrap();
+    let conn = db.connect_worker(0, Role::Write).await.unwrap();
+    let mut session = db.session(conn).await;
+
+    let mut lease = session.get().await;
+    let first: Option<u64> = lease.query_first("SELECT CONNECTION_ID()").await.unwrap();
+    let first = first.unwrap();
+    drop(lease);
+
+    kill(&mut killer, first).await;
+
+    // The iteration after the kill fails, and the worker recovers after it.
+    let mut lease = session.get().await;
+    assert!(lease.query_drop("SELECT 1").await.is_err());
+    lease.fail();
+    drop(lease);
+    session.recover(&db, 0, Role::Write).await.unwrap();
+
+    let mut lease = session.get().await;
+    let (id, mode, timeout): (u64, String, u64) = lease
+        .query_first(
+            "SELECT CONNECTION_ID(), @@SESSION.tidb_txn_mode, @@SESSION.innodb_lock_wait_timeout",
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_ne!(id, first);
+    assert_eq!(mode, "pessimistic");
+    assert_eq!(timeout, 7);
+}
+
+#[tokio::test]
+async fn killed_worker_connection_reconnects_pessimistic() {
+    killed_connection_is_replaced(&[]).await;
+}
+
+#[tokio::test]
+async fn killed_pooled_connection_reconnects_pessimistic() {
+    killed_connection_is_replaced(&["--connections", "1"]).await;
+}
+
+/// `@@tidb_current_ts`, the start timestamp of the open transaction or 0 without one.
+async fn current_ts(conn: &mut Conn) -> u64 {
+    let ts: Option<u64> = conn
+        .query_first("SELECT @@SESSION.tidb_current_ts")
+        .await
+        .unwrap();
+    ts.unwrap()
+}
+
+/// Fail an iteration with a duplicate key inside an explicit transaction that already wrote
+/// a row of `table`, and check that the next iteration starts a new transaction with the
+/// row gone.
+async fn failed_transaction_is_rolled_back(table: &str, extra: &[&str]) {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let mut args = vec!["--tx-mode", "pessimistic"];
+    args.extend(extra);
+    let db = common::db_opts(&server, &args);
+    let mut admin = db.connect().await.unwrap();
+    admin
+        .query_drop(format!("DROP TABLE IF EXISTS {table}"))
+        .await
+        .unwrap();
+    admin
+        .query_drop(format!("CREATE TABLE {table} (id INT PRIMARY KEY)"))
+        .await
+        .unwrap();
+    admin
+        .query_drop(format!("INSERT INTO {table} VALUES (1)"))
+        .await
+        .unwrap();
+
+    let conn = db.connect_worker(0, Role::Write).await.unwrap();
+    let mut session = db.session(conn).await;
+    let mut lease = session.get().await;
+    lease.query_drop("BEGIN").await.unwrap();
+    lease
+        .query_drop(format!("INSERT INTO {table} VALUES (2)"))
+        .await
+        .unwrap();
+    let failed_ts = current_ts(&mut lease).await;
+    assert_ne!(failed_ts, 0);
+    let duplicate = lease
+        .query_drop(format!("INSERT INTO {table} VALUES (1)"))
+        .await;
+    assert!(duplicate.is_err());
+    lease.fail();
+    drop(lease);
+    session.recover(&db, 0, Role::Write).await.unwrap();
+
+    let mut lease = session.get().await;
+    assert_eq!(
+        current_ts(&mut lease).await,
+        0,
+        "the failed transaction is still open"
+    );
+    lease.query_drop("BEGIN").await.unwrap();
+    lease
+        .query_drop(format!("SELECT COUNT(*) FROM {table}"))
+        .await
+        .unwrap();
+    let ts = current_ts(&mut lease).await;
+    assert!(
+        ts > failed_ts,
+        "{ts} is not a new transaction after {failed_ts}"
+    );
+    lease.query_drop("ROLLBACK").await.unwrap();
+    drop(lease);
+
+    let ids: Vec<i32> = admin
+        .query(format!("SELECT id FROM {table} ORDER BY id"))
+        .await
+        .unwrap();
+    assert_eq!(ids, [1]);
+    admin
+        .query_drop(format!("DROP TABLE {table}"))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn failed_transaction_does_not_leak_into_the_next_iteration() {
+    failed_transaction_is_rolled_back("it_failed_tx", &[]).await;
+}
+
+#[tokio::test]
+async fn failed_pooled_transaction_does_not_leak_into_the_next_iteration() {
+    failed_transaction_is_rolled_back("it_failed_pooled_tx", &["--connections", "1"]).await;
+}
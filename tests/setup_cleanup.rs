@@ -0,0 +1,99 @@
+//! A setup that fails midway drops what it created and reports its own error.
+
+mod common;
+
+use anyhow::Result;
+use mysql_async::prelude::*;
+use mysql_async::Conn;
+use tidb_bench::{quote_ident, DbOpts};
+
+const SCHEMA: &str = "id BIGINT PRIMARY KEY";
+
+/// MySQL's `ER_DUP_ENTRY`.
+const DUPLICATE_KEY: u16 = 1062;
+
+/// Create `table` and an auxiliary table, then fail seeding the first with a duplicate key.
+async fn failing_setup(db: &DbOpts, conn: &mut Conn, table: &str) -> Result<()> {
+    db.create_table(conn, table, SCHEMA).await?;
+    db.create_table(conn, &db.aux_table("inner"), SCHEMA)
+        .await?;
+    conn.query_drop(format!(
+        "INSERT INTO {} VALUES (1), (1)",
+        quote_ident(table)
+    ))
+    .await?;
+    Ok(())
+}
+
+fn is_duplicate_key(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<mysql_async::Error>(),
+        Some(mysql_async::Error::Server(e)) if e.code == DUPLICATE_KEY
+    )
+}
+
+#[tokio::test]
+async fn failed_setup_leaves_nothing_and_keeps_its_error() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let table = "it_setup_failed";
+    let db = common::db_opts(&server, &["--table", table]);
+    let mut conn = db.connect().await.unwrap();
+
+    let e = db
+        .undo_on_error(failing_setup(&db, &mut conn, table))
+        .await
+        .unwrap_err();
+    assert!(is_duplicate_key(&e), "{e:#}");
+    assert!(!common::table_exists(&mut conn, table).await);
+    assert!(!common::table_exists(&mut conn, &db.aux_table("inner")).await);
+}
+
+#[tokio::test]
+async fn failed_setup_keeps_its_tables_with_keep_table() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let table = "it_setup_failed_kept";
+    let db = common::db_opts(&server, &["--table", table, "--keep-table"]);
+    let mut conn = db.connect().await.unwrap();
+
+    let e = db
+        .undo_on_error(failing_setup(&db, &mut conn, table))
+        .await
+        .unwrap_err();
+    assert!(is_duplicate_key(&e), "{e:#}");
+    for table in [table.to_owned(), db.aux_table("inner")] {
+        assert!(common::table_exists(&mut conn, &table).await);
+        conn.query_drop(format!("DROP TABLE {}", quote_ident(&table)))
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn tables_of_an_earlier_step_are_left_alone() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let table = "it_setup_untouched";
+    let db = common::db_opts(&server, &["--table", table]);
+    let mut conn = db.connect().await.unwrap();
+    let quoted = quote_ident(table);
+    conn.query_drop(format!("CREATE TABLE IF NOT EXISTS {quoted} ({SCHEMA})"))
+        .await
+        .unwrap();
+
+    // Fails before creating anything: the existing table is not this setup's to drop.
+    let e = db
+        .undo_on_error(async { anyhow::bail!("injected failure") })
+        .await
+        .unwrap_err();
+    assert_eq!(e.to_string(), "injected failure");
+    assert!(common::table_exists(&mut conn, table).await);
+
+    conn.query_drop(format!("DROP TABLE {quoted}"))
+        .await
+        .unwrap();
+}
@@ -0,0 +1,42 @@
+//! `--setup-only` seeds every row of a table whose size is not a multiple of the batches.
+
+mod common;
+
+use std::time::Duration;
+
+use mysql_async::prelude::*;
+
+#[tokio::test]
+async fn setup_only_seeds_a_partial_last_batch() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let table = "it_setup_only";
+    // Seeding inserts 5000 rows per statement, split over the seed connections.
+    let mut child = common::spawn_bench(
+        env!("CARGO_BIN_EXE_bench-select"),
+        &server,
+        &[
+            "--table",
+            table,
+            "--table-size",
+            "12347",
+            "--seed-concurrency",
+            "3",
+            "--force-reseed",
+            "--setup-only",
+        ],
+    );
+    assert!(common::wait_for(&mut child, Duration::from_secs(300)).success());
+
+    let db = common::db_opts(&server, &["--table", table]);
+    let mut conn = db.connect().await.unwrap();
+    let stats: Option<(u64, i64, i64)> = conn
+        .query_first(format!("SELECT COUNT(*), MIN(id), MAX(id) FROM {table}"))
+        .await
+        .unwrap();
+    assert_eq!(stats, Some((12347, 1, 12347)));
+    conn.query_drop(format!("DROP TABLE {table}"))
+        .await
+        .unwrap();
+}
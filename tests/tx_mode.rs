@@ -0,0 +1,46 @@
+//! `--tx-mode` holds on every worker session, pooled connections included.
+
+mod common;
+
+use mysql_async::prelude::*;
+use tidb_bench::Role;
+
+/// `@@tidb_txn_mode` and `@@autocommit` of a worker session opened with `--tx-mode mode`
+/// and `extra` arguments.
+async fn session_vars(server: &[String], mode: &str, extra: &[&str]) -> (String, u8) {
+    let mut args = vec!["--tx-mode", mode];
+    args.extend(extra);
+    let db = common::db_opts(server, &args);
+    let conn = db.connect_worker(0, Role::Write).await.unwrap();
+    let mut session = db.session(conn).await;
+    let mut lease = session.get().await;
+    let vars: Option<(String, u8)> = lease
+        .query_first("SELECT @@SESSION.tidb_txn_mode, @@SESSION.autocommit")
+        .await
+        .unwrap();
+    vars.unwrap()
+}
+
+async fn tx_modes_hold(extra: &[&str]) {
+    let Some(server) = common::server() else {
+        return;
+    };
+    for mode in ["optimistic", "pessimistic"] {
+        let (txn_mode, autocommit) = session_vars(&server, mode, extra).await;
+        assert_eq!(txn_mode, mode);
+        assert_eq!(autocommit, 1, "--tx-mode {mode}");
+    }
+    // Autocommit leaves the server's transaction mode alone and only relies on autocommit.
+    let (_, autocommit) = session_vars(&server, "auto-commit", extra).await;
+    assert_eq!(autocommit, 1);
+}
+
+#[tokio::test]
+async fn worker_connections_hold_the_tx_mode() {
+    tx_modes_hold(&[]).await;
+}
+
+#[tokio::test]
+async fn pooled_connections_hold_the_tx_mode() {
+    tx_modes_hold(&["--connections", "2"]).await;
+}
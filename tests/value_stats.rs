@@ -0,0 +1,95 @@
+//! The server's statistics of seeded `value` columns agree with the thresholds
+//! `--selectivity` derives from the value distribution.
+
+mod common;
+
+use clap::Parser;
+use mysql_async::prelude::*;
+use mysql_async::Row;
+use tidb_bench::distributions::ValueOpts;
+
+const TABLE: &str = "it_value_stats";
+const ROWS: u64 = 100_000;
+
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    values: ValueOpts,
+}
+
+/// Share of the rows below `threshold` as estimated from the column's histogram: the
+/// cumulative count of the last bucket that ends below it, over the total.
+fn estimated_share(buckets: &[(i64, u64)], threshold: u32) -> f64 {
+    let total = buckets.last().map_or(0, |&(_, count)| count);
+    let below = buckets
+        .iter()
+        .take_while(|&&(upper, _)| upper < threshold as i64)
+        .last()
+        .map_or(0, |&(_, count)| count);
+    below as f64 / total.max(1) as f64
+}
+
+#[tokio::test]
+async fn histograms_match_the_thresholds() {
+    let Some(server) = common::server() else {
+        return;
+    };
+    let db = common::db_opts(&server, &[]);
+    let mut conn = db.connect().await.unwrap();
+    for shape in ["uniform", "zipfian", "normal"] {
+        let mut child = common::spawn_bench(
+            env!("CARGO_BIN_EXE_bench-select"),
+            &server,
+            &[
+                "--table",
+                TABLE,
+                "--table-size",
+                &ROWS.to_string(),
+                "--value-distribution",
+                shape,
+                "--force-reseed",
+                "--setup-only",
+            ],
+        );
+        assert!(common::wait_for(&mut child, std::time::Duration::from_secs(300)).success());
+
+        // Without TopN every value lands in a bucket, so the buckets cover all rows.
+        conn.query_drop(format!("ANALYZE TABLE {TABLE} WITH 0 TOPN, 64 BUCKETS"))
+            .await
+            .unwrap();
+        let rows: Vec<Row> = conn
+            .query(format!(
+                "SHOW STATS_BUCKETS WHERE Table_name = '{TABLE}' AND Column_name = 'value' \
+                 AND Is_index = 0"
+            ))
+            .await
+            .unwrap();
+        let buckets: Vec<(i64, u64)> = rows
+            .into_iter()
+            .map(|row| {
+                let upper: String = row.get("Upper_Bound").unwrap();
+                (upper.parse().unwrap(), row.get("Count").unwrap())
+            })
+            .collect();
+        assert!(
+            !buckets.is_empty(),
+            "{shape}: no histogram for {TABLE}.value"
+        );
+
+        let cli = Cli::parse_from(["test", "--value-distribution", shape]);
+        let values = cli.values.values();
+        for selectivity in [0.25, 0.5, 0.9] {
+            let share = estimated_share(&buckets, values.threshold(selectivity));
+            // The threshold falls inside a bucket, which holds about 1/64 of the rows or one
+            // whole value, up to an eighth of them for the first zipfian one.
+            assert!(
+                (share - selectivity).abs() < 0.1,
+                "{shape}: the histogram puts {share} of the rows below the threshold of \
+                 selectivity {selectivity}"
+            );
+        }
+    }
+    conn.query_drop(format!("DROP TABLE {TABLE}"))
+        .await
+        .unwrap();
+}